@@ -0,0 +1,103 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    Error, HttpResponse,
+};
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
+use serde::Deserialize;
+use std::{
+    convert::TryFrom,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use crate::{ratelimit::ClientIps, JsonResponse};
+
+/// A CIDR range such as `10.0.0.0/8`, or a single address.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IpRange(IpNet);
+
+impl IpRange {
+    pub const LOOPBACK_V4: Self = IpRange(IpNet::V4(Ipv4Net::new_assert(Ipv4Addr::LOCALHOST, 8)));
+    pub const LOOPBACK_V6: Self = IpRange(IpNet::V6(Ipv6Net::new_assert(Ipv6Addr::LOCALHOST, 128)));
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.0.contains(ip)
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<IpNet>()
+            .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+            .map(IpRange)
+            .map_err(|_| format!("invalid IP range {}", s))
+    }
+}
+
+impl TryFrom<String> for IpRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Source IPs allowed to access the service.
+#[derive(Clone, Debug, Default)]
+pub struct AccessList {
+    /// Only these ranges may access the service, any if empty
+    pub allow: Vec<IpRange>,
+    /// These ranges may not access the service, even if allowed
+    pub deny: Vec<IpRange>,
+    /// Where client IPs are taken from
+    pub client_ips: ClientIps,
+}
+
+impl AccessList {
+    pub fn enabled(&self) -> bool {
+        !self.allow.is_empty() || !self.deny.is_empty()
+    }
+
+    /// Whether `ip` may access the service, unknown addresses are only allowed without an
+    /// allowlist.
+    fn allows(&self, ip: Option<IpAddr>) -> bool {
+        match ip {
+            Some(ip) => {
+                (self.allow.is_empty() || self.allow.iter().any(|range| range.contains(&ip)))
+                    && !self.deny.iter().any(|range| range.contains(&ip))
+            }
+            None => self.allow.is_empty(),
+        }
+    }
+}
+
+/// Middleware answering requests from IPs that aren't allowed with `403 Forbidden`.
+pub fn restrict_ips<S>(
+    access: &AccessList,
+    req: ServiceRequest,
+    srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let allowed = !access.enabled() || access.allows(access.client_ips.of(&req));
+
+    let response = if allowed {
+        Ok(srv.call(req))
+    } else {
+        Err(req.into_response(
+            HttpResponse::Forbidden().json(JsonResponse::error("Access denied".to_string())),
+        ))
+    };
+
+    async move {
+        match response {
+            Ok(response) => response.await,
+            Err(response) => Ok(response),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use actix_web::{
+    dev::{Payload, Service, ServiceRequest, ServiceResponse},
+    error::InternalError,
+    http::header,
+    web, Error, FromRequest, HttpRequest, HttpResponse,
+};
+use futures::future::{ready, Ready};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    future::Future,
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use crate::{JsonResponse, State};
+
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Compare `a` and `b` in constant time, so keys can't be guessed byte by byte from timings.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Check the `X-Api-Key` header of `req` against the configured API keys. Without configured
+/// keys, this passes only if `public` is set.
+fn check_api_key(req: &HttpRequest, public: bool) -> Result<(), Error> {
+    let keys = req
+        .app_data::<web::Data<State>>()
+        .map(|state| state.cfg.api_keys.as_slice())
+        .unwrap_or_default();
+    if keys.is_empty() {
+        if public {
+            return Ok(());
+        }
+        let response = HttpResponse::NotFound().json(JsonResponse::error(
+            "No API keys are configured".to_string(),
+        ));
+        return Err(InternalError::from_response("no API keys", response).into());
+    }
+
+    let key = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .map(|key| key.as_bytes())
+        .unwrap_or_default();
+    if keys
+        .iter()
+        .any(|valid| constant_time_eq(valid.as_bytes(), key))
+    {
+        Ok(())
+    } else {
+        let response = HttpResponse::Unauthorized().json(JsonResponse::error(
+            "Missing or invalid API key".to_string(),
+        ));
+        Err(InternalError::from_response("invalid API key", response).into())
+    }
+}
+
+/// Proof that a request may access protected endpoints, extracted from the `X-Api-Key` header.
+/// Without configured API keys, protected endpoints are public.
+#[derive(Clone, Copy)]
+pub struct ApiKey;
+
+impl FromRequest for ApiKey {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(check_api_key(req, true).map(|()| ApiKey))
+    }
+}
+
+/// Proof that a request may access admin endpoints, extracted from the `X-Api-Key` header like
+/// `ApiKey`. Without configured API keys, admin endpoints are not found.
+#[derive(Clone, Copy)]
+pub struct AdminKey;
+
+impl FromRequest for AdminKey {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(check_api_key(req, false).map(|()| AdminKey))
+    }
+}
+
+/// Credentials for HTTP Basic authentication of the whole service.
+#[derive(Clone, Debug, Default)]
+pub struct BasicAuthOptions {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// htpasswd file with one `user:hash` per line, as created by `htpasswd -B`
+    pub htpasswd: Option<PathBuf>,
+}
+
+/// Password of a user as configured or read from the htpasswd file.
+enum Password {
+    Plain(String),
+    Bcrypt(String),
+}
+
+impl Password {
+    fn verify(&self, password: &str) -> bool {
+        match self {
+            Password::Plain(valid) => constant_time_eq(valid.as_bytes(), password.as_bytes()),
+            Password::Bcrypt(hash) => bcrypt::verify(password, hash).unwrap_or(false),
+        }
+    }
+}
+
+/// Users allowed to access the service with HTTP Basic authentication.
+pub struct BasicAuth {
+    users: HashMap<String, Password>,
+    /// `Authorization` headers that were verified before, so bcrypt hashes, which take a while
+    /// to verify on purpose, are only checked on the first request of a user
+    verified: Mutex<HashSet<String>>,
+}
+
+impl BasicAuth {
+    /// Collect the users of `options`, fails if the htpasswd file can't be read or uses an
+    /// unsupported hash.
+    pub fn new(options: &BasicAuthOptions) -> Result<Self, String> {
+        let mut users = HashMap::new();
+        if let Some(path) = &options.htpasswd {
+            let content = fs::read_to_string(path)
+                .map_err(|e| format!("can't read htpasswd {}: {}", path.display(), e))?;
+            for line in content.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (user, hash) = line
+                    .split_once(':')
+                    .ok_or_else(|| format!("invalid line in htpasswd {}", path.display()))?;
+                let password = if hash.starts_with("$2") {
+                    Password::Bcrypt(hash.to_string())
+                } else if hash.starts_with('$') || hash.starts_with("{SHA}") {
+                    return Err(format!(
+                        "unsupported hash for {} in htpasswd {}, create it with `htpasswd -B`",
+                        user,
+                        path.display()
+                    ));
+                } else {
+                    Password::Plain(hash.to_string())
+                };
+                users.insert(user.to_string(), password);
+            }
+        }
+        match (&options.username, &options.password) {
+            (Some(username), Some(password)) => {
+                users.insert(username.clone(), Password::Plain(password.clone()));
+            }
+            (None, None) => {}
+            _ => return Err("basic auth needs both a username and a password".to_string()),
+        }
+
+        Ok(BasicAuth {
+            users,
+            verified: Mutex::new(HashSet::new()),
+        })
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+
+    /// Whether the `Authorization` header `authorization` holds valid credentials.
+    fn verify(&self, authorization: &str) -> bool {
+        let mut verified = self
+            .verified
+            .lock()
+            .expect("can't lock verified credentials");
+        if verified.contains(authorization) {
+            return true;
+        }
+
+        let credentials = authorization
+            .strip_prefix("Basic ")
+            .and_then(|encoded| base64::decode(encoded.trim()).ok())
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+        let valid = credentials
+            .as_deref()
+            .and_then(|credentials| credentials.split_once(':'))
+            .and_then(|(user, password)| Some(self.users.get(user)?.verify(password)))
+            .unwrap_or(false);
+        if valid {
+            if verified.len() >= 1024 {
+                verified.clear();
+            }
+            verified.insert(authorization.to_string());
+        }
+        valid
+    }
+}
+
+/// Middleware answering requests without valid Basic auth credentials with
+/// `401 Unauthorized` if any users are configured.
+pub fn require_basic_auth<S>(
+    auth: &BasicAuth,
+    req: ServiceRequest,
+    srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let authorized = !auth.enabled()
+        || req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| auth.verify(value));
+
+    let response = if authorized {
+        Ok(srv.call(req))
+    } else {
+        Err(req.into_response(
+            HttpResponse::Unauthorized()
+                .header(header::WWW_AUTHENTICATE, "Basic realm=\"ts3status\"")
+                .json(JsonResponse::error("Authentication required".to_string())),
+        ))
+    };
+
+    async move {
+        match response {
+            Ok(response) => response.await,
+            Err(response) => Ok(response),
+        }
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, trace};
+use serde::{
+    de::{self, value::MapDeserializer},
+    Deserialize, Deserializer,
+};
+use ts3_query::*;
+
+use crate::{
+    compat::ServerVersion,
+    icons::{self, Transfer},
+    query::Connection,
+};
+
+/// Response of `serverinfo`. Its fields differ between server versions, missing or malformed ones
+/// are left at their defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RawServerInfo {
+    #[serde(rename = "virtualserver_name")]
+    pub name: String,
+    #[serde(rename = "virtualserver_version")]
+    pub version: String,
+    #[serde(rename = "virtualserver_platform")]
+    pub platform: String,
+    #[serde(rename = "virtualserver_clientsonline", deserialize_with = "lenient")]
+    pub clients_online: u32,
+    #[serde(rename = "virtualserver_maxclients", deserialize_with = "lenient")]
+    pub max_clients: u32,
+    #[serde(rename = "virtualserver_reserved_slots", deserialize_with = "lenient")]
+    pub reserved_slots: u32,
+    #[serde(
+        rename = "virtualserver_queryclientsonline",
+        deserialize_with = "lenient"
+    )]
+    pub queryclients_online: u32,
+    /// Seconds since the virtual server started
+    #[serde(rename = "virtualserver_uptime", deserialize_with = "lenient")]
+    pub uptime: u64,
+    /// Average ping of all clients in milliseconds
+    #[serde(rename = "virtualserver_total_ping", deserialize_with = "lenient")]
+    pub total_ping: f64,
+    /// Average packet loss of all clients, between 0 and 1
+    #[serde(
+        rename = "virtualserver_total_packetloss_total",
+        deserialize_with = "lenient"
+    )]
+    pub total_packet_loss: f64,
+    #[serde(
+        rename = "connection_bandwidth_sent_last_second_total",
+        deserialize_with = "lenient"
+    )]
+    pub bandwidth_sent: u64,
+    #[serde(
+        rename = "connection_bandwidth_received_last_second_total",
+        deserialize_with = "lenient"
+    )]
+    pub bandwidth_received: u64,
+    /// CRC32 of the icon as signed 32 bit integer, 0 without icon
+    #[serde(rename = "virtualserver_icon_id", deserialize_with = "lenient")]
+    pub icon_id: i64,
+    #[serde(rename = "virtualserver_hostmessage")]
+    pub host_message: String,
+    #[serde(rename = "virtualserver_welcomemessage")]
+    pub welcome_message: String,
+}
+
+impl RawServerInfo {
+    /// Parse the `serverinfo` response `fields`, as returned by `raw::parse_hashmap`.
+    pub fn parse(fields: &HashMap<String, Option<String>>) -> Self {
+        let fields = fields
+            .iter()
+            .filter_map(|(key, value)| Some((key.as_str(), value.as_deref()?)));
+        // Fields are only ever read as text or parsed leniently, so this doesn't fail
+        Self::deserialize(MapDeserializer::<_, de::value::Error>::new(fields)).unwrap_or_default()
+    }
+}
+
+/// Deserialize a field of a query response by parsing its text, its default if it's malformed.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Default,
+{
+    Ok(String::deserialize(deserializer)?
+        .parse()
+        .unwrap_or_default())
+}
+
+/// Everything queried from a virtual server that its status is built from.
+pub struct RawStatus {
+    pub server_info: RawServerInfo,
+    pub channels: Vec<ChannelFull>,
+    pub clients: Vec<OnlineClientFull>,
+    /// Unix timestamps of when clients connected by client id
+    pub connected_since: HashMap<u16, u64>,
+    /// Channel group names by id
+    pub channel_groups: HashMap<u64, String>,
+    /// Server group names by id
+    pub server_groups: HashMap<u64, String>,
+    /// Channel descriptions by channel id, empty unless `channel_descriptions` is enabled
+    pub descriptions: HashMap<u64, String>,
+}
+
+/// Source of the status of virtual servers, the query connection to a TS3 host or an in-memory
+/// mock. Its methods block and must not be called from the async runtime.
+pub trait StatusBackend: Send {
+    /// Query the current status of the virtual server `server_id`, with the description of every
+    /// channel if `descriptions` is set.
+    fn fetch(&mut self, server_id: u64, descriptions: bool) -> Result<RawStatus, Ts3Error>;
+
+    /// Announce the download of the icon `icon_id` of the virtual server `server_id`, `None` if it
+    /// doesn't exist.
+    fn init_download(&mut self, server_id: u64, icon_id: u64)
+        -> Result<Option<Transfer>, Ts3Error>;
+
+    /// Keep an idle connection from being closed.
+    fn keepalive(&mut self) -> Result<(), Ts3Error>;
+
+    /// Log out, so no session is left behind.
+    fn disconnect(&mut self) -> Result<(), Ts3Error>;
+}
+
+impl StatusBackend for Connection {
+    fn fetch(&mut self, server_id: u64, descriptions: bool) -> Result<RawStatus, Ts3Error> {
+        let nickname = self.nickname.clone();
+        let mut known = self.connected_since.remove(&server_id).unwrap_or_default();
+        let result = self.with_client(|client, version, named| {
+            select_server(client, server_id, &nickname, named)?;
+            query_status(client, version, descriptions, &mut known)
+        });
+        self.connected_since.insert(server_id, known);
+        result
+    }
+
+    fn init_download(
+        &mut self,
+        server_id: u64,
+        icon_id: u64,
+    ) -> Result<Option<Transfer>, Ts3Error> {
+        let nickname = self.nickname.clone();
+        self.with_client(|client, _, named| {
+            select_server(client, server_id, &nickname, named)?;
+            icons::init_download(client, icon_id)
+        })
+    }
+
+    fn keepalive(&mut self) -> Result<(), Ts3Error> {
+        Connection::keepalive(self)
+    }
+
+    fn disconnect(&mut self) -> Result<(), Ts3Error> {
+        Connection::disconnect(self)
+    }
+}
+
+/// Runs query commands and parses every item of their response, over a query connection or
+/// WebQuery. Commands are raw query commands with escaped arguments.
+pub trait Commands {
+    fn run(&mut self, command: &str) -> Result<Vec<HashMap<String, Option<String>>>, Ts3Error>;
+}
+
+impl Commands for QueryClient {
+    fn run(&mut self, command: &str) -> Result<Vec<HashMap<String, Option<String>>>, Ts3Error> {
+        self.raw_command(command)
+            .map(|res| raw::parse_multi_hashmap(res, true))
+    }
+}
+
+/// Select the virtual server `server_id` and name the session `nickname` there, so admins can
+/// tell it apart from other query sessions. The nickname is kept, so it's only set on virtual
+/// servers missing from `named`, which it is added to. An empty `nickname` keeps the default one.
+fn select_server(
+    client: &mut QueryClient,
+    server_id: u64,
+    nickname: &str,
+    named: &mut HashSet<u64>,
+) -> Result<(), Ts3Error> {
+    client.select_server_by_id(server_id)?;
+    if nickname.is_empty() || !named.insert(server_id) {
+        return Ok(());
+    }
+    let command = format!("clientupdate client_nickname={}", raw::escape_arg(nickname));
+    match client.raw_command(command) {
+        // The nickname is cosmetic, if it's taken the session keeps the default one
+        Err(e) if e.is_error_response() => {
+            debug!("Can't set nickname {}: {:?}", nickname, e);
+            Ok(())
+        }
+        result => result.map(drop),
+    }
+}
+
+/// Query the names of all channel groups by their id.
+pub(crate) fn channel_groups(client: &mut impl Commands) -> Result<HashMap<u64, String>, Ts3Error> {
+    let groups = client.run("channelgrouplist")?;
+    Ok(groups
+        .into_iter()
+        .filter_map(|mut group| {
+            let id = group.get("cgid")?.as_ref()?.parse().ok()?;
+            Some((id, group.remove("name")??))
+        })
+        .collect())
+}
+
+/// When clients connected as unix timestamp, by client id and database id. Client ids are reused
+/// once clients leave, the database id tells a new client apart.
+pub type ConnectedSince = HashMap<(u16, u64), u64>;
+
+/// Query when each of `clients` connected as unix timestamp. Only clients missing from `known`
+/// are queried, one `clientinfo` each, so the number of commands doesn't grow with the number of
+/// clients on every refresh. `known` is updated to the clients in `clients`, those that left in
+/// the meantime are skipped.
+pub(crate) fn connected_since(
+    client: &mut impl Commands,
+    clients: &[OnlineClientFull],
+    known: &mut ConnectedSince,
+) -> Result<HashMap<u16, u64>, Ts3Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let mut current = ConnectedSince::new();
+    for online_client in clients {
+        let key = (online_client.clid, online_client.client_database_id);
+        if let Some(&since) = known.get(&key) {
+            current.insert(key, since);
+            continue;
+        }
+        let info = match client.run(&format!("clientinfo clid={}", online_client.clid)) {
+            Ok(info) => info.into_iter().next().unwrap_or_default(),
+            Err(e) if e.is_error_response() => continue,
+            Err(e) => return Err(e),
+        };
+        let connected_ms: Option<u64> = info
+            .get("connection_connected_time")
+            .and_then(|time| time.as_ref())
+            .and_then(|time| time.parse().ok());
+        if let Some(connected_ms) = connected_ms {
+            current.insert(key, now.saturating_sub(connected_ms / 1000));
+        }
+    }
+    *known = current;
+    Ok(known
+        .iter()
+        .map(|(&(clid, _), &since)| (clid, since))
+        .collect())
+}
+
+/// Query the description of every channel in `channels`, leaving out empty ones.
+pub(crate) fn channel_descriptions(
+    client: &mut impl Commands,
+    channels: &[ChannelFull],
+) -> Result<HashMap<u64, String>, Ts3Error> {
+    let mut descriptions = HashMap::new();
+    for channel in channels {
+        let info = match client.run(&format!("channelinfo cid={}", channel.cid)) {
+            Ok(info) => info.into_iter().next().unwrap_or_default(),
+            Err(e) if e.is_error_response() => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(Some(description)) = info.get("channel_description") {
+            if !description.is_empty() {
+                descriptions.insert(channel.cid, description.clone());
+            }
+        }
+    }
+    Ok(descriptions)
+}
+
+/// Query everything the status of the selected virtual server is built from, from a server
+/// running `version`. `known` holds the connection times of clients of the last query.
+fn query_status(
+    client: &mut QueryClient,
+    version: ServerVersion,
+    descriptions: bool,
+    known: &mut ConnectedSince,
+) -> Result<RawStatus, Ts3Error> {
+    let server_info = client
+        .raw_command("serverinfo")
+        .map(|res| RawServerInfo::parse(&raw::parse_hashmap(res, true)))?;
+    trace!("info: {:?}", server_info);
+
+    let channels = version.channels(client)?;
+    trace!("channels: {:?}", channels);
+
+    let clients = version.clients(client)?;
+    trace!("clients: {:?}", clients);
+
+    Ok(RawStatus {
+        connected_since: connected_since(client, &clients, known)?,
+        channel_groups: channel_groups(client)?,
+        server_groups: version.server_groups(client)?,
+        descriptions: if descriptions {
+            channel_descriptions(client, &channels)?
+        } else {
+            HashMap::new()
+        },
+        server_info,
+        channels,
+        clients,
+    })
+}
@@ -0,0 +1,199 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use serde::Deserialize;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+use crate::query::{ChannelNode, ServerInfo};
+
+// Upper bound for requested banner dimensions
+const MAX_SIZE: u32 = 2000;
+const GLYPH_SIZE: u32 = 8;
+const PADDING: u32 = 6;
+
+type Color = [u8; 4];
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    Dark,
+    Light,
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            _ => Err(format!("unknown theme {}, expected dark or light", s)),
+        }
+    }
+}
+
+impl Theme {
+    /// Background, text and accent color
+    fn colors(self) -> (Color, Color, Color) {
+        match self {
+            Theme::Dark => ([32, 34, 37, 255], [220, 221, 222, 255], [88, 166, 255, 255]),
+            Theme::Light => ([246, 246, 246, 255], [40, 40, 40, 255], [0, 102, 204, 255]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BannerOptions {
+    pub width: u32,
+    pub height: u32,
+    pub theme: Theme,
+}
+
+impl BannerOptions {
+    /// Clamp the dimensions to sane bounds.
+    pub fn clamped(self) -> Self {
+        Self {
+            width: self.width.clamp(GLYPH_SIZE * 4, MAX_SIZE),
+            height: self.height.clamp(GLYPH_SIZE * 2, MAX_SIZE),
+            theme: self.theme,
+        }
+    }
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Color) -> Self {
+        Self {
+            width,
+            height,
+            pixels: background
+                .iter()
+                .copied()
+                .cycle()
+                .take((width * height * 4) as usize)
+                .collect(),
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, color: Color) {
+        if x < self.width && y < self.height {
+            let offset = ((y * self.width + x) * 4) as usize;
+            self.pixels[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+
+    /// Draw `text` with its top left corner at `x`/`y`, every glyph pixel is scaled to
+    /// `scale`x`scale` pixels. Text that doesn't fit is cut off.
+    fn text(&mut self, x: u32, y: u32, text: &str, scale: u32, color: Color) {
+        for (i, c) in text.chars().enumerate() {
+            let glyph = BASIC_FONTS
+                .get(c)
+                .or_else(|| LATIN_FONTS.get(c))
+                .or_else(|| BASIC_FONTS.get('?'))
+                .unwrap_or_default();
+            let glyph_x = x + i as u32 * GLYPH_SIZE * scale;
+            if glyph_x >= self.width {
+                break;
+            }
+
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..GLYPH_SIZE {
+                    if bits & (1 << column) == 0 {
+                        continue;
+                    }
+                    for dy in 0..scale {
+                        for dx in 0..scale {
+                            self.set(
+                                glyph_x + column * scale + dx,
+                                y + row as u32 * scale + dy,
+                                color,
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn encode(self) -> Result<Vec<u8>, png::EncodingError> {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, self.width, self.height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&self.pixels)?;
+        }
+        Ok(data)
+    }
+}
+
+fn collect_channels<'a>(channels: &'a [ChannelNode], out: &mut Vec<&'a ChannelNode>) {
+    for channel in channels {
+        out.push(channel);
+        collect_channels(&channel.children, out);
+    }
+}
+
+/// The occupied channels with the most clients first.
+fn top_channels(server_info: &ServerInfo) -> Vec<&ChannelNode> {
+    let mut channels = Vec::new();
+    collect_channels(&server_info.channels, &mut channels);
+    channels.retain(|channel| !channel.clients.is_empty());
+    channels.sort_by_key(|channel| std::cmp::Reverse(channel.clients.len()));
+    channels
+}
+
+/// Render a banner with the server name, the number of online clients and the busiest channels
+/// as PNG image. Without `server_info` the server is shown as offline.
+pub fn render(
+    server_info: Option<&ServerInfo>,
+    options: BannerOptions,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let options = options.clamped();
+    let (background, foreground, accent) = options.theme.colors();
+    let mut canvas = Canvas::new(options.width, options.height, background);
+
+    // Use a larger title on banners that have room for it
+    let title_scale = if options.height >= 4 * GLYPH_SIZE + 3 * PADDING {
+        2
+    } else {
+        1
+    };
+    let mut y = PADDING.min(options.height / 8);
+
+    let server_info = match server_info {
+        Some(server_info) => server_info,
+        None => {
+            canvas.text(PADDING, y, "TeamSpeak server offline", title_scale, accent);
+            return canvas.encode();
+        }
+    };
+
+    canvas.text(PADDING, y, &server_info.name, title_scale, accent);
+    y += GLYPH_SIZE * title_scale + PADDING / 2;
+
+    let mut lines = vec![format!(
+        "{}/{} online",
+        server_info.clients().len(),
+        server_info.max_clients
+    )];
+    lines.extend(
+        top_channels(server_info)
+            .into_iter()
+            .map(|channel| format!("{} ({})", channel.name, channel.clients.len())),
+    );
+
+    for line in lines {
+        if y + GLYPH_SIZE > options.height {
+            break;
+        }
+        canvas.text(PADDING, y, &line, 1, foreground);
+        y += GLYPH_SIZE + 2;
+    }
+
+    canvas.encode()
+}
@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+/// Stops querying a TS3 host after consecutive failures, so outages neither slow down requests
+/// nor trip the anti-flood protection of the host. After the cooldown, a single probe query is
+/// let through and closes the circuit again if it succeeds.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Consecutive failures that open the circuit, never opens if 0
+    threshold: u32,
+    cooldown: Duration,
+    failures: u32,
+    /// When the circuit was opened, `None` while it is closed
+    opened_at: Option<Instant>,
+    /// Whether the probe query of a half-open circuit is running
+    probing: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            failures: 0,
+            opened_at: None,
+            probing: false,
+        }
+    }
+
+    /// Whether queries are stopped, also while probing.
+    pub fn is_open(&self) -> bool {
+        self.opened_at.is_some()
+    }
+
+    /// Whether a query may be sent now, `Err` with the time until the next probe otherwise.
+    pub fn allow(&mut self) -> Result<(), Duration> {
+        let opened_at = match self.opened_at {
+            Some(opened_at) => opened_at,
+            None => return Ok(()),
+        };
+        let open_for = opened_at.elapsed();
+        if open_for < self.cooldown {
+            Err(self.cooldown - open_for)
+        } else if self.probing {
+            Err(Duration::from_secs(0))
+        } else {
+            self.probing = true;
+            Ok(())
+        }
+    }
+
+    /// Record the outcome of a query that was allowed.
+    pub fn record(&mut self, success: bool) {
+        if success {
+            self.failures = 0;
+            self.opened_at = None;
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            if self.probing || (self.threshold > 0 && self.failures >= self.threshold) {
+                self.opened_at = Some(Instant::now());
+            }
+        }
+        self.probing = false;
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{query, query::Server};
+
+/// Result of a check, the exit code of monitoring plugins such as those of Nagios and Icinga.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Ok = 0,
+    Warning = 1,
+    Critical = 2,
+    Unknown = 3,
+}
+
+impl Status {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Status::Ok => "OK",
+            Status::Warning => "WARNING",
+            Status::Critical => "CRITICAL",
+            Status::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Numbers of clients above which a check warns or fails.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Thresholds {
+    pub warning: Option<u32>,
+    pub critical: Option<u32>,
+}
+
+impl Thresholds {
+    fn status(self, clients: u32) -> Status {
+        if self.critical.is_some_and(|critical| clients > critical) {
+            Status::Critical
+        } else if self.warning.is_some_and(|warning| clients > warning) {
+            Status::Warning
+        } else {
+            Status::Ok
+        }
+    }
+}
+
+/// Plugin output line in the format `TS3 <STATUS> - <message>`.
+pub fn output(status: Status, message: &str) -> String {
+    format!("TS3 {} - {}", status.label(), message)
+}
+
+/// Query the first virtual server of `server` and check that it's reachable and its number of
+/// clients is within `thresholds`.
+pub async fn check(server: &Server, thresholds: Thresholds) -> (Status, String) {
+    let id = server.cfg.ts3_server_ids[0];
+    let server_info = match query::refresh_shared(server, id, &server.caches[&id]).await {
+        Ok(server_info) => server_info,
+        Err(e) => return (Status::Critical, output(Status::Critical, &e.message)),
+    };
+
+    let clients = server_info
+        .visible(server.client_options.include_query)
+        .clients()
+        .len() as u32;
+    let status = thresholds.status(clients);
+    let threshold = |value: Option<u32>| value.map(|value| value.to_string()).unwrap_or_default();
+    let message = format!(
+        "{} of {} clients online on {} | clients={};{};{};0;{}",
+        clients,
+        server_info.max_clients,
+        server_info.name,
+        clients,
+        threshold(thresholds.warning),
+        threshold(thresholds.critical),
+        server_info.max_clients
+    );
+    (status, output(status, &message))
+}
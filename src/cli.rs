@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+/// Status of TeamSpeak 3 servers over HTTP.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// TOML configuration file, environment variables take precedence over its settings
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Dotenv file with environment variables, ENV_FILE or `.env` if it exists by default.
+    /// Variables already set in the environment take precedence.
+    #[arg(long, global = true, value_name = "FILE")]
+    pub env_file: Option<PathBuf>,
+    /// Arguments of `serve`, which runs without a subcommand, also accepted before any other one
+    #[command(flatten)]
+    pub serve: ServeArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Serve the status over HTTP, the default
+    Serve(ServeArgs),
+    /// Query a server once and print its status
+    Once {
+        #[command(flatten)]
+        server: ServerArg,
+        #[arg(long, value_enum, default_value_t = OnceFormat::Json)]
+        format: OnceFormat,
+    },
+    /// Query a server once and report the result as Nagios/Icinga plugin
+    Check {
+        #[command(flatten)]
+        server: ServerArg,
+        /// Warn if more clients are online
+        #[arg(short, long, value_name = "CLIENTS")]
+        warning: Option<u32>,
+        /// Fail if more clients are online
+        #[arg(short, long, value_name = "CLIENTS")]
+        critical: Option<u32>,
+    },
+    /// Check the configuration, log in to every TS3 host and use its virtual servers
+    Validate(ServeArgs),
+    /// Check the configuration without connecting to any server
+    ValidateConfig(ServeArgs),
+}
+
+#[derive(Args)]
+pub struct ServeArgs {
+    /// Address to listen on, `unix:<path>` for unix sockets. May be passed repeatedly and takes
+    /// precedence over LISTEN_ADDR and the configuration file.
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ServerArg {
+    /// Name of the TS3 host, the default one if unset
+    #[arg(long, value_name = "NAME")]
+    pub server: Option<String>,
+}
+
+/// Output format of `once`.
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum OnceFormat {
+    Json,
+    /// Summary line and indented channel tree
+    Text,
+}
@@ -0,0 +1,194 @@
+use std::{collections::HashMap, str::FromStr};
+
+use log::info;
+use serde::Deserialize;
+use ts3_query::*;
+
+/// Major version of the server software of a TS3 host, whose query responses differ slightly.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerVersion {
+    /// Detected from the `version` command after connecting
+    Auto,
+    /// TeamSpeak 3 server, whose responses are parsed strictly
+    Ts3,
+    /// TeamSpeak 6 server, whose responses may lack or rename fields that are then left at their
+    /// defaults
+    Ts6,
+}
+
+impl FromStr for ServerVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ServerVersion::Auto),
+            "ts3" => Ok(ServerVersion::Ts3),
+            "ts6" => Ok(ServerVersion::Ts6),
+            _ => Err(format!(
+                "unknown server version {}, expected auto, ts3 or ts6",
+                s
+            )),
+        }
+    }
+}
+
+impl ServerVersion {
+    /// The configured version, or the one `client` reports for `Auto`.
+    pub fn resolve(self, client: &mut QueryClient, name: &str) -> Result<Self, Ts3Error> {
+        if self != ServerVersion::Auto {
+            return Ok(self);
+        }
+        let response = client
+            .raw_command("version")
+            .map(|res| raw::parse_hashmap(res, true))?;
+        let version = response
+            .get("version")
+            .and_then(|version| version.as_deref())
+            .unwrap_or_default();
+        // TS6 reports versions such as `v6.0.0-beta2`, TS3 plain ones such as `3.13.7`
+        let major: Option<u32> = version
+            .trim_start_matches('v')
+            .split(|c: char| !c.is_ascii_digit())
+            .next()
+            .and_then(|major| major.parse().ok());
+        if major.is_some_and(|major| major >= 6) {
+            info!("{} runs TeamSpeak {}, parsing it leniently", name, version);
+            Ok(ServerVersion::Ts6)
+        } else {
+            Ok(ServerVersion::Ts3)
+        }
+    }
+
+    /// Query all channels like `QueryClient::channels_full`.
+    pub fn channels(self, client: &mut QueryClient) -> Result<Vec<ChannelFull>, Ts3Error> {
+        if self != ServerVersion::Ts6 {
+            return client.channels_full();
+        }
+        let channels = client
+            .raw_command("channellist -topic -flags -voice -limits -icon -secondsempty")
+            .map(|res| raw::parse_multi_hashmap(res, true))?;
+        Ok(channels.iter().map(channel).collect())
+    }
+
+    /// Query all online clients like `QueryClient::online_clients_full`.
+    pub fn clients(self, client: &mut QueryClient) -> Result<Vec<OnlineClientFull>, Ts3Error> {
+        if self != ServerVersion::Ts6 {
+            return client.online_clients_full();
+        }
+        let clients = client
+            .raw_command("clientlist -uid -away -voice -times -groups -info -country -ip -badges")
+            .map(|res| raw::parse_multi_hashmap(res, true))?;
+        Ok(clients.iter().map(online_client).collect())
+    }
+
+    /// Query the names of all server groups by their id.
+    pub fn server_groups(self, client: &mut QueryClient) -> Result<HashMap<u64, String>, Ts3Error> {
+        if self != ServerVersion::Ts6 {
+            return Ok(client
+                .server_groups()?
+                .into_iter()
+                .map(|group| (group.sgid, group.name))
+                .collect());
+        }
+        let groups = client
+            .raw_command("servergrouplist")
+            .map(|res| raw::parse_multi_hashmap(res, true))?;
+        Ok(server_groups(&groups))
+    }
+}
+
+/// Names of the server groups of a `servergrouplist` response by their id.
+pub(crate) fn server_groups(groups: &[HashMap<String, Option<String>>]) -> HashMap<u64, String> {
+    groups
+        .iter()
+        .filter_map(|group| Some((field(group, "sgid"), group.get("name")?.clone()?)))
+        .collect()
+}
+
+/// The field `key` of `data`, its default if it's missing or malformed.
+fn field<T: FromStr + Default>(data: &HashMap<String, Option<String>>, key: &str) -> T {
+    data.get(key)
+        .and_then(|value| value.as_ref())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default()
+}
+
+fn flag(data: &HashMap<String, Option<String>>, key: &str) -> bool {
+    field::<u8>(data, key) == 1
+}
+
+fn text(data: &HashMap<String, Option<String>>, key: &str) -> Option<String> {
+    data.get(key).cloned().flatten()
+}
+
+/// Parse an item of a `channellist` response leniently, missing or malformed fields are left at
+/// their defaults.
+pub(crate) fn channel(data: &HashMap<String, Option<String>>) -> ChannelFull {
+    ChannelFull {
+        cid: field(data, "cid"),
+        pid: field(data, "pid"),
+        channel_order: field(data, "channel_order"),
+        channel_name: field(data, "channel_name"),
+        total_clients: field(data, "total_clients"),
+        channel_needed_subscribe_power: field(data, "channel_needed_subscribe_power"),
+        channel_topic: text(data, "channel_topic"),
+        channel_flag_default: flag(data, "channel_flag_default"),
+        channel_flag_password: flag(data, "channel_flag_password"),
+        channel_flag_permanent: flag(data, "channel_flag_permanent"),
+        channel_flag_semi_permanent: flag(data, "channel_flag_semi_permanent"),
+        channel_codec: field(data, "channel_codec"),
+        channel_codec_quality: field(data, "channel_codec_quality"),
+        channel_needed_talk_power: field(data, "channel_needed_talk_power"),
+        channel_icon_id: field(data, "channel_icon_id"),
+        seconds_empty: field(data, "seconds_empty"),
+        total_clients_family: field(data, "total_clients_family"),
+        // Missing limits mean unlimited rather than none
+        channel_maxclients: text(data, "channel_maxclients")
+            .and_then(|max| max.parse().ok())
+            .unwrap_or(-1),
+        channel_maxfamilyclients: text(data, "channel_maxfamilyclients")
+            .and_then(|max| max.parse().ok())
+            .unwrap_or(-1),
+    }
+}
+
+/// Parse an item of a `clientlist` response leniently, like `channel`.
+pub(crate) fn online_client(data: &HashMap<String, Option<String>>) -> OnlineClientFull {
+    OnlineClientFull {
+        clid: field(data, "clid"),
+        cid: field(data, "cid"),
+        client_database_id: field(data, "client_database_id"),
+        client_nickname: field(data, "client_nickname"),
+        client_type: field(data, "client_type"),
+        client_away: flag(data, "client_away"),
+        client_away_message: text(data, "client_away_message"),
+        client_flag_talking: flag(data, "client_flag_talking"),
+        client_input_muted: flag(data, "client_input_muted"),
+        client_output_muted: flag(data, "client_output_muted"),
+        client_input_hardware: flag(data, "client_input_hardware"),
+        client_output_hardware: flag(data, "client_output_hardware"),
+        client_talk_power: field(data, "client_talk_power"),
+        client_is_talker: flag(data, "client_is_talker"),
+        client_is_priority_speaker: flag(data, "client_is_priority_speaker"),
+        client_is_recording: flag(data, "client_is_recording"),
+        client_is_channel_commander: flag(data, "client_is_channel_commander"),
+        client_unique_identifier: field(data, "client_unique_identifier"),
+        client_servergroups: text(data, "client_servergroups")
+            .map(|groups| groups.split(',').filter_map(|id| id.parse().ok()).collect())
+            .unwrap_or_default(),
+        client_channel_group_id: field(data, "client_channel_group_id"),
+        client_channel_group_inherited_channel_id: field(
+            data,
+            "client_channel_group_inherited_channel_id",
+        ),
+        client_version: field(data, "client_version"),
+        client_platform: field(data, "client_platform"),
+        client_idle_time: field(data, "client_idle_time"),
+        client_created: field(data, "client_created"),
+        client_lastconnected: field(data, "client_lastconnected"),
+        client_country: field(data, "client_country"),
+        connection_client_ip: field(data, "connection_client_ip"),
+        client_badges: text(data, "client_badges"),
+    }
+}
@@ -0,0 +1,95 @@
+use actix_web::{
+    body::{Body, ResponseBody},
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{header, HeaderValue, Method, StatusCode},
+    Error, HttpResponse,
+};
+use std::{
+    collections::hash_map::DefaultHasher,
+    future::Future,
+    hash::{Hash, Hasher},
+    time::SystemTime,
+};
+
+/// Parse an HTTP date header such as `If-Modified-Since`.
+fn http_date(value: Option<&HeaderValue>) -> Option<SystemTime> {
+    let date: header::HttpDate = value?.to_str().ok()?.parse().ok()?;
+    Some(date.into())
+}
+
+/// Whether the `If-None-Match` header lists `etag`.
+fn matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+/// Entity tag of the response that represents `value`.
+pub fn entity_tag<T: Hash + ?Sized>(value: &T) -> String {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Middleware for conditional GET requests. Successful responses get an `ETag` computed from their
+/// body unless the handler set one, and `304 Not Modified` is returned instead if it matches
+/// `If-None-Match`, or if the `Last-Modified` header isn't newer than `If-Modified-Since`.
+pub fn conditional_get<S>(
+    req: ServiceRequest,
+    srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let is_get = req.method() == Method::GET || req.method() == Method::HEAD;
+    let if_none_match = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let if_modified_since = http_date(req.headers().get(header::IF_MODIFIED_SINCE));
+    let response = srv.call(req);
+
+    async move {
+        let mut response = response.await?;
+        if !is_get || response.status() != StatusCode::OK {
+            return Ok(response);
+        }
+        let etag = match response.headers().get(header::ETAG) {
+            Some(etag) => match etag.to_str() {
+                Ok(etag) => etag.to_string(),
+                Err(_) => return Ok(response),
+            },
+            None => match response.response().body() {
+                ResponseBody::Body(Body::Bytes(body)) => entity_tag(body),
+                _ => return Ok(response),
+            },
+        };
+
+        let not_modified = match (if_none_match, if_modified_since) {
+            (Some(if_none_match), _) => matches(&if_none_match, &etag),
+            (None, Some(if_modified_since)) => {
+                http_date(response.headers().get(header::LAST_MODIFIED))
+                    .is_some_and(|last_modified| last_modified <= if_modified_since)
+            }
+            (None, None) => false,
+        };
+
+        if not_modified {
+            let mut not_modified = HttpResponse::NotModified();
+            for name in &[header::LAST_MODIFIED, header::CACHE_CONTROL, header::AGE] {
+                if let Some(value) = response.headers().get(name) {
+                    not_modified.set_header(name.clone(), value.clone());
+                }
+            }
+            let not_modified = not_modified.set_header(header::ETAG, etag).finish();
+            return Ok(response.into_response(not_modified));
+        }
+
+        if let Ok(etag) = HeaderValue::from_str(&etag) {
+            response.headers_mut().insert(header::ETAG, etag);
+        }
+        Ok(response)
+    }
+}
@@ -0,0 +1,754 @@
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    access::IpRange,
+    auth::{BasicAuth, BasicAuthOptions},
+    banner::{BannerOptions, Theme},
+    compat::ServerVersion,
+    cors::CorsOptions,
+    discord::DiscordOptions,
+    history::HistoryOptions,
+    icons::ICON_CACHE_LIFETIME,
+    logging::{AccessLog, LogFormat},
+    mqtt::BrokerOptions,
+    privacy::PrivacyMode,
+    query::{QueryProtocol, CACHE_LIFETIME},
+    tls,
+    webhooks::WebhookOptions,
+};
+
+/// Name of the server configured through the top level `ts3_*` settings and environment.
+pub const DEFAULT_SERVER: &str = "default";
+
+/// Connection settings of a single TS3 host.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub name: String,
+    pub ts3_host: String,
+    /// Look up `ts3_host` by its SRV records and TSDNS like the TeamSpeak client
+    pub ts3_resolve: bool,
+    pub ts3_port: u16,
+    pub ts3_query_protocol: QueryProtocol,
+    /// Version of the server software, TS6 responses are parsed leniently
+    pub ts3_server_version: ServerVersion,
+    pub ts3_server_ids: Vec<u64>,
+    /// Query login, unused by WebQuery
+    pub user: String,
+    pub password: String,
+    /// API key of WebQuery
+    pub ts3_api_key: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub servers: BTreeMap<String, ServerConfig>,
+    /// Seconds to wait for the connection to a TS3 host
+    pub ts3_connect_timeout: u64,
+    /// Seconds to wait for the response to a TS3 query command
+    pub ts3_command_timeout: u64,
+    /// Nickname of the query sessions on the virtual servers, empty to keep the default one
+    pub query_nickname: String,
+    /// Retries of TS3 queries failing with network errors
+    pub ts3_retries: u32,
+    /// Milliseconds before the first retry, doubling with every further one
+    pub ts3_retry_backoff_ms: u64,
+    /// Consecutive failed refreshes after which a TS3 host isn't queried for a while, 0 to never
+    /// stop querying
+    pub circuit_breaker_threshold: u32,
+    /// Seconds a TS3 host isn't queried after too many failures
+    pub circuit_breaker_cooldown: u64,
+    /// Addresses to listen on, `unix:<path>` for unix sockets. May be empty with sockets passed
+    /// by systemd.
+    pub listen: Vec<String>,
+    /// PEM certificate chain and private key to serve HTTPS with, plain HTTP if unset
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub hostname: String,
+    /// Prefix of every route such as `/ts3status`, for reverse proxies serving the service at a
+    /// subpath. Empty for the root, it never ends in a slash.
+    pub base_path: String,
+    /// Seconds the channel tree, clients and the server details of `/serverinfo` are cached
+    pub cache_lifetime: u64,
+    /// Seconds icons are cached, by the service and by browsers
+    pub icon_cache_lifetime: u64,
+    /// Refresh the caches in the background instead of on incoming requests
+    pub background_refresh: bool,
+    /// Serve expired caches while refreshing them instead of waiting for the refresh
+    pub stale_while_revalidate: bool,
+    /// Update the caches from notifications of a dedicated query connection per virtual server
+    pub event_updates: bool,
+    pub log_level: Option<String>,
+    pub log_format: LogFormat,
+    /// Log every request with the `access` target in this format, which is shown even if
+    /// `log_level` hides info messages unless it filters `access` itself
+    pub access_log: AccessLog,
+    /// Base URL of an OTLP/HTTP collector to export traces to
+    pub otlp_endpoint: Option<String>,
+    /// Include the platform and version of clients
+    pub client_versions: bool,
+    /// Anonymize the nicknames and countries of clients
+    pub privacy_mode: PrivacyMode,
+    /// Key of the hashes that replace nicknames, kept in the history database or random if unset
+    pub privacy_secret: Option<String>,
+    /// Globs of nicknames or unique ids of clients to leave out, such as music bots
+    pub exclude_clients: Vec<String>,
+    /// Show ServerQuery clients unless requested otherwise with `?include_query=`
+    pub include_query_clients: bool,
+    /// Query channel descriptions, which takes one extra query per channel
+    pub channel_descriptions: bool,
+    /// Default size and theme of `/banner.png`
+    pub banner: BannerOptions,
+    pub cors: CorsOptions,
+    /// Users required for every request, the service is public if none are configured
+    pub basic_auth: BasicAuthOptions,
+    /// Serve Swagger UI at `/docs`
+    pub swagger_ui: bool,
+    /// Requests per minute and client IP, unlimited if unset
+    pub rate_limit: Option<u32>,
+    /// Header with the client IP set by a reverse proxy, such as `X-Forwarded-For`
+    pub client_ip_header: Option<String>,
+    /// Peers whose `client_ip_header` is trusted, the reverse proxies
+    pub trusted_proxies: Vec<IpRange>,
+    /// Source IP ranges allowed to access the service, any if empty
+    pub allowed_ips: Vec<IpRange>,
+    /// Source IP ranges denied access to the service
+    pub denied_ips: Vec<IpRange>,
+    /// Keys accepted in `X-Api-Key` by protected endpoints, which are public if empty
+    pub api_keys: Vec<String>,
+    /// URLs notified about joining and leaving clients
+    pub webhooks: WebhookOptions,
+    /// Discord channel notified about joining and leaving clients
+    pub discord: DiscordOptions,
+    /// MQTT broker the status is published to
+    pub mqtt: BrokerOptions,
+    /// Database the client counts are recorded in
+    pub history: HistoryOptions,
+}
+
+/// Settings as read from the TOML configuration file, every key is optional
+/// since it may also be provided through the environment.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    ts3_host: Option<String>,
+    ts3_resolve: Option<bool>,
+    ts3_port: Option<u16>,
+    ts3_query_protocol: Option<QueryProtocol>,
+    ts3_server_version: Option<ServerVersion>,
+    ts3_server_id: Option<u64>,
+    ts3_server_ids: Option<Vec<u64>>,
+    user: Option<String>,
+    password: Option<String>,
+    ts3_api_key: Option<String>,
+    #[serde(default)]
+    servers: BTreeMap<String, FileServerConfig>,
+    ts3_connect_timeout: Option<u64>,
+    ts3_command_timeout: Option<u64>,
+    query_nickname: Option<String>,
+    ts3_retries: Option<u32>,
+    ts3_retry_backoff_ms: Option<u64>,
+    circuit_breaker_threshold: Option<u32>,
+    circuit_breaker_cooldown: Option<u64>,
+    listen: Option<String>,
+    listen_addrs: Option<Vec<String>>,
+    tls_cert: Option<PathBuf>,
+    tls_key: Option<PathBuf>,
+    hostname: Option<String>,
+    base_path: Option<String>,
+    cache_lifetime: Option<u64>,
+    icon_cache_lifetime: Option<u64>,
+    background_refresh: Option<bool>,
+    stale_while_revalidate: Option<bool>,
+    event_updates: Option<bool>,
+    log_level: Option<String>,
+    log_format: Option<LogFormat>,
+    access_log: Option<AccessLog>,
+    otlp_endpoint: Option<String>,
+    client_versions: Option<bool>,
+    privacy_mode: Option<PrivacyMode>,
+    privacy_secret: Option<String>,
+    exclude_clients: Option<Vec<String>>,
+    include_query_clients: Option<bool>,
+    channel_descriptions: Option<bool>,
+    swagger_ui: Option<bool>,
+    rate_limit: Option<u32>,
+    client_ip_header: Option<String>,
+    trusted_proxies: Option<Vec<IpRange>>,
+    allowed_ips: Option<Vec<IpRange>>,
+    denied_ips: Option<Vec<IpRange>>,
+    api_keys: Option<Vec<String>>,
+    #[serde(default)]
+    banner: FileBannerConfig,
+    #[serde(default)]
+    cors: FileCorsConfig,
+    #[serde(default)]
+    basic_auth: FileBasicAuthConfig,
+    #[serde(default)]
+    webhooks: FileWebhooksConfig,
+    #[serde(default)]
+    discord: FileDiscordConfig,
+    #[serde(default)]
+    mqtt: FileMqttConfig,
+    #[serde(default)]
+    history: FileHistoryConfig,
+}
+
+/// The `[banner]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileBannerConfig {
+    width: Option<u32>,
+    height: Option<u32>,
+    theme: Option<Theme>,
+}
+
+/// The `[cors]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileCorsConfig {
+    origins: Option<Vec<String>>,
+    methods: Option<Vec<String>>,
+    max_age: Option<usize>,
+}
+
+/// The `[basic_auth]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileBasicAuthConfig {
+    username: Option<String>,
+    password: Option<String>,
+    htpasswd: Option<PathBuf>,
+}
+
+/// The `[webhooks]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileWebhooksConfig {
+    urls: Option<Vec<String>>,
+    secret: Option<String>,
+    retries: Option<u32>,
+}
+
+/// The `[discord]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileDiscordConfig {
+    webhook_url: Option<String>,
+    join_template: Option<String>,
+    leave_template: Option<String>,
+    messages_per_minute: Option<u32>,
+}
+
+/// The `[mqtt]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileMqttConfig {
+    host: Option<String>,
+    port: Option<u16>,
+    username: Option<String>,
+    password: Option<String>,
+    client_id: Option<String>,
+    topic_prefix: Option<String>,
+    discovery_prefix: Option<String>,
+}
+
+/// The `[history]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileHistoryConfig {
+    database: Option<PathBuf>,
+    retention_days: Option<u32>,
+}
+
+/// A `[servers.<name>]` section of the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileServerConfig {
+    ts3_host: Option<String>,
+    ts3_resolve: Option<bool>,
+    ts3_port: Option<u16>,
+    ts3_query_protocol: Option<QueryProtocol>,
+    ts3_server_version: Option<ServerVersion>,
+    ts3_server_id: Option<u64>,
+    ts3_server_ids: Option<Vec<u64>>,
+    user: Option<String>,
+    password: Option<String>,
+    ts3_api_key: Option<String>,
+}
+
+impl FileServerConfig {
+    fn is_empty(&self) -> bool {
+        self.ts3_host.is_none()
+            && self.ts3_port.is_none()
+            && self.ts3_server_id.is_none()
+            && self.ts3_server_ids.is_none()
+            && self.user.is_none()
+            && self.password.is_none()
+            && self.ts3_api_key.is_none()
+    }
+
+    fn into_config(self, name: &str, loader: &mut Loader) -> ServerConfig {
+        let mut missing =
+            |key: &str| loader.problem(format!("{} not set for server {}", key, name));
+        let server_id = self.ts3_server_id;
+        let protocol = self.ts3_query_protocol.unwrap_or(QueryProtocol::Raw);
+        // WebQuery authenticates with the API key instead of a login
+        let webquery = matches!(protocol, QueryProtocol::Http | QueryProtocol::Https);
+        if !webquery && self.user.is_none() {
+            missing("user");
+        }
+        if !webquery && self.password.is_none() {
+            missing("password");
+        }
+        if webquery && self.ts3_api_key.is_none() {
+            missing("ts3_api_key");
+        }
+        if self.ts3_host.is_none() {
+            missing("ts3_host");
+        }
+        if self.ts3_port.is_none() {
+            missing("ts3_port");
+        }
+        let ts3_server_ids = self
+            .ts3_server_ids
+            .or_else(|| server_id.map(|id| vec![id]))
+            .filter(|ids| !ids.is_empty());
+        if ts3_server_ids.is_none() {
+            missing("ts3_server_id");
+        }
+
+        // Missing settings are reported, the defaults only fill in for them
+        ServerConfig {
+            name: name.to_string(),
+            ts3_host: self.ts3_host.unwrap_or_default(),
+            ts3_resolve: self.ts3_resolve.unwrap_or(false),
+            ts3_port: self.ts3_port.unwrap_or_default(),
+            ts3_query_protocol: protocol,
+            ts3_server_version: self.ts3_server_version.unwrap_or(ServerVersion::Auto),
+            ts3_server_ids: ts3_server_ids.unwrap_or_default(),
+            user: self.user.unwrap_or_default(),
+            password: self.password.unwrap_or_default(),
+            ts3_api_key: self.ts3_api_key,
+        }
+    }
+}
+
+/// Missing or invalid settings, all of them rather than just the first one.
+#[derive(Debug)]
+pub struct ConfigError {
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for problem in &self.problems {
+            write!(f, "\n  {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Reads settings from the environment, collecting the problems of invalid ones instead of
+/// stopping at the first.
+#[derive(Default)]
+struct Loader {
+    problems: Vec<String>,
+}
+
+impl Loader {
+    fn problem(&mut self, problem: String) {
+        self.problems.push(problem);
+    }
+
+    /// Set the variables of the dotenv file `path`, `ENV_FILE` or `.env` in the working
+    /// directory, that aren't set in the environment yet. Only `.env` may be missing.
+    fn load_env_file(&mut self, path: Option<&Path>) {
+        let path = path
+            .map(Path::to_path_buf)
+            .or_else(|| env::var_os("ENV_FILE").map(PathBuf::from));
+        match path {
+            Some(path) => {
+                if let Err(e) = dotenvy::from_path(&path) {
+                    self.problem(format!("can't load env file {}: {}", path.display(), e));
+                }
+            }
+            None => match dotenvy::dotenv() {
+                Err(e) if !e.not_found() => {
+                    self.problem(format!("can't load env file .env: {}", e))
+                }
+                _ => {}
+            },
+        }
+    }
+
+    /// Returns the value of the environment variable `var` if it is set, falling back to the
+    /// value from the configuration file otherwise.
+    fn env_or<T>(&mut self, var: &str, file_value: Option<T>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match env::var(var) {
+            Ok(value) => match value.parse() {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    self.problem(format!("invalid value {:?} for {}: {}", value, var, e));
+                    file_value
+                }
+            },
+            Err(_) => file_value,
+        }
+    }
+
+    /// Like `env_or`, but falls back to the content of the file named by `<var>_FILE` before the
+    /// value from the configuration file, for secrets mounted by Docker or Kubernetes.
+    fn env_file_or(&mut self, var: &str, file_value: Option<String>) -> Option<String> {
+        if let Some(value) = self.env_or(var, None) {
+            return Some(value);
+        }
+        let file_var = format!("{}_FILE", var);
+        match env::var_os(&file_var) {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(content) => Some(content.trim_end_matches(&['\r', '\n'][..]).to_string()),
+                Err(e) => {
+                    self.problem(format!(
+                        "can't read {} {}: {}",
+                        file_var,
+                        Path::new(&path).display(),
+                        e
+                    ));
+                    file_value
+                }
+            },
+            None => file_value,
+        }
+    }
+
+    /// Like `env_or`, but parses the environment variable as a comma separated list.
+    fn env_list_or<T>(&mut self, var: &str, file_value: Option<Vec<T>>) -> Option<Vec<T>>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = match env::var(var) {
+            Ok(value) => value,
+            Err(_) => return file_value,
+        };
+        let items: Result<Vec<T>, String> = value
+            .split(',')
+            .map(|item| {
+                item.trim()
+                    .parse()
+                    .map_err(|e| format!("invalid value {:?} for {}: {}", item.trim(), var, e))
+            })
+            .collect();
+        match items {
+            Ok(items) => Some(items),
+            Err(problem) => {
+                self.problem(problem);
+                file_value
+            }
+        }
+    }
+}
+
+impl Config {
+    /// The server served at `/`, the default server if configured, the first named one otherwise.
+    pub fn default_server(&self) -> &ServerConfig {
+        self.servers
+            .get(DEFAULT_SERVER)
+            .or_else(|| self.servers.values().next())
+            .expect("no servers configured")
+    }
+
+    /// Check the settings only used when serving, such as the TLS certificate, so they are
+    /// reported along with the others instead of failing once the server starts.
+    fn check_serving(&self, loader: &mut Loader) {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => {
+                if let Err(e) = tls::server_config(cert, key) {
+                    loader.problem(e);
+                }
+            }
+            (Some(_), None) => loader.problem("TLS_CERT is set without TLS_KEY".to_string()),
+            (None, Some(_)) => loader.problem("TLS_KEY is set without TLS_CERT".to_string()),
+            (None, None) => {}
+        }
+        if self.tls_cert.is_some() && self.listen.iter().any(|listen| listen.starts_with("unix:")) {
+            loader.problem("TLS and unix sockets can't be combined".to_string());
+        }
+        if let Err(e) = BasicAuth::new(&self.basic_auth) {
+            loader.problem(e);
+        }
+        if let Err(e) = self.cors.check() {
+            loader.problem(e);
+        }
+    }
+
+    /// Load the configuration from an optional TOML file, environment variables take precedence
+    /// over values from the file and `listen`, passed with `--listen`, over both. Variables not
+    /// set in the environment are read from `env_file` first, see `Loader::load_env_file`. Fails
+    /// with every missing or invalid setting.
+    pub fn load(
+        path: Option<&Path>,
+        env_file: Option<&Path>,
+        listen: Vec<String>,
+    ) -> Result<Config, ConfigError> {
+        let mut loader = Loader::default();
+        loader.load_env_file(env_file);
+        let file: FileConfig = match path {
+            Some(path) => {
+                let file = fs::read_to_string(path)
+                    .map_err(|e| format!("can't read config {}: {}", path.display(), e))
+                    .and_then(|content| {
+                        toml::from_str(&content)
+                            .map_err(|e| format!("invalid config {}: {}", path.display(), e))
+                    });
+                match file {
+                    Ok(file) => file,
+                    // Everything else would be reported missing
+                    Err(problem) => {
+                        loader.problem(problem);
+                        return Err(ConfigError {
+                            problems: loader.problems,
+                        });
+                    }
+                }
+            }
+            None => FileConfig::default(),
+        };
+
+        // The top level settings describe the default server, which is optional as long as
+        // there are named servers.
+        let default = FileServerConfig {
+            ts3_host: loader.env_file_or("TS3_HOST", file.ts3_host),
+            ts3_resolve: loader.env_or("TS3_RESOLVE", file.ts3_resolve),
+            ts3_port: loader.env_or("TS3_PORT", file.ts3_port),
+            ts3_query_protocol: loader.env_or("TS3_QUERY_PROTOCOL", file.ts3_query_protocol),
+            ts3_server_version: loader.env_or("TS3_SERVER_VERSION", file.ts3_server_version),
+            ts3_server_id: file.ts3_server_id,
+            ts3_server_ids: loader.env_list_or("TS3_SERVER_ID", file.ts3_server_ids),
+            user: loader.env_file_or("TS3_USER", file.user),
+            password: loader.env_file_or("TS3_PASS", file.password),
+            ts3_api_key: loader.env_file_or("TS3_API_KEY", file.ts3_api_key),
+        };
+        let mut servers: BTreeMap<String, ServerConfig> = file
+            .servers
+            .into_iter()
+            .map(|(name, server)| {
+                let server = server.into_config(&name, &mut loader);
+                (name, server)
+            })
+            .collect();
+        if !default.is_empty() || servers.is_empty() {
+            if servers.contains_key(DEFAULT_SERVER) {
+                loader.problem(format!("server {} is configured twice", DEFAULT_SERVER));
+            }
+            let server = default.into_config(DEFAULT_SERVER, &mut loader);
+            servers.insert(DEFAULT_SERVER.to_string(), server);
+        }
+
+        let file_listen = match (file.listen_addrs, file.listen) {
+            (Some(addrs), _) => Some(addrs),
+            (None, listen) => listen.map(|listen| vec![listen]),
+        };
+        let listen = Some(listen)
+            .filter(|listen| !listen.is_empty())
+            .or_else(|| loader.env_list_or("LISTEN_ADDR", file_listen))
+            .unwrap_or_default();
+
+        let cache_lifetime = loader
+            .env_or("CACHE_LIFETIME", file.cache_lifetime)
+            .unwrap_or(CACHE_LIFETIME);
+        let hostname = loader.env_or("HOSTNAME", file.hostname);
+        if hostname.is_none() {
+            loader.problem("HOSTNAME not set".to_string());
+        }
+        let base_path = loader
+            .env_or::<String>("BASE_PATH", file.base_path)
+            .map(|path| format!("/{}", path.trim_matches('/')))
+            .filter(|path| path != "/")
+            .unwrap_or_default();
+        if base_path.contains(|c: char| c.is_whitespace() || "?#{}".contains(c)) {
+            loader.problem(format!("invalid value {:?} for BASE_PATH", base_path));
+        }
+
+        let config = Config {
+            servers,
+            ts3_connect_timeout: loader
+                .env_or("TS3_CONNECT_TIMEOUT", file.ts3_connect_timeout)
+                .unwrap_or(5),
+            ts3_command_timeout: loader
+                .env_or("TS3_COMMAND_TIMEOUT", file.ts3_command_timeout)
+                .unwrap_or(10),
+            query_nickname: loader
+                .env_or("QUERY_NICKNAME", file.query_nickname)
+                .unwrap_or_else(|| "ts3status".to_string()),
+            ts3_retries: loader.env_or("TS3_RETRIES", file.ts3_retries).unwrap_or(2),
+            ts3_retry_backoff_ms: loader
+                .env_or("TS3_RETRY_BACKOFF_MS", file.ts3_retry_backoff_ms)
+                .unwrap_or(250),
+            circuit_breaker_threshold: loader
+                .env_or("CIRCUIT_BREAKER_THRESHOLD", file.circuit_breaker_threshold)
+                .unwrap_or(5),
+            circuit_breaker_cooldown: loader
+                .env_or("CIRCUIT_BREAKER_COOLDOWN", file.circuit_breaker_cooldown)
+                .unwrap_or(30),
+            listen,
+            tls_cert: loader.env_or("TLS_CERT", file.tls_cert),
+            tls_key: loader.env_or("TLS_KEY", file.tls_key),
+            hostname: hostname.unwrap_or_default(),
+            base_path,
+            cache_lifetime,
+            icon_cache_lifetime: loader
+                .env_or("ICON_CACHE_LIFETIME", file.icon_cache_lifetime)
+                .unwrap_or(ICON_CACHE_LIFETIME),
+            background_refresh: loader
+                .env_or("BACKGROUND_REFRESH", file.background_refresh)
+                .unwrap_or(true),
+            stale_while_revalidate: loader
+                .env_or("STALE_WHILE_REVALIDATE", file.stale_while_revalidate)
+                .unwrap_or(true),
+            event_updates: loader
+                .env_or("EVENT_UPDATES", file.event_updates)
+                .unwrap_or(false),
+            log_level: loader.env_or("RUST_LOG", file.log_level),
+            log_format: loader
+                .env_or("LOG_FORMAT", file.log_format)
+                .unwrap_or(LogFormat::Text),
+            access_log: loader
+                .env_or("ACCESS_LOG", file.access_log)
+                .unwrap_or(AccessLog::Off),
+            otlp_endpoint: loader.env_or("OTEL_EXPORTER_OTLP_ENDPOINT", file.otlp_endpoint),
+            client_versions: loader
+                .env_or("CLIENT_VERSIONS", file.client_versions)
+                .unwrap_or(true),
+            privacy_mode: loader
+                .env_or("PRIVACY_MODE", file.privacy_mode)
+                .unwrap_or(PrivacyMode::Off),
+            privacy_secret: loader.env_or("PRIVACY_SECRET", file.privacy_secret),
+            exclude_clients: loader
+                .env_list_or("EXCLUDE_CLIENTS", file.exclude_clients)
+                .unwrap_or_default(),
+            include_query_clients: loader
+                .env_or("INCLUDE_QUERY_CLIENTS", file.include_query_clients)
+                .unwrap_or(false),
+            channel_descriptions: loader
+                .env_or("CHANNEL_DESCRIPTIONS", file.channel_descriptions)
+                .unwrap_or(false),
+            swagger_ui: loader
+                .env_or("SWAGGER_UI", file.swagger_ui)
+                .unwrap_or(false),
+            rate_limit: loader.env_or("RATE_LIMIT", file.rate_limit),
+            client_ip_header: loader.env_or("CLIENT_IP_HEADER", file.client_ip_header),
+            trusted_proxies: loader
+                .env_list_or("TRUSTED_PROXIES", file.trusted_proxies)
+                .unwrap_or_else(|| vec![IpRange::LOOPBACK_V4, IpRange::LOOPBACK_V6]),
+            allowed_ips: loader
+                .env_list_or("ALLOWED_IPS", file.allowed_ips)
+                .unwrap_or_default(),
+            denied_ips: loader
+                .env_list_or("DENIED_IPS", file.denied_ips)
+                .unwrap_or_default(),
+            api_keys: loader
+                .env_list_or("API_KEYS", file.api_keys)
+                .unwrap_or_default(),
+            banner: BannerOptions {
+                width: loader
+                    .env_or("BANNER_WIDTH", file.banner.width)
+                    .unwrap_or(468),
+                height: loader
+                    .env_or("BANNER_HEIGHT", file.banner.height)
+                    .unwrap_or(60),
+                theme: loader
+                    .env_or("BANNER_THEME", file.banner.theme)
+                    .unwrap_or(Theme::Dark),
+            },
+            cors: CorsOptions {
+                origins: loader
+                    .env_list_or("CORS_ORIGINS", file.cors.origins)
+                    .unwrap_or_default(),
+                methods: loader
+                    .env_list_or("CORS_METHODS", file.cors.methods)
+                    .unwrap_or_else(|| vec!["GET".to_string()]),
+                max_age: loader
+                    .env_or("CORS_MAX_AGE", file.cors.max_age)
+                    .or(Some(3600)),
+            },
+            basic_auth: BasicAuthOptions {
+                username: loader.env_or("BASIC_AUTH_USER", file.basic_auth.username),
+                password: loader.env_or("BASIC_AUTH_PASSWORD", file.basic_auth.password),
+                htpasswd: loader.env_or("BASIC_AUTH_HTPASSWD", file.basic_auth.htpasswd),
+            },
+            webhooks: WebhookOptions {
+                urls: loader
+                    .env_list_or("WEBHOOK_URLS", file.webhooks.urls)
+                    .unwrap_or_default(),
+                secret: loader.env_or("WEBHOOK_SECRET", file.webhooks.secret),
+                retries: loader
+                    .env_or("WEBHOOK_RETRIES", file.webhooks.retries)
+                    .unwrap_or(3),
+            },
+            discord: DiscordOptions {
+                webhook_url: loader.env_or("DISCORD_WEBHOOK_URL", file.discord.webhook_url),
+                join_template: loader
+                    .env_or("DISCORD_JOIN_TEMPLATE", file.discord.join_template)
+                    .unwrap_or_else(|| "**{nickname}** joined {server}".to_string()),
+                leave_template: loader
+                    .env_or("DISCORD_LEAVE_TEMPLATE", file.discord.leave_template)
+                    .unwrap_or_else(|| "**{nickname}** left {server}".to_string()),
+                messages_per_minute: loader
+                    .env_or(
+                        "DISCORD_MESSAGES_PER_MINUTE",
+                        file.discord.messages_per_minute,
+                    )
+                    .unwrap_or(20),
+            },
+            mqtt: BrokerOptions {
+                host: loader.env_or("MQTT_HOST", file.mqtt.host),
+                port: loader.env_or("MQTT_PORT", file.mqtt.port).unwrap_or(1883),
+                username: loader.env_or("MQTT_USER", file.mqtt.username),
+                password: loader.env_or("MQTT_PASSWORD", file.mqtt.password),
+                client_id: loader
+                    .env_or("MQTT_CLIENT_ID", file.mqtt.client_id)
+                    .unwrap_or_else(|| "ts3status".to_string()),
+                topic_prefix: loader
+                    .env_or("MQTT_TOPIC_PREFIX", file.mqtt.topic_prefix)
+                    .unwrap_or_else(|| "ts3status".to_string()),
+                // Empty to turn off Home Assistant discovery
+                discovery_prefix: Some(
+                    loader
+                        .env_or("MQTT_DISCOVERY_PREFIX", file.mqtt.discovery_prefix)
+                        .unwrap_or_else(|| "homeassistant".to_string()),
+                )
+                .filter(|prefix| !prefix.is_empty()),
+            },
+            history: HistoryOptions {
+                database: loader.env_or("HISTORY_DATABASE", file.history.database),
+                retention_days: loader
+                    .env_or("HISTORY_RETENTION_DAYS", file.history.retention_days)
+                    .unwrap_or(30),
+            },
+        };
+        config.check_serving(&mut loader);
+        if loader.problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError {
+                problems: loader.problems,
+            })
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::convert::TryFrom;
+
+use actix_cors::Cors;
+use actix_web::http::{Method, Uri};
+
+/// Origins, methods and preflight lifetime allowed for cross-origin requests.
+#[derive(Clone, Debug)]
+pub struct CorsOptions {
+    /// Allowed origins, `*` allows any. CORS is disabled if empty.
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    /// Seconds browsers may cache preflight responses
+    pub max_age: Option<usize>,
+}
+
+impl CorsOptions {
+    pub fn enabled(&self) -> bool {
+        !self.origins.is_empty()
+    }
+
+    /// Check that every origin and method is valid, `Cors` only reports invalid ones by panicking
+    /// once the server starts.
+    pub fn check(&self) -> Result<(), String> {
+        for origin in self.origins.iter().filter(|origin| *origin != "*") {
+            if Uri::try_from(origin.as_str()).is_err() {
+                return Err(format!("invalid origin {:?} in CORS_ORIGINS", origin));
+            }
+        }
+        for method in &self.methods {
+            if Method::from_bytes(method.as_bytes()).is_err() {
+                return Err(format!("invalid method {:?} in CORS_METHODS", method));
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the CORS middleware for these options.
+    pub fn middleware(&self) -> Cors {
+        let mut cors = Cors::default()
+            .allowed_methods(self.methods.iter().map(String::as_str))
+            .allow_any_header()
+            .max_age(self.max_age);
+        for origin in &self.origins {
+            cors = match origin.as_str() {
+                "*" => cors.allow_any_origin().send_wildcard(),
+                origin => cors.allowed_origin(origin),
+            };
+        }
+        cors
+    }
+}
@@ -0,0 +1,287 @@
+/// Languages country names are available in, in the order of the columns of `COUNTRIES`.
+const LANGUAGES: [&str; 4] = ["en", "de", "fr", "es"];
+
+/// ISO 3166-1 alpha-2 codes with the country names in every language of `LANGUAGES`.
+#[rustfmt::skip]
+const COUNTRIES: &[(&str, [&str; 4])] = &[
+    ("AD", ["Andorra", "Andorra", "Andorre", "Andorra"]),
+    ("AE", ["United Arab Emirates", "Vereinigte Arabische Emirate", "Émirats arabes unis", "Emiratos Árabes Unidos"]),
+    ("AF", ["Afghanistan", "Afghanistan", "Afghanistan", "Afganistán"]),
+    ("AG", ["Antigua and Barbuda", "Antigua und Barbuda", "Antigua-et-Barbuda", "Antigua y Barbuda"]),
+    ("AI", ["Anguilla", "Anguilla", "Anguilla", "Anguila"]),
+    ("AL", ["Albania", "Albanien", "Albanie", "Albania"]),
+    ("AM", ["Armenia", "Armenien", "Arménie", "Armenia"]),
+    ("AO", ["Angola", "Angola", "Angola", "Angola"]),
+    ("AQ", ["Antarctica", "Antarktis", "Antarctique", "Antártida"]),
+    ("AR", ["Argentina", "Argentinien", "Argentine", "Argentina"]),
+    ("AS", ["American Samoa", "Amerikanisch-Samoa", "Samoa américaines", "Samoa Americana"]),
+    ("AT", ["Austria", "Österreich", "Autriche", "Austria"]),
+    ("AU", ["Australia", "Australien", "Australie", "Australia"]),
+    ("AW", ["Aruba", "Aruba", "Aruba", "Aruba"]),
+    ("AX", ["Åland Islands", "Ålandinseln", "Îles Åland", "Islas Åland"]),
+    ("AZ", ["Azerbaijan", "Aserbaidschan", "Azerbaïdjan", "Azerbaiyán"]),
+    ("BA", ["Bosnia and Herzegovina", "Bosnien und Herzegowina", "Bosnie-Herzégovine", "Bosnia y Herzegovina"]),
+    ("BB", ["Barbados", "Barbados", "Barbade", "Barbados"]),
+    ("BD", ["Bangladesh", "Bangladesch", "Bangladesh", "Bangladés"]),
+    ("BE", ["Belgium", "Belgien", "Belgique", "Bélgica"]),
+    ("BF", ["Burkina Faso", "Burkina Faso", "Burkina Faso", "Burkina Faso"]),
+    ("BG", ["Bulgaria", "Bulgarien", "Bulgarie", "Bulgaria"]),
+    ("BH", ["Bahrain", "Bahrain", "Bahreïn", "Baréin"]),
+    ("BI", ["Burundi", "Burundi", "Burundi", "Burundi"]),
+    ("BJ", ["Benin", "Benin", "Bénin", "Benín"]),
+    ("BL", ["Saint Barthélemy", "St. Barthélemy", "Saint-Barthélemy", "San Bartolomé"]),
+    ("BM", ["Bermuda", "Bermuda", "Bermudes", "Bermudas"]),
+    ("BN", ["Brunei", "Brunei Darussalam", "Brunei", "Brunéi"]),
+    ("BO", ["Bolivia", "Bolivien", "Bolivie", "Bolivia"]),
+    ("BQ", ["Caribbean Netherlands", "Karibische Niederlande", "Pays-Bas caribéens", "Caribe neerlandés"]),
+    ("BR", ["Brazil", "Brasilien", "Brésil", "Brasil"]),
+    ("BS", ["Bahamas", "Bahamas", "Bahamas", "Bahamas"]),
+    ("BT", ["Bhutan", "Bhutan", "Bhoutan", "Bután"]),
+    ("BV", ["Bouvet Island", "Bouvetinsel", "Île Bouvet", "Isla Bouvet"]),
+    ("BW", ["Botswana", "Botsuana", "Botswana", "Botsuana"]),
+    ("BY", ["Belarus", "Belarus", "Biélorussie", "Bielorrusia"]),
+    ("BZ", ["Belize", "Belize", "Belize", "Belice"]),
+    ("CA", ["Canada", "Kanada", "Canada", "Canadá"]),
+    ("CC", ["Cocos (Keeling) Islands", "Kokosinseln", "Îles Cocos", "Islas Cocos"]),
+    ("CD", ["Congo (DRC)", "Kongo (Demokratische Republik)", "Congo (RDC)", "Congo (RDC)"]),
+    ("CF", ["Central African Republic", "Zentralafrikanische Republik", "République centrafricaine", "República Centroafricana"]),
+    ("CG", ["Congo", "Kongo", "Congo", "Congo"]),
+    ("CH", ["Switzerland", "Schweiz", "Suisse", "Suiza"]),
+    ("CI", ["Côte d’Ivoire", "Côte d’Ivoire", "Côte d’Ivoire", "Côte d’Ivoire"]),
+    ("CK", ["Cook Islands", "Cookinseln", "Îles Cook", "Islas Cook"]),
+    ("CL", ["Chile", "Chile", "Chili", "Chile"]),
+    ("CM", ["Cameroon", "Kamerun", "Cameroun", "Camerún"]),
+    ("CN", ["China", "China", "Chine", "China"]),
+    ("CO", ["Colombia", "Kolumbien", "Colombie", "Colombia"]),
+    ("CR", ["Costa Rica", "Costa Rica", "Costa Rica", "Costa Rica"]),
+    ("CU", ["Cuba", "Kuba", "Cuba", "Cuba"]),
+    ("CV", ["Cape Verde", "Cabo Verde", "Cap-Vert", "Cabo Verde"]),
+    ("CW", ["Curaçao", "Curaçao", "Curaçao", "Curazao"]),
+    ("CX", ["Christmas Island", "Weihnachtsinsel", "Île Christmas", "Isla de Navidad"]),
+    ("CY", ["Cyprus", "Zypern", "Chypre", "Chipre"]),
+    ("CZ", ["Czechia", "Tschechien", "Tchéquie", "Chequia"]),
+    ("DE", ["Germany", "Deutschland", "Allemagne", "Alemania"]),
+    ("DJ", ["Djibouti", "Dschibuti", "Djibouti", "Yibuti"]),
+    ("DK", ["Denmark", "Dänemark", "Danemark", "Dinamarca"]),
+    ("DM", ["Dominica", "Dominica", "Dominique", "Dominica"]),
+    ("DO", ["Dominican Republic", "Dominikanische Republik", "République dominicaine", "República Dominicana"]),
+    ("DZ", ["Algeria", "Algerien", "Algérie", "Argelia"]),
+    ("EC", ["Ecuador", "Ecuador", "Équateur", "Ecuador"]),
+    ("EE", ["Estonia", "Estland", "Estonie", "Estonia"]),
+    ("EG", ["Egypt", "Ägypten", "Égypte", "Egipto"]),
+    ("EH", ["Western Sahara", "Westsahara", "Sahara occidental", "Sáhara Occidental"]),
+    ("ER", ["Eritrea", "Eritrea", "Érythrée", "Eritrea"]),
+    ("ES", ["Spain", "Spanien", "Espagne", "España"]),
+    ("ET", ["Ethiopia", "Äthiopien", "Éthiopie", "Etiopía"]),
+    ("FI", ["Finland", "Finnland", "Finlande", "Finlandia"]),
+    ("FJ", ["Fiji", "Fidschi", "Fidji", "Fiyi"]),
+    ("FK", ["Falkland Islands", "Falklandinseln", "Îles Malouines", "Islas Malvinas"]),
+    ("FM", ["Micronesia", "Mikronesien", "Micronésie", "Micronesia"]),
+    ("FO", ["Faroe Islands", "Färöer", "Îles Féroé", "Islas Feroe"]),
+    ("FR", ["France", "Frankreich", "France", "Francia"]),
+    ("GA", ["Gabon", "Gabun", "Gabon", "Gabón"]),
+    ("GB", ["United Kingdom", "Vereinigtes Königreich", "Royaume-Uni", "Reino Unido"]),
+    ("GD", ["Grenada", "Grenada", "Grenade", "Granada"]),
+    ("GE", ["Georgia", "Georgien", "Géorgie", "Georgia"]),
+    ("GF", ["French Guiana", "Französisch-Guayana", "Guyane française", "Guayana Francesa"]),
+    ("GG", ["Guernsey", "Guernsey", "Guernesey", "Guernsey"]),
+    ("GH", ["Ghana", "Ghana", "Ghana", "Ghana"]),
+    ("GI", ["Gibraltar", "Gibraltar", "Gibraltar", "Gibraltar"]),
+    ("GL", ["Greenland", "Grönland", "Groenland", "Groenlandia"]),
+    ("GM", ["Gambia", "Gambia", "Gambie", "Gambia"]),
+    ("GN", ["Guinea", "Guinea", "Guinée", "Guinea"]),
+    ("GP", ["Guadeloupe", "Guadeloupe", "Guadeloupe", "Guadalupe"]),
+    ("GQ", ["Equatorial Guinea", "Äquatorialguinea", "Guinée équatoriale", "Guinea Ecuatorial"]),
+    ("GR", ["Greece", "Griechenland", "Grèce", "Grecia"]),
+    ("GS", ["South Georgia and the South Sandwich Islands", "Südgeorgien und die Südlichen Sandwichinseln", "Géorgie du Sud-et-les Îles Sandwich du Sud", "Islas Georgia del Sur y Sandwich del Sur"]),
+    ("GT", ["Guatemala", "Guatemala", "Guatemala", "Guatemala"]),
+    ("GU", ["Guam", "Guam", "Guam", "Guam"]),
+    ("GW", ["Guinea-Bissau", "Guinea-Bissau", "Guinée-Bissau", "Guinea-Bisáu"]),
+    ("GY", ["Guyana", "Guyana", "Guyana", "Guyana"]),
+    ("HK", ["Hong Kong", "Hongkong", "Hong Kong", "Hong Kong"]),
+    ("HM", ["Heard Island and McDonald Islands", "Heard und McDonaldinseln", "Îles Heard-et-MacDonald", "Islas Heard y McDonald"]),
+    ("HN", ["Honduras", "Honduras", "Honduras", "Honduras"]),
+    ("HR", ["Croatia", "Kroatien", "Croatie", "Croacia"]),
+    ("HT", ["Haiti", "Haiti", "Haïti", "Haití"]),
+    ("HU", ["Hungary", "Ungarn", "Hongrie", "Hungría"]),
+    ("ID", ["Indonesia", "Indonesien", "Indonésie", "Indonesia"]),
+    ("IE", ["Ireland", "Irland", "Irlande", "Irlanda"]),
+    ("IL", ["Israel", "Israel", "Israël", "Israel"]),
+    ("IM", ["Isle of Man", "Isle of Man", "Île de Man", "Isla de Man"]),
+    ("IN", ["India", "Indien", "Inde", "India"]),
+    ("IO", ["British Indian Ocean Territory", "Britisches Territorium im Indischen Ozean", "Territoire britannique de l’océan Indien", "Territorio Británico del Océano Índico"]),
+    ("IQ", ["Iraq", "Irak", "Irak", "Irak"]),
+    ("IR", ["Iran", "Iran", "Iran", "Irán"]),
+    ("IS", ["Iceland", "Island", "Islande", "Islandia"]),
+    ("IT", ["Italy", "Italien", "Italie", "Italia"]),
+    ("JE", ["Jersey", "Jersey", "Jersey", "Jersey"]),
+    ("JM", ["Jamaica", "Jamaika", "Jamaïque", "Jamaica"]),
+    ("JO", ["Jordan", "Jordanien", "Jordanie", "Jordania"]),
+    ("JP", ["Japan", "Japan", "Japon", "Japón"]),
+    ("KE", ["Kenya", "Kenia", "Kenya", "Kenia"]),
+    ("KG", ["Kyrgyzstan", "Kirgisistan", "Kirghizistan", "Kirguistán"]),
+    ("KH", ["Cambodia", "Kambodscha", "Cambodge", "Camboya"]),
+    ("KI", ["Kiribati", "Kiribati", "Kiribati", "Kiribati"]),
+    ("KM", ["Comoros", "Komoren", "Comores", "Comoras"]),
+    ("KN", ["Saint Kitts and Nevis", "St. Kitts und Nevis", "Saint-Christophe-et-Niévès", "San Cristóbal y Nieves"]),
+    ("KP", ["North Korea", "Nordkorea", "Corée du Nord", "Corea del Norte"]),
+    ("KR", ["South Korea", "Südkorea", "Corée du Sud", "Corea del Sur"]),
+    ("KW", ["Kuwait", "Kuwait", "Koweït", "Kuwait"]),
+    ("KY", ["Cayman Islands", "Kaimaninseln", "Îles Caïmans", "Islas Caimán"]),
+    ("KZ", ["Kazakhstan", "Kasachstan", "Kazakhstan", "Kazajistán"]),
+    ("LA", ["Laos", "Laos", "Laos", "Laos"]),
+    ("LB", ["Lebanon", "Libanon", "Liban", "Líbano"]),
+    ("LC", ["Saint Lucia", "St. Lucia", "Sainte-Lucie", "Santa Lucía"]),
+    ("LI", ["Liechtenstein", "Liechtenstein", "Liechtenstein", "Liechtenstein"]),
+    ("LK", ["Sri Lanka", "Sri Lanka", "Sri Lanka", "Sri Lanka"]),
+    ("LR", ["Liberia", "Liberia", "Liberia", "Liberia"]),
+    ("LS", ["Lesotho", "Lesotho", "Lesotho", "Lesoto"]),
+    ("LT", ["Lithuania", "Litauen", "Lituanie", "Lituania"]),
+    ("LU", ["Luxembourg", "Luxemburg", "Luxembourg", "Luxemburgo"]),
+    ("LV", ["Latvia", "Lettland", "Lettonie", "Letonia"]),
+    ("LY", ["Libya", "Libyen", "Libye", "Libia"]),
+    ("MA", ["Morocco", "Marokko", "Maroc", "Marruecos"]),
+    ("MC", ["Monaco", "Monaco", "Monaco", "Mónaco"]),
+    ("MD", ["Moldova", "Republik Moldau", "Moldavie", "Moldavia"]),
+    ("ME", ["Montenegro", "Montenegro", "Monténégro", "Montenegro"]),
+    ("MF", ["Saint Martin", "St. Martin", "Saint-Martin", "San Martín"]),
+    ("MG", ["Madagascar", "Madagaskar", "Madagascar", "Madagascar"]),
+    ("MH", ["Marshall Islands", "Marshallinseln", "Îles Marshall", "Islas Marshall"]),
+    ("MK", ["North Macedonia", "Nordmazedonien", "Macédoine du Nord", "Macedonia del Norte"]),
+    ("ML", ["Mali", "Mali", "Mali", "Mali"]),
+    ("MM", ["Myanmar", "Myanmar", "Myanmar", "Myanmar"]),
+    ("MN", ["Mongolia", "Mongolei", "Mongolie", "Mongolia"]),
+    ("MO", ["Macao", "Macau", "Macao", "Macao"]),
+    ("MP", ["Northern Mariana Islands", "Nördliche Marianen", "Îles Mariannes du Nord", "Islas Marianas del Norte"]),
+    ("MQ", ["Martinique", "Martinique", "Martinique", "Martinica"]),
+    ("MR", ["Mauritania", "Mauretanien", "Mauritanie", "Mauritania"]),
+    ("MS", ["Montserrat", "Montserrat", "Montserrat", "Montserrat"]),
+    ("MT", ["Malta", "Malta", "Malte", "Malta"]),
+    ("MU", ["Mauritius", "Mauritius", "Maurice", "Mauricio"]),
+    ("MV", ["Maldives", "Malediven", "Maldives", "Maldivas"]),
+    ("MW", ["Malawi", "Malawi", "Malawi", "Malaui"]),
+    ("MX", ["Mexico", "Mexiko", "Mexique", "México"]),
+    ("MY", ["Malaysia", "Malaysia", "Malaisie", "Malasia"]),
+    ("MZ", ["Mozambique", "Mosambik", "Mozambique", "Mozambique"]),
+    ("NA", ["Namibia", "Namibia", "Namibie", "Namibia"]),
+    ("NC", ["New Caledonia", "Neukaledonien", "Nouvelle-Calédonie", "Nueva Caledonia"]),
+    ("NE", ["Niger", "Niger", "Niger", "Níger"]),
+    ("NF", ["Norfolk Island", "Norfolkinsel", "Île Norfolk", "Isla Norfolk"]),
+    ("NG", ["Nigeria", "Nigeria", "Nigéria", "Nigeria"]),
+    ("NI", ["Nicaragua", "Nicaragua", "Nicaragua", "Nicaragua"]),
+    ("NL", ["Netherlands", "Niederlande", "Pays-Bas", "Países Bajos"]),
+    ("NO", ["Norway", "Norwegen", "Norvège", "Noruega"]),
+    ("NP", ["Nepal", "Nepal", "Népal", "Nepal"]),
+    ("NR", ["Nauru", "Nauru", "Nauru", "Nauru"]),
+    ("NU", ["Niue", "Niue", "Niue", "Niue"]),
+    ("NZ", ["New Zealand", "Neuseeland", "Nouvelle-Zélande", "Nueva Zelanda"]),
+    ("OM", ["Oman", "Oman", "Oman", "Omán"]),
+    ("PA", ["Panama", "Panama", "Panama", "Panamá"]),
+    ("PE", ["Peru", "Peru", "Pérou", "Perú"]),
+    ("PF", ["French Polynesia", "Französisch-Polynesien", "Polynésie française", "Polinesia Francesa"]),
+    ("PG", ["Papua New Guinea", "Papua-Neuguinea", "Papouasie-Nouvelle-Guinée", "Papúa Nueva Guinea"]),
+    ("PH", ["Philippines", "Philippinen", "Philippines", "Filipinas"]),
+    ("PK", ["Pakistan", "Pakistan", "Pakistan", "Pakistán"]),
+    ("PL", ["Poland", "Polen", "Pologne", "Polonia"]),
+    ("PM", ["Saint Pierre and Miquelon", "St. Pierre und Miquelon", "Saint-Pierre-et-Miquelon", "San Pedro y Miquelón"]),
+    ("PN", ["Pitcairn Islands", "Pitcairninseln", "Îles Pitcairn", "Islas Pitcairn"]),
+    ("PR", ["Puerto Rico", "Puerto Rico", "Porto Rico", "Puerto Rico"]),
+    ("PS", ["Palestine", "Palästinensische Autonomiegebiete", "Territoires palestiniens", "Territorios Palestinos"]),
+    ("PT", ["Portugal", "Portugal", "Portugal", "Portugal"]),
+    ("PW", ["Palau", "Palau", "Palaos", "Palaos"]),
+    ("PY", ["Paraguay", "Paraguay", "Paraguay", "Paraguay"]),
+    ("QA", ["Qatar", "Katar", "Qatar", "Catar"]),
+    ("RE", ["Réunion", "Réunion", "La Réunion", "Reunión"]),
+    ("RO", ["Romania", "Rumänien", "Roumanie", "Rumanía"]),
+    ("RS", ["Serbia", "Serbien", "Serbie", "Serbia"]),
+    ("RU", ["Russia", "Russland", "Russie", "Rusia"]),
+    ("RW", ["Rwanda", "Ruanda", "Rwanda", "Ruanda"]),
+    ("SA", ["Saudi Arabia", "Saudi-Arabien", "Arabie saoudite", "Arabia Saudí"]),
+    ("SB", ["Solomon Islands", "Salomonen", "Îles Salomon", "Islas Salomón"]),
+    ("SC", ["Seychelles", "Seychellen", "Seychelles", "Seychelles"]),
+    ("SD", ["Sudan", "Sudan", "Soudan", "Sudán"]),
+    ("SE", ["Sweden", "Schweden", "Suède", "Suecia"]),
+    ("SG", ["Singapore", "Singapur", "Singapour", "Singapur"]),
+    ("SH", ["Saint Helena", "St. Helena", "Sainte-Hélène", "Santa Elena"]),
+    ("SI", ["Slovenia", "Slowenien", "Slovénie", "Eslovenia"]),
+    ("SJ", ["Svalbard and Jan Mayen", "Spitzbergen und Jan Mayen", "Svalbard et Jan Mayen", "Svalbard y Jan Mayen"]),
+    ("SK", ["Slovakia", "Slowakei", "Slovaquie", "Eslovaquia"]),
+    ("SL", ["Sierra Leone", "Sierra Leone", "Sierra Leone", "Sierra Leona"]),
+    ("SM", ["San Marino", "San Marino", "Saint-Marin", "San Marino"]),
+    ("SN", ["Senegal", "Senegal", "Sénégal", "Senegal"]),
+    ("SO", ["Somalia", "Somalia", "Somalie", "Somalia"]),
+    ("SR", ["Suriname", "Suriname", "Suriname", "Surinam"]),
+    ("SS", ["South Sudan", "Südsudan", "Soudan du Sud", "Sudán del Sur"]),
+    ("ST", ["São Tomé and Príncipe", "São Tomé und Príncipe", "Sao Tomé-et-Principe", "Santo Tomé y Príncipe"]),
+    ("SV", ["El Salvador", "El Salvador", "Salvador", "El Salvador"]),
+    ("SX", ["Sint Maarten", "Sint Maarten", "Saint-Martin (partie néerlandaise)", "Sint Maarten"]),
+    ("SY", ["Syria", "Syrien", "Syrie", "Siria"]),
+    ("SZ", ["Eswatini", "Eswatini", "Eswatini", "Esuatini"]),
+    ("TC", ["Turks and Caicos Islands", "Turks- und Caicosinseln", "Îles Turques-et-Caïques", "Islas Turcas y Caicos"]),
+    ("TD", ["Chad", "Tschad", "Tchad", "Chad"]),
+    ("TF", ["French Southern Territories", "Französische Süd- und Antarktisgebiete", "Terres australes françaises", "Territorios Australes Franceses"]),
+    ("TG", ["Togo", "Togo", "Togo", "Togo"]),
+    ("TH", ["Thailand", "Thailand", "Thaïlande", "Tailandia"]),
+    ("TJ", ["Tajikistan", "Tadschikistan", "Tadjikistan", "Tayikistán"]),
+    ("TK", ["Tokelau", "Tokelau", "Tokelau", "Tokelau"]),
+    ("TL", ["Timor-Leste", "Timor-Leste", "Timor oriental", "Timor-Leste"]),
+    ("TM", ["Turkmenistan", "Turkmenistan", "Turkménistan", "Turkmenistán"]),
+    ("TN", ["Tunisia", "Tunesien", "Tunisie", "Túnez"]),
+    ("TO", ["Tonga", "Tonga", "Tonga", "Tonga"]),
+    ("TR", ["Türkiye", "Türkei", "Turquie", "Turquía"]),
+    ("TT", ["Trinidad and Tobago", "Trinidad und Tobago", "Trinité-et-Tobago", "Trinidad y Tobago"]),
+    ("TV", ["Tuvalu", "Tuvalu", "Tuvalu", "Tuvalu"]),
+    ("TW", ["Taiwan", "Taiwan", "Taïwan", "Taiwán"]),
+    ("TZ", ["Tanzania", "Tansania", "Tanzanie", "Tanzania"]),
+    ("UA", ["Ukraine", "Ukraine", "Ukraine", "Ucrania"]),
+    ("UG", ["Uganda", "Uganda", "Ouganda", "Uganda"]),
+    ("UM", ["U.S. Outlying Islands", "Amerikanische Überseeinseln", "Îles mineures éloignées des États-Unis", "Islas menores alejadas de EE. UU."]),
+    ("US", ["United States", "Vereinigte Staaten", "États-Unis", "Estados Unidos"]),
+    ("UY", ["Uruguay", "Uruguay", "Uruguay", "Uruguay"]),
+    ("UZ", ["Uzbekistan", "Usbekistan", "Ouzbékistan", "Uzbekistán"]),
+    ("VA", ["Vatican City", "Vatikanstadt", "État de la Cité du Vatican", "Ciudad del Vaticano"]),
+    ("VC", ["Saint Vincent and the Grenadines", "St. Vincent und die Grenadinen", "Saint-Vincent-et-les-Grenadines", "San Vicente y las Granadinas"]),
+    ("VE", ["Venezuela", "Venezuela", "Venezuela", "Venezuela"]),
+    ("VG", ["British Virgin Islands", "Britische Jungferninseln", "Îles Vierges britanniques", "Islas Vírgenes Británicas"]),
+    ("VI", ["U.S. Virgin Islands", "Amerikanische Jungferninseln", "Îles Vierges des États-Unis", "Islas Vírgenes de EE. UU."]),
+    ("VN", ["Vietnam", "Vietnam", "Viêt Nam", "Vietnam"]),
+    ("VU", ["Vanuatu", "Vanuatu", "Vanuatu", "Vanuatu"]),
+    ("WF", ["Wallis and Futuna", "Wallis und Futuna", "Wallis-et-Futuna", "Wallis y Futuna"]),
+    ("WS", ["Samoa", "Samoa", "Samoa", "Samoa"]),
+    ("XK", ["Kosovo", "Kosovo", "Kosovo", "Kosovo"]),
+    ("YE", ["Yemen", "Jemen", "Yémen", "Yemen"]),
+    ("YT", ["Mayotte", "Mayotte", "Mayotte", "Mayotte"]),
+    ("ZA", ["South Africa", "Südafrika", "Afrique du Sud", "Sudáfrica"]),
+    ("ZM", ["Zambia", "Sambia", "Zambie", "Zambia"]),
+    ("ZW", ["Zimbabwe", "Simbabwe", "Zimbabwe", "Zimbabue"]),
+];
+
+/// Name of the country with the ISO 3166-1 alpha-2 code `code` in the language of `locale`, such
+/// as `de` or `de-AT`. Unsupported languages fall back to English.
+pub fn name(code: &str, locale: Option<&str>) -> Option<&'static str> {
+    let language = locale
+        .and_then(|locale| locale.split(['-', '_']).next())
+        .and_then(|language| {
+            LANGUAGES
+                .iter()
+                .position(|l| l.eq_ignore_ascii_case(language))
+        })
+        .unwrap_or(0);
+
+    let code = code.to_ascii_uppercase();
+    COUNTRIES
+        .binary_search_by(|(c, _)| (*c).cmp(code.as_str()))
+        .ok()
+        .map(|index| COUNTRIES[index].1[language])
+}
+
+/// Flag emoji of the country with the code `code`, built from regional indicator symbols.
+pub fn flag(code: &str) -> Option<String> {
+    if code.len() != 2 || !code.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    code.to_ascii_uppercase()
+        .chars()
+        .map(|c| char::from_u32(0x1F1E6 + (c as u32 - 'A' as u32)))
+        .collect()
+}
@@ -0,0 +1,61 @@
+use serde::Serialize;
+
+use crate::{
+    query::{ChannelNode, Client, ServerInfo},
+    view::{self, PageQuery},
+};
+
+/// A row of the client list exported as CSV.
+#[derive(Serialize)]
+struct ClientRow<'a> {
+    nickname: &'a str,
+    /// Names of the channel and its parents, separated by `/`
+    channel: &'a str,
+    country: &'a str,
+    away: bool,
+    muted: bool,
+    /// Unix timestamp
+    connected_since: Option<u64>,
+}
+
+/// Collect the clients of `channels` together with the path of their channel.
+fn collect<'a>(channels: &'a [ChannelNode], parent: &str, out: &mut Vec<(String, &'a Client)>) {
+    for channel in channels {
+        let path = if parent.is_empty() {
+            channel.name.clone()
+        } else {
+            format!("{}/{}", parent, channel.name)
+        };
+        out.extend(channel.clients.iter().map(|client| (path.clone(), client)));
+        collect(&channel.children, &path, out);
+    }
+}
+
+/// The page of the clients on the server described by `query` as CSV with a header row.
+pub fn clients(query: &PageQuery, server_info: &ServerInfo) -> Result<Vec<u8>, csv::Error> {
+    let mut clients = Vec::new();
+    collect(&server_info.channels, "", &mut clients);
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for (channel, client) in clients
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+    {
+        let mut client = client.clone();
+        if let Some(locale) = query.locale.as_deref() {
+            view::localize(&mut client, locale);
+        }
+        writer.serialize(ClientRow {
+            nickname: &client.nickname,
+            channel: &channel,
+            country: client.country_name.as_deref().unwrap_or(&client.country),
+            away: client.away,
+            muted: client.input_muted,
+            connected_since: client.connected_since,
+        })?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
@@ -0,0 +1,108 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+use crate::{
+    events::{client_events, ClientEvent, ClientEventKind},
+    query::{ChannelNode, Client, ServerInfo},
+};
+
+/// A channel that was added or changed, without its subchannels.
+#[derive(Clone, PartialEq, Serialize, ToSchema)]
+pub struct ChannelChange {
+    /// Id of the parent channel, 0 for top level channels
+    pub parent_id: u64,
+    #[serde(flatten)]
+    pub channel: ChannelNode,
+}
+
+/// Changes of a virtual server between two revisions.
+#[derive(Default, Serialize, ToSchema)]
+pub struct Delta {
+    pub joined: Vec<ClientEvent>,
+    pub left: Vec<ClientEvent>,
+    /// Channels that were added or changed, including clients joining, leaving or moving
+    pub channels: Vec<ChannelChange>,
+    /// Ids of the channels that were removed
+    pub removed_channels: Vec<u64>,
+}
+
+impl Delta {
+    pub fn is_empty(&self) -> bool {
+        self.joined.is_empty()
+            && self.left.is_empty()
+            && self.channels.is_empty()
+            && self.removed_channels.is_empty()
+    }
+}
+
+impl ChannelChange {
+    /// Whether `other` is the same channel with the same clients, apart from how long the clients
+    /// have been idle and connected, which changes with every refresh.
+    fn same_as(&self, other: &ChannelChange) -> bool {
+        let settled = |change: &ChannelChange| ChannelChange {
+            channel: ChannelNode {
+                clients: change
+                    .channel
+                    .clients
+                    .iter()
+                    .map(|client| Client {
+                        idle_ms: 0,
+                        connected_since: None,
+                        ..client.clone()
+                    })
+                    .collect(),
+                ..change.channel.clone()
+            },
+            ..change.clone()
+        };
+        settled(self) == settled(other)
+    }
+}
+
+/// All channels of the tree `channels` in order, without their subchannels.
+fn flatten(channels: &[ChannelNode], parent_id: u64, out: &mut Vec<ChannelChange>) {
+    for channel in channels {
+        out.push(ChannelChange {
+            parent_id,
+            channel: ChannelNode {
+                children: Vec::new(),
+                ..channel.clone()
+            },
+        });
+        flatten(&channel.children, channel.id, out);
+    }
+}
+
+/// Compare two snapshots of a server and return what changed in between.
+pub fn delta(previous: &ServerInfo, current: &ServerInfo) -> Delta {
+    let mut previous_channels = Vec::new();
+    flatten(&previous.channels, 0, &mut previous_channels);
+    let mut previous_channels: HashMap<u64, ChannelChange> = previous_channels
+        .into_iter()
+        .map(|change| (change.channel.id, change))
+        .collect();
+    let mut current_channels = Vec::new();
+    flatten(&current.channels, 0, &mut current_channels);
+
+    let channels = current_channels
+        .into_iter()
+        .filter(|change| {
+            !previous_channels
+                .remove(&change.channel.id)
+                .is_some_and(|previous| previous.same_as(change))
+        })
+        .collect();
+    let mut removed_channels: Vec<u64> = previous_channels.into_keys().collect();
+    removed_channels.sort_unstable();
+
+    let (joined, left) = client_events(previous, current)
+        .into_iter()
+        .partition(|event| event.event == ClientEventKind::Join);
+    Delta {
+        joined,
+        left,
+        channels,
+        removed_channels,
+    }
+}
@@ -0,0 +1,202 @@
+use std::time::{Duration, Instant};
+
+use actix_web::rt::time;
+use futures::{
+    channel::mpsc::{UnboundedReceiver, UnboundedSender},
+    StreamExt,
+};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{client_event_stream, ClientEvent, ClientEventKind},
+    query::{ServerInfo, StatusUpdate},
+};
+
+// Give up on sending a message after this long
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Discord rejects messages longer than this many characters
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+// Attempts to send a message that keeps failing
+const ATTEMPTS: u32 = 3;
+
+/// Discord channel notified about joining and leaving clients.
+#[derive(Clone, Debug, Default)]
+pub struct DiscordOptions {
+    /// Webhook URL of the channel, as created in the channel settings
+    pub webhook_url: Option<String>,
+    /// Messages for joining and leaving clients, with `{nickname}`, `{channel}` and `{server}`
+    /// replaced by the nickname of the client, the name of its channel and the server name
+    pub join_template: String,
+    pub leave_template: String,
+    /// Messages sent per minute at most, events in the meantime are sent together
+    pub messages_per_minute: u32,
+}
+
+impl DiscordOptions {
+    pub fn enabled(&self) -> bool {
+        self.webhook_url.is_some()
+    }
+
+    /// The line announcing `event` on the server `server`.
+    fn line(&self, server: &str, event: &ClientEvent) -> String {
+        let template = match event.event {
+            ClientEventKind::Join => &self.join_template,
+            ClientEventKind::Leave => &self.leave_template,
+        };
+        let mut line = String::new();
+        let mut rest = template.as_str();
+        // Replaced in a single pass, so placeholders in nicknames stay as they are
+        while let Some(start) = rest.find('{') {
+            line.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find('}').map_or(rest.len(), |end| end + 1);
+            match &rest[..end] {
+                "{nickname}" => line.push_str(&escape(&event.client.nickname)),
+                "{channel}" => line.push_str(&escape(&event.channel_name)),
+                "{server}" => line.push_str(&escape(server)),
+                other => line.push_str(other),
+            }
+            rest = &rest[end..];
+        }
+        line.push_str(rest);
+        line
+    }
+}
+
+/// Escape Discord markdown in `text`, so nicknames show up as they are.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '~' | '`' | '|' | '>' | '#' | '[' | ']'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[derive(Serialize)]
+struct AllowedMentions {
+    parse: [&'static str; 0],
+}
+
+/// Body of a webhook request, see https://discord.com/developers/docs/resources/webhook
+#[derive(Serialize)]
+struct Message<'a> {
+    content: &'a str,
+    /// Nicknames such as `@everyone` must not ping anybody
+    allowed_mentions: AllowedMentions,
+}
+
+/// Body of `429 Too Many Requests` responses.
+#[derive(Deserialize)]
+struct RateLimited {
+    /// Seconds to wait before sending again
+    retry_after: f64,
+}
+
+/// Queue the announcements of every client joining or leaving the virtual server behind
+/// `updates` on `lines`, for `send_messages`.
+pub async fn announce(
+    options: DiscordOptions,
+    current: Option<ServerInfo>,
+    updates: UnboundedReceiver<StatusUpdate>,
+    include_query: bool,
+    lines: UnboundedSender<String>,
+) {
+    let mut changes = Box::pin(client_event_stream(current, updates, include_query));
+    while let Some((server_info, events)) = changes.next().await {
+        for event in &events {
+            if lines
+                .unbounded_send(options.line(&server_info.name, event))
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// Join `lines` into messages of at most `MAX_MESSAGE_LENGTH` characters.
+fn messages(lines: &[String]) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut message = String::new();
+    for line in lines {
+        let line: String = line.chars().take(MAX_MESSAGE_LENGTH).collect();
+        if !message.is_empty()
+            && message.chars().count() + 1 + line.chars().count() > MAX_MESSAGE_LENGTH
+        {
+            messages.push(std::mem::take(&mut message));
+        }
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(&line);
+    }
+    if !message.is_empty() {
+        messages.push(message);
+    }
+    messages
+}
+
+/// Post `content` to the webhook `url`, waiting as long as Discord asks when rate limited.
+async fn send(client: &awc::Client, url: &str, content: &str) {
+    let message = Message {
+        content,
+        allowed_mentions: AllowedMentions { parse: [] },
+    };
+    for _ in 0..ATTEMPTS {
+        match client.post(url).send_json(&message).await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(mut response) if response.status().as_u16() == 429 => {
+                let retry_after = response
+                    .json::<RateLimited>()
+                    .await
+                    .map(|limited| Duration::from_secs_f64(limited.retry_after.max(0.0)))
+                    .unwrap_or(Duration::from_secs(1));
+                warn!("Rate limited by Discord, retrying in {:?}", retry_after);
+                time::delay_for(retry_after).await;
+            }
+            Ok(response) => {
+                error!("Discord webhook failed with status {}", response.status());
+                return;
+            }
+            Err(e) => warn!("Discord webhook failed: {}", e),
+        }
+    }
+    error!("Discord webhook kept failing, dropping message");
+}
+
+/// Send the lines queued by `announce` to Discord. Lines queued together, such as several clients
+/// joining at once, or while waiting for the rate limit are sent as one message.
+pub async fn send_messages(options: DiscordOptions, mut lines: UnboundedReceiver<String>) {
+    let url = match options.webhook_url {
+        Some(url) => url,
+        None => return,
+    };
+    let client = awc::Client::builder().timeout(TIMEOUT).finish();
+    let interval = Duration::from_secs(60) / options.messages_per_minute.max(1);
+    let mut last_sent: Option<Instant> = None;
+
+    while let Some(line) = lines.next().await {
+        if let Some(wait) = last_sent.and_then(|sent| interval.checked_sub(sent.elapsed())) {
+            time::delay_for(wait).await;
+        }
+        let mut batch = vec![line];
+        while let Ok(line) = lines.try_recv() {
+            batch.push(line);
+        }
+
+        info!("Sending {} announcements to Discord", batch.len());
+        for message in messages(&batch) {
+            send(&client, &url, &message).await;
+        }
+        last_sent = Some(Instant::now());
+    }
+}
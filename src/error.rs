@@ -0,0 +1,83 @@
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use serde::Serialize;
+use ts3_query::Ts3Error;
+use utoipa::ToSchema;
+
+// TS3 error ids of responses with a known cause
+pub const INVALID_SERVER_ID: usize = 1024;
+pub const INVALID_LOGIN: usize = 520;
+pub const BANNED: usize = 3329;
+pub const FLOOD_BANNED: usize = 3331;
+pub const INSUFFICIENT_PERMISSIONS: usize = 2568;
+
+/// Why the status of a virtual server couldn't be queried.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The TS3 host couldn't be reached or closed the connection
+    ConnectFailed,
+    /// The query login or API key was rejected or lacks permissions
+    AuthFailed,
+    /// The virtual server doesn't exist
+    ServerNotFound,
+    /// The TS3 host banned this service, usually for flooding it with commands
+    FloodBan,
+    /// The TS3 host answered with something that couldn't be parsed
+    ParseError,
+    /// The TS3 host answered with another error
+    QueryFailed,
+}
+
+impl ErrorCode {
+    /// HTTP status of responses failing with this error. Unreachable or banning hosts are
+    /// unavailable, a rejected login or unknown virtual server is a configuration problem.
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::ConnectFailed | ErrorCode::FloodBan => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::AuthFailed | ErrorCode::ServerNotFound => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::ParseError | ErrorCode::QueryFailed => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// A failed query of a TS3 host.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl QueryError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<Ts3Error> for QueryError {
+    fn from(e: Ts3Error) -> Self {
+        let code = match &e {
+            Ts3Error::Io { .. }
+            | Ts3Error::ConnectionClosed { .. }
+            | Ts3Error::InvalidSocketAddress { .. } => ErrorCode::ConnectFailed,
+            Ts3Error::ServerError { response, .. } => match response.id {
+                INVALID_LOGIN | INSUFFICIENT_PERMISSIONS => ErrorCode::AuthFailed,
+                INVALID_SERVER_ID => ErrorCode::ServerNotFound,
+                BANNED | FLOOD_BANNED => ErrorCode::FloodBan,
+                _ => ErrorCode::QueryFailed,
+            },
+            _ => ErrorCode::ParseError,
+        };
+        Self::new(code, e.to_string())
+    }
+}
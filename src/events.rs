@@ -0,0 +1,130 @@
+use futures::{channel::mpsc::UnboundedReceiver, future, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::HashSet;
+use utoipa::ToSchema;
+
+use crate::query::{Client, ServerInfo, StatusUpdate};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientEventKind {
+    Join,
+    Leave,
+}
+
+impl ClientEventKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClientEventKind::Join => "join",
+            ClientEventKind::Leave => "leave",
+        }
+    }
+}
+
+/// A client that joined or left the server between two snapshots.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct ClientEvent {
+    pub event: ClientEventKind,
+    pub client: Client,
+    pub channel_id: u64,
+    pub channel_name: String,
+}
+
+/// Compare two snapshots of a server and return the clients that left and joined in between.
+pub fn client_events(previous: &ServerInfo, current: &ServerInfo) -> Vec<ClientEvent> {
+    let previous_clients = previous.clients();
+    let current_clients = current.clients();
+    let previous_ids: HashSet<u16> = previous_clients.iter().map(|(_, c)| c.id).collect();
+    let current_ids: HashSet<u16> = current_clients.iter().map(|(_, c)| c.id).collect();
+
+    let left = previous_clients
+        .into_iter()
+        .filter(|(_, client)| !current_ids.contains(&client.id))
+        .map(|entry| (ClientEventKind::Leave, entry));
+    let joined = current_clients
+        .into_iter()
+        .filter(|(_, client)| !previous_ids.contains(&client.id))
+        .map(|entry| (ClientEventKind::Join, entry));
+
+    left.chain(joined)
+        .map(|(event, (channel, client))| ClientEvent {
+            event,
+            client: client.clone(),
+            channel_id: channel.id,
+            channel_name: channel.name.clone(),
+        })
+        .collect()
+}
+
+/// A change of a virtual server listed in the event feed.
+#[derive(Clone)]
+pub enum ServerEvent {
+    Client(Box<ClientEvent>),
+    ChannelCreated { id: u64, name: String },
+    ChannelDeleted { id: u64, name: String },
+}
+
+/// Compare two snapshots of a server and return the clients that left and joined, and the
+/// channels that were created and deleted in between.
+pub fn server_events(previous: &ServerInfo, current: &ServerInfo) -> Vec<ServerEvent> {
+    let channel_ids = |server_info: &ServerInfo| -> Vec<u64> {
+        server_info
+            .channel_counts()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    };
+    let previous_ids = channel_ids(previous);
+    let current_ids = channel_ids(current);
+
+    let created = current_ids
+        .iter()
+        .filter(|id| !previous_ids.contains(id))
+        .filter_map(|&id| current.channel(id))
+        .map(|channel| ServerEvent::ChannelCreated {
+            id: channel.id,
+            name: channel.name.clone(),
+        });
+    let deleted = previous_ids
+        .iter()
+        .filter(|id| !current_ids.contains(id))
+        .filter_map(|&id| previous.channel(id))
+        .map(|channel| ServerEvent::ChannelDeleted {
+            id: channel.id,
+            name: channel.name.clone(),
+        });
+    let (joined, left): (Vec<_>, Vec<_>) = client_events(previous, current)
+        .into_iter()
+        .partition(|event| event.event == ClientEventKind::Join);
+
+    // Clients leave deleted channels before they are deleted, and join created ones after
+    left.into_iter()
+        .map(|event| ServerEvent::Client(Box::new(event)))
+        .chain(deleted)
+        .chain(created)
+        .chain(
+            joined
+                .into_iter()
+                .map(|event| ServerEvent::Client(Box::new(event))),
+        )
+        .collect()
+}
+
+/// Stream of every status of `updates` together with the clients that joined and left since the
+/// previous one, starting from `current`. No events are reported for the first status if
+/// `current` is `None`.
+pub fn client_event_stream(
+    current: Option<ServerInfo>,
+    updates: UnboundedReceiver<StatusUpdate>,
+    include_query: bool,
+) -> impl Stream<Item = (ServerInfo, Vec<ClientEvent>)> {
+    updates.scan(current, move |previous, update| {
+        let server_info = update.server_info.visible(include_query);
+        let events = previous
+            .as_ref()
+            .map(|previous| client_events(previous, &server_info))
+            .unwrap_or_default();
+        *previous = Some(server_info.clone());
+        future::ready(Some((server_info, events)))
+    })
+}
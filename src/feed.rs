@@ -0,0 +1,113 @@
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use quick_xml::escape::escape;
+
+use crate::{
+    events::{ClientEventKind, ServerEvent},
+    query::LoggedEvent,
+};
+
+/// `time` as RFC 3339 timestamp in UTC, as used by Atom.
+pub fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Title and summary of the feed entry for `event`.
+fn describe(event: &ServerEvent) -> (String, String) {
+    match event {
+        ServerEvent::Client(event) => {
+            let (action, preposition) = match event.event {
+                ClientEventKind::Join => ("joined", "in"),
+                ClientEventKind::Leave => ("left", "from"),
+            };
+            (
+                format!("{} {} the server", event.client.nickname, action),
+                format!(
+                    "{} {} the server {} channel {}",
+                    event.client.nickname, action, preposition, event.channel_name
+                ),
+            )
+        }
+        ServerEvent::ChannelCreated { id, name } => (
+            format!("Channel {} was created", name),
+            format!("Channel {} with id {} was created", name, id),
+        ),
+        ServerEvent::ChannelDeleted { id, name } => (
+            format!("Channel {} was deleted", name),
+            format!("Channel {} with id {} was deleted", name, id),
+        ),
+    }
+}
+
+/// Atom feed `id` titled `title` and served at `url`, with the `events` in the order given.
+pub fn render(id: &str, title: &str, url: &str, events: &[&LoggedEvent]) -> String {
+    let updated = events
+        .iter()
+        .map(|logged| logged.time)
+        .max()
+        .unwrap_or_else(SystemTime::now);
+
+    let mut feed = String::new();
+    let _ = write!(
+        feed,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <id>{}</id>\n\
+         <title>{}</title>\n\
+         <updated>{}</updated>\n\
+         <author><name>ts3status</name></author>\n\
+         <link rel=\"self\" href=\"{}\"/>\n",
+        escape(id),
+        escape(title),
+        rfc3339(updated),
+        escape(url)
+    );
+    for logged in events {
+        let (title, summary) = describe(&logged.event);
+        let _ = write!(
+            feed,
+            "<entry>\n\
+             <id>{}:{}:{}</id>\n\
+             <title>{}</title>\n\
+             <summary>{}</summary>\n\
+             <updated>{}</updated>\n\
+             </entry>\n",
+            escape(id),
+            logged.revision,
+            logged.index,
+            escape(&title),
+            escape(&summary),
+            rfc3339(logged.time)
+        );
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
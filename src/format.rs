@@ -0,0 +1,146 @@
+use actix_web::{
+    dev::{HttpResponseBuilder, Payload},
+    error::ErrorBadRequest,
+    http::header,
+    web::{self, Bytes},
+    Error, FromRequest, HttpRequest, HttpResponse,
+};
+use futures::future::{ready, Ready};
+use log::error;
+use serde::{Deserialize, Serialize};
+use tracing::info_span;
+use utoipa::ToSchema;
+
+/// Output format of the status endpoints, chosen with `?format=` or the `Accept` header.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Json,
+    Msgpack,
+    Xml,
+    Text,
+}
+
+/// Plain text rendering of a response for `?format=text`.
+pub trait ToText {
+    fn to_text(&self) -> String;
+}
+
+impl<T: ToText> ToText for Vec<T> {
+    fn to_text(&self) -> String {
+        self.iter()
+            .map(ToText::to_text)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Deserialize)]
+struct FormatQuery {
+    format: Option<Format>,
+}
+
+/// Root element of XML documents. Lists become repeated `<status>` elements, as XML documents
+/// can only have a single root.
+#[derive(Serialize)]
+struct XmlDocument<'a, T> {
+    status: &'a T,
+}
+
+impl Format {
+    /// The format requested by `Accept`, JSON unless another supported format is listed.
+    fn accepted(req: &HttpRequest) -> Self {
+        let accept = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+        let accepts = |mime: &str| {
+            accept
+                .split(',')
+                .any(|range| range.split(';').next().unwrap_or_default().trim() == mime)
+        };
+
+        if accepts("application/msgpack") || accepts("application/x-msgpack") {
+            Format::Msgpack
+        } else if accepts("application/xml") || accepts("text/xml") {
+            Format::Xml
+        } else if accepts("text/plain") {
+            Format::Text
+        } else {
+            Format::Json
+        }
+    }
+
+    /// MIME type of response bodies in this format.
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Msgpack => "application/msgpack",
+            Format::Xml => "application/xml; charset=utf-8",
+            Format::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// Serialize `value` in this format within its own tracing span.
+    pub fn render<T: Serialize + ToText>(self, value: &T) -> Result<Bytes, String> {
+        let _span = info_span!("serialize", format = ?self).entered();
+        match self {
+            Format::Json => serde_json::to_vec(value)
+                .map(Bytes::from)
+                .map_err(|e| format!("Can not serialize JSON response: {}", e)),
+            Format::Msgpack => rmp_serde::to_vec_named(value)
+                .map(Bytes::from)
+                .map_err(|e| format!("Can not serialize MessagePack response: {}", e)),
+            Format::Xml => {
+                quick_xml::se::to_string_with_root("ts3status", &XmlDocument { status: value })
+                    .map(|body| {
+                        Bytes::from(format!(
+                            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}",
+                            body
+                        ))
+                    })
+                    .map_err(|e| format!("Can not serialize XML response: {}", e))
+            }
+            Format::Text => Ok(Bytes::from(value.to_text())),
+        }
+    }
+
+    /// Response with `body`, rendered in this format.
+    pub fn body(self, mut builder: HttpResponseBuilder, body: Bytes) -> HttpResponse {
+        builder
+            .header(header::VARY, "Accept")
+            .content_type(self.content_type())
+            .body(body)
+    }
+
+    /// Serialize `value` in this format as response body within its own tracing span.
+    pub fn response<T: Serialize + ToText>(
+        self,
+        builder: HttpResponseBuilder,
+        value: &T,
+    ) -> HttpResponse {
+        match self.render(value) {
+            Ok(body) => self.body(builder, body),
+            Err(e) => {
+                error!("{}", e);
+                HttpResponse::InternalServerError().finish()
+            }
+        }
+    }
+}
+
+impl FromRequest for Format {
+    type Error = Error;
+    type Future = Ready<Result<Self, Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            match web::Query::<FormatQuery>::from_query(req.query_string()) {
+                Ok(query) => Ok(query.format.unwrap_or_else(|| Format::accepted(req))),
+                Err(e) => Err(ErrorBadRequest(e)),
+            },
+        )
+    }
+}
@@ -0,0 +1,75 @@
+use actix_web::web;
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Error, Object, Result};
+
+use crate::{
+    auth::ApiKey,
+    query::{self, ServerDetails, ServerInfo},
+    State,
+};
+
+pub type Schema = async_graphql::Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub struct QueryRoot;
+
+/// The configured TS3 host `name`, or the default one, and its virtual server `id`, or the first
+/// one.
+fn server(state: &State, name: Option<String>, id: Option<u64>) -> Result<(&query::Server, u64)> {
+    let name = name.unwrap_or_else(|| state.cfg.default_server().name.clone());
+    let server = state
+        .servers
+        .get(&name)
+        .ok_or_else(|| Error::new(format!("Unknown server {}", name)))?;
+    let id = id.unwrap_or(server.cfg.ts3_server_ids[0]);
+    Ok((server, id))
+}
+
+#[Object]
+impl QueryRoot {
+    /// Status of the virtual server `id` of the TS3 host `name`, the first virtual server of the
+    /// default host if unset.
+    async fn server(
+        &self,
+        ctx: &Context<'_>,
+        name: Option<String>,
+        id: Option<u64>,
+        include_query: Option<bool>,
+    ) -> Result<ServerInfo> {
+        let state = ctx.data::<web::Data<State>>()?;
+        let (server, id) = server(state, name, id)?;
+        crate::fetch_server(&state.cfg, server, id, include_query)
+            .await
+            .ok_or_else(|| Error::new(format!("Unknown server id {}", id)))?
+            .map_err(|e| Error::new(e.message))
+    }
+
+    /// Details of the virtual server `id` of the TS3 host `name`, the first virtual server of the
+    /// default host if unset. Requires an API key if any are configured.
+    async fn details(
+        &self,
+        ctx: &Context<'_>,
+        name: Option<String>,
+        id: Option<u64>,
+    ) -> Result<ServerDetails> {
+        let state = ctx.data::<web::Data<State>>()?;
+        ctx.data::<ApiKey>()
+            .map_err(|_| Error::new("Missing or invalid API key"))?;
+        let (server, id) = server(state, name, id)?;
+        let cache = server
+            .caches
+            .get(&id)
+            .ok_or_else(|| Error::new(format!("Unknown server id {}", id)))?;
+        query::fetch_details(&state.cfg, server, id, cache)
+            .await
+            .map_err(|e| Error::new(e.message))
+    }
+
+    /// Names of the configured TS3 hosts.
+    async fn servers(&self, ctx: &Context<'_>) -> Result<Vec<String>> {
+        let state = ctx.data::<web::Data<State>>()?;
+        Ok(state.servers.keys().cloned().collect())
+    }
+}
+
+pub fn schema() -> Schema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription).finish()
+}
@@ -0,0 +1,581 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{error::BlockingError, rt::time, web};
+use futures::{channel::mpsc::UnboundedReceiver, StreamExt};
+use log::{error, info};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    events::{client_events, ClientEventKind},
+    privacy,
+    query::{Client, ServerInfo, StatusUpdate},
+};
+
+// Delete samples past the retention period this often
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Entries of a client count history unless a resolution is requested, 5 minutes apart for a day
+pub const DEFAULT_ENTRIES: u64 = 288;
+
+/// Entries of a client count history at most
+pub const MAX_ENTRIES: u64 = 10_000;
+
+// Wait this long for other connections to release the database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS client_counts (
+    server TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    clients INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS client_counts_time ON client_counts (server, server_id, time);
+CREATE TABLE IF NOT EXISTS channel_counts (
+    server TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    clients INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS channel_counts_time ON channel_counts (server, server_id, time);
+CREATE TABLE IF NOT EXISTS sessions (
+    server TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    client_id INTEGER NOT NULL,
+    unique_id TEXT NOT NULL,
+    nickname TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    left_at INTEGER
+);
+CREATE INDEX IF NOT EXISTS sessions_joined ON sessions (server, server_id, joined_at);
+CREATE TABLE IF NOT EXISTS settings (
+    name TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// Database the client counts are recorded in.
+#[derive(Clone, Debug, Default)]
+pub struct HistoryOptions {
+    /// Path of the SQLite database, created if missing. History is disabled if unset.
+    pub database: Option<PathBuf>,
+    /// Days samples are kept, 0 to keep them forever
+    pub retention_days: u32,
+}
+
+/// A client that joined a virtual server.
+struct Session {
+    client_id: u16,
+    unique_id: String,
+    nickname: String,
+}
+
+/// Client counts of a virtual server at one point in time, and the clients that joined and left
+/// since the previous sample.
+struct Sample {
+    /// Unix timestamp of the update
+    time: i64,
+    clients: usize,
+    /// Channels with clients in them and their number of clients, not counting subchannels
+    channels: Vec<(u64, usize)>,
+    /// Whether the client counts are recorded. They are only sampled on refreshes, so the
+    /// averages aren't skewed by clients joining and leaving in bursts.
+    counted: bool,
+    /// Whether this is the first sample since the service started. Sessions still open from
+    /// before are closed then and every client online starts a new one.
+    first: bool,
+    joined: Vec<Session>,
+    /// Ids of the clients that left
+    left: Vec<u16>,
+}
+
+/// Client counts of a channel during a period of time.
+#[derive(Serialize, ToSchema)]
+pub struct ChannelCounts {
+    pub id: u64,
+    /// Average number of clients in the channel, not counting subchannels
+    pub average: f64,
+    pub max: u32,
+}
+
+/// Client counts of a virtual server during a period of time.
+#[derive(Serialize, ToSchema)]
+pub struct ClientCounts {
+    /// Unix timestamp of the start of the period
+    pub time: i64,
+    /// Average number of clients online
+    pub average: f64,
+    pub max: u32,
+    /// Channels that had clients in them, only if requested
+    pub channels: Option<Vec<ChannelCounts>>,
+}
+
+/// The most clients online at once during a period.
+#[derive(Serialize, ToSchema)]
+pub struct Peak {
+    pub clients: u32,
+    /// Unix timestamp of when the peak was first reached
+    pub time: i64,
+}
+
+/// The channel with the most clients on average during a period.
+#[derive(Serialize, ToSchema)]
+pub struct BusiestChannel {
+    pub id: u64,
+    /// Name of the channel, `None` if it doesn't exist anymore
+    pub name: Option<String>,
+    /// Average number of clients in the channel, not counting subchannels
+    pub average: f64,
+}
+
+/// Statistics of a virtual server during a period, `None` if nothing was recorded.
+#[derive(Serialize, ToSchema)]
+pub struct PeriodStats {
+    pub peak: Option<Peak>,
+    /// Average number of clients online
+    pub average: Option<f64>,
+    pub busiest_channel: Option<BusiestChannel>,
+}
+
+/// Statistics of the last day, the last week and the whole history of a virtual server.
+#[derive(Serialize, ToSchema)]
+pub struct Stats {
+    pub day: PeriodStats,
+    pub week: PeriodStats,
+    pub all_time: PeriodStats,
+}
+
+impl Stats {
+    /// Fill in the names of the busiest channels from `name`, which looks up a channel by id.
+    pub fn name_channels<F: Fn(u64) -> Option<String>>(&mut self, name: F) {
+        for period in [&mut self.day, &mut self.week, &mut self.all_time] {
+            if let Some(channel) = period.busiest_channel.as_mut() {
+                channel.name = name(channel.id);
+            }
+        }
+    }
+}
+
+/// Statistics of the virtual server `server_id` of the TS3 host `server` since the Unix timestamp
+/// `since`.
+fn period_stats(
+    connection: &Connection,
+    server: &str,
+    server_id: u64,
+    since: i64,
+) -> rusqlite::Result<PeriodStats> {
+    let peak = connection
+        .prepare_cached(
+            "SELECT clients, time FROM client_counts
+             WHERE server = ?1 AND server_id = ?2 AND time >= ?3
+             ORDER BY clients DESC, time ASC LIMIT 1",
+        )?
+        .query_map(params![server, server_id as i64, since], |row| {
+            Ok(Peak {
+                clients: row.get(0)?,
+                time: row.get(1)?,
+            })
+        })?
+        .next()
+        .transpose()?;
+    let (samples, average): (i64, Option<f64>) = connection
+        .prepare_cached(
+            "SELECT COUNT(*), AVG(clients) FROM client_counts
+             WHERE server = ?1 AND server_id = ?2 AND time >= ?3",
+        )?
+        .query_row(params![server, server_id as i64, since], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    // Channels without clients aren't recorded, so the average is taken over every sample
+    let busiest_channel = connection
+        .prepare_cached(
+            "SELECT channel_id, SUM(clients) AS total FROM channel_counts
+             WHERE server = ?1 AND server_id = ?2 AND time >= ?3
+             GROUP BY channel_id ORDER BY total DESC, channel_id ASC LIMIT 1",
+        )?
+        .query_map(params![server, server_id as i64, since], |row| {
+            Ok(BusiestChannel {
+                id: row.get(0)?,
+                name: None,
+                average: row.get::<_, i64>(1)? as f64 / samples.max(1) as f64,
+            })
+        })?
+        .next()
+        .transpose()?;
+    Ok(PeriodStats {
+        peak,
+        average,
+        busiest_channel,
+    })
+}
+
+/// Cumulative online time of a client during a period.
+#[derive(Serialize, ToSchema)]
+pub struct OnlineTime {
+    /// The most recent nickname of the client
+    pub nickname: String,
+    pub online_seconds: i64,
+    /// Number of times the client connected
+    pub sessions: u32,
+}
+
+/// Connection to the history database, shared by every recording task.
+#[derive(Clone)]
+pub struct History {
+    connection: Arc<Mutex<Connection>>,
+    retention_days: u32,
+}
+
+impl History {
+    /// Open the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path, retention_days: u32) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.busy_timeout(BUSY_TIMEOUT)?;
+        // Readers don't block the recording tasks in WAL mode
+        connection.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            retention_days,
+        })
+    }
+
+    /// The secret hashed nicknames are keyed with, generated when it is first needed so the hashes
+    /// stay the same across restarts. This blocks and is meant to be called on startup.
+    pub fn privacy_secret(&self) -> rusqlite::Result<String> {
+        let connection = self.connection.lock().expect("can't lock history database");
+        connection.execute(
+            "INSERT OR IGNORE INTO settings (name, value) VALUES ('privacy_secret', ?1)",
+            [privacy::random_secret()],
+        )?;
+        connection.query_row(
+            "SELECT value FROM settings WHERE name = 'privacy_secret'",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Run `f` with the database connection on the blocking thread pool.
+    pub async fn with_connection<T, F>(&self, f: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        web::block(move || f(&mut connection.lock().expect("can't lock history database")))
+            .await
+            .map_err(|e| match e {
+                BlockingError::Error(e) => e.to_string(),
+                BlockingError::Canceled => "History query was canceled".to_string(),
+            })
+    }
+
+    async fn insert(&self, server: String, server_id: u64, sample: Sample) -> Result<(), String> {
+        self.with_connection(move |connection| {
+            let transaction = connection.transaction()?;
+            if sample.counted {
+                transaction.execute(
+                    "INSERT INTO client_counts (server, server_id, time, clients) VALUES (?1, ?2, ?3, ?4)",
+                    params![server, server_id as i64, sample.time, sample.clients as i64],
+                )?;
+                let mut insert = transaction.prepare_cached(
+                    "INSERT INTO channel_counts (server, server_id, time, channel_id, clients)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                )?;
+                for (channel_id, clients) in sample.channels {
+                    insert.execute(params![
+                        server,
+                        server_id as i64,
+                        sample.time,
+                        channel_id as i64,
+                        clients as i64
+                    ])?;
+                }
+            }
+
+            if sample.first {
+                // Sessions interrupted by a restart end with the last sample before it
+                transaction.execute(
+                    "UPDATE sessions SET left_at = MAX(joined_at, COALESCE(
+                         (SELECT MAX(time) FROM client_counts
+                          WHERE server = ?1 AND server_id = ?2 AND time < ?3),
+                         joined_at))
+                     WHERE server = ?1 AND server_id = ?2 AND left_at IS NULL",
+                    params![server, server_id as i64, sample.time],
+                )?;
+            }
+            {
+                let mut leave = transaction.prepare_cached(
+                    "UPDATE sessions SET left_at = ?3
+                     WHERE server = ?1 AND server_id = ?2 AND client_id = ?4 AND left_at IS NULL",
+                )?;
+                for client_id in sample.left {
+                    leave.execute(params![server, server_id as i64, sample.time, client_id])?;
+                }
+                let mut join = transaction.prepare_cached(
+                    "INSERT INTO sessions
+                     (server, server_id, client_id, unique_id, nickname, joined_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for session in sample.joined {
+                    join.execute(params![
+                        server,
+                        server_id as i64,
+                        session.client_id,
+                        session.unique_id,
+                        session.nickname,
+                        sample.time
+                    ])?;
+                }
+            }
+            transaction.commit()
+        })
+        .await
+    }
+
+    /// Client counts of the virtual server `server_id` of the TS3 host `server` between the Unix
+    /// timestamps `from` and `to`, in periods of `resolution` seconds. Periods without samples,
+    /// such as while the TS3 host was unreachable, are left out. Counts per channel are included
+    /// if `channels` is set.
+    pub async fn client_counts(
+        &self,
+        server: String,
+        server_id: u64,
+        from: i64,
+        to: i64,
+        resolution: u64,
+        channels: bool,
+    ) -> Result<Vec<ClientCounts>, String> {
+        let resolution = resolution as i64;
+        self.with_connection(move |connection| {
+            let mut channel_counts: HashMap<i64, Vec<(u64, i64, u32)>> = HashMap::new();
+            if channels {
+                let mut statement = connection.prepare_cached(
+                    "SELECT time / ?4 * ?4 AS period, channel_id, SUM(clients), MAX(clients)
+                     FROM channel_counts
+                     WHERE server = ?1 AND server_id = ?2 AND time >= ?3 AND time < ?5
+                     GROUP BY period, channel_id ORDER BY period, channel_id",
+                )?;
+                let rows = statement.query_map(
+                    params![server, server_id as i64, from, resolution, to],
+                    |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?))),
+                )?;
+                for row in rows {
+                    let (period, counts) = row?;
+                    channel_counts.entry(period).or_default().push(counts);
+                }
+            }
+
+            let mut statement = connection.prepare_cached(
+                "SELECT time / ?4 * ?4 AS period, COUNT(*), SUM(clients), MAX(clients)
+                 FROM client_counts
+                 WHERE server = ?1 AND server_id = ?2 AND time >= ?3 AND time < ?5
+                 GROUP BY period ORDER BY period",
+            )?;
+            let rows = statement.query_map(
+                params![server, server_id as i64, from, resolution, to],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, u32>(3)?,
+                    ))
+                },
+            )?;
+            rows.map(|row| {
+                let (time, samples, sum, max) = row?;
+                // Channels without clients aren't recorded, they count as empty in the average
+                let channels = channels.then(|| {
+                    channel_counts
+                        .remove(&time)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|(id, sum, max)| ChannelCounts {
+                            id,
+                            average: sum as f64 / samples as f64,
+                            max,
+                        })
+                        .collect()
+                });
+                Ok(ClientCounts {
+                    time,
+                    average: sum as f64 / samples as f64,
+                    max,
+                    channels,
+                })
+            })
+            .collect()
+        })
+        .await
+    }
+
+    /// Statistics of the virtual server `server_id` of the TS3 host `server`, without the names
+    /// of the busiest channels.
+    pub async fn stats(&self, server: String, server_id: u64) -> Result<Stats, String> {
+        let now = now();
+        self.with_connection(move |connection| {
+            Ok(Stats {
+                day: period_stats(connection, &server, server_id, now - 86400)?,
+                week: period_stats(connection, &server, server_id, now - 7 * 86400)?,
+                all_time: period_stats(connection, &server, server_id, i64::MIN)?,
+            })
+        })
+        .await
+    }
+
+    /// The `limit` clients of the virtual server `server_id` of the TS3 host `server` that were
+    /// online the longest between the Unix timestamps `from` and `to`, longest first.
+    pub async fn leaderboard(
+        &self,
+        server: String,
+        server_id: u64,
+        from: i64,
+        to: i64,
+        limit: u32,
+    ) -> Result<Vec<OnlineTime>, String> {
+        let now = now();
+        self.with_connection(move |connection| {
+            // With a single MAX() aggregate, SQLite takes the nickname from the latest session
+            let mut statement = connection.prepare_cached(
+                "SELECT nickname, MAX(joined_at),
+                     SUM(MIN(COALESCE(left_at, ?3), ?5) - MAX(joined_at, ?4)) AS online, COUNT(*)
+                 FROM sessions
+                 WHERE server = ?1 AND server_id = ?2 AND joined_at < ?5
+                     AND COALESCE(left_at, ?3) > ?4
+                 GROUP BY unique_id ORDER BY online DESC, unique_id LIMIT ?6",
+            )?;
+            let rows = statement.query_map(
+                params![server, server_id as i64, now, from, to, limit],
+                |row| {
+                    Ok(OnlineTime {
+                        nickname: row.get(0)?,
+                        online_seconds: row.get(2)?,
+                        sessions: row.get(3)?,
+                    })
+                },
+            )?;
+            rows.collect()
+        })
+        .await
+    }
+
+    /// Delete the samples and sessions older than the retention period.
+    async fn prune(&self) -> Result<usize, String> {
+        let cutoff = now() - i64::from(self.retention_days) * 86400;
+        self.with_connection(move |connection| {
+            let mut deleted =
+                connection.execute("DELETE FROM client_counts WHERE time < ?1", [cutoff])?;
+            deleted +=
+                connection.execute("DELETE FROM channel_counts WHERE time < ?1", [cutoff])?;
+            deleted += connection.execute("DELETE FROM sessions WHERE left_at < ?1", [cutoff])?;
+            Ok(deleted)
+        })
+        .await
+    }
+}
+
+/// Start and length in seconds of the time range from `from` to `to`, starting `default_span`
+/// seconds before `to` unless `from` is given. `None` if it doesn't start before `to` or its
+/// length overflows.
+pub fn time_range(from: Option<i64>, to: i64, default_span: i64) -> Option<(i64, u64)> {
+    let from = from.unwrap_or_else(|| to.saturating_sub(default_span));
+    let span = to.checked_sub(from).filter(|&span| span > 0)?;
+    Some((from, span as u64))
+}
+
+/// The current Unix timestamp.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Record the client counts of the virtual server `server_id` of the TS3 host `server` after every
+/// refresh, and the sessions of its clients after every update. ServerQuery clients are only
+/// counted if `include_query` is set.
+pub async fn record(
+    history: History,
+    server: String,
+    server_id: u64,
+    mut updates: UnboundedReceiver<StatusUpdate>,
+    include_query: bool,
+) {
+    let mut previous: Option<ServerInfo> = None;
+    while let Some(update) = updates.next().await {
+        let server_info = update.server_info.visible(include_query);
+        let session = |client: &Client| Session {
+            client_id: client.id,
+            unique_id: client.unique_id.clone(),
+            nickname: client.nickname.clone(),
+        };
+        // Clients online before the first sample only count from then on, they might have been
+        // counted before a restart already
+        let (joined, left) = match &previous {
+            Some(previous) => {
+                let mut joined = Vec::new();
+                let mut left = Vec::new();
+                for event in client_events(previous, &server_info) {
+                    match event.event {
+                        ClientEventKind::Join => joined.push(session(&event.client)),
+                        ClientEventKind::Leave => left.push(event.client.id),
+                    }
+                }
+                (joined, left)
+            }
+            None => (
+                server_info
+                    .clients()
+                    .into_iter()
+                    .map(|(_, client)| session(client))
+                    .collect(),
+                Vec::new(),
+            ),
+        };
+        let sample = Sample {
+            time: now(),
+            clients: server_info.clients().len(),
+            // Empty channels are left out, they make up most of the samples otherwise
+            channels: server_info
+                .channel_counts()
+                .into_iter()
+                .filter(|&(_, clients)| clients > 0)
+                .collect(),
+            counted: update.refresh,
+            first: previous.is_none(),
+            joined,
+            left,
+        };
+        previous = Some(server_info);
+        if let Err(e) = history.insert(server.clone(), server_id, sample).await {
+            error!(
+                "Can not record history of {} server {}: {}",
+                server, server_id, e
+            );
+        }
+    }
+}
+
+/// Delete samples past the retention period of `history` every `PRUNE_INTERVAL`.
+pub async fn prune_periodically(history: History) {
+    if history.retention_days == 0 {
+        return;
+    }
+    loop {
+        match history.prune().await {
+            Ok(0) => {}
+            Ok(deleted) => info!("Deleted {} expired history samples", deleted),
+            Err(e) => error!("Can not delete expired history samples: {}", e),
+        }
+        time::delay_for(PRUNE_INTERVAL).await;
+    }
+}
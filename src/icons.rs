@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::TcpStream,
+    sync::atomic::{AtomicU16, Ordering},
+    time::{Duration, Instant},
+};
+
+use actix_web::{error::BlockingError, web};
+use log::{info, trace};
+use ts3_query::*;
+
+use crate::{backend::Commands, config::ServerConfig, query::Server, resolve};
+
+// Icons are tiny, anything larger is most likely not an icon
+const MAX_ICON_SIZE: usize = 1024 * 1024;
+
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Cache icons for a week unless configured otherwise
+pub const ICON_CACHE_LIFETIME: u64 = 7 * 24 * 60 * 60;
+
+/// Client side id of file transfers, only needs to be unique among running transfers
+static TRANSFER_ID: AtomicU16 = AtomicU16::new(1);
+
+/// A file transfer announced to the TS3 host that is ready to be downloaded.
+pub struct Transfer {
+    key: String,
+    port: u16,
+    size: usize,
+}
+
+/// Ask the TS3 host for the icon `icon_id` of the selected virtual server, `None` if it doesn't
+/// exist.
+pub fn init_download(
+    client: &mut impl Commands,
+    icon_id: u64,
+) -> Result<Option<Transfer>, Ts3Error> {
+    let id = TRANSFER_ID.fetch_add(1, Ordering::Relaxed);
+    let response = match client.run(&format!(
+        "ftinitdownload clientftfid={} name=\\/icon_{} cid=0 cpw= seekpos=0",
+        id, icon_id
+    )) {
+        Ok(response) => response.into_iter().next().unwrap_or_default(),
+        Err(e) if e.is_error_response() => {
+            trace!("Icon {} not available: {:?}", icon_id, e);
+            return Ok(None);
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(parse_transfer(&response))
+}
+
+fn parse_transfer(response: &HashMap<String, Option<String>>) -> Option<Transfer> {
+    let field = |key: &str| response.get(key)?.as_ref();
+    Some(Transfer {
+        key: field("ftkey")?.clone(),
+        port: field("port")?.parse().ok()?,
+        size: field("size")?.parse().ok()?,
+    })
+}
+
+/// Download a file transfer from the file transfer port of `host`.
+fn download(server: &ServerConfig, transfer: &Transfer) -> std::io::Result<Vec<u8>> {
+    let host = resolve::ts3_host(server)?;
+    let mut stream = TcpStream::connect((&*host, transfer.port))?;
+    stream.set_read_timeout(Some(TRANSFER_TIMEOUT))?;
+    stream.set_write_timeout(Some(TRANSFER_TIMEOUT))?;
+    stream.write_all(transfer.key.as_bytes())?;
+
+    let mut data = vec![0; transfer.size];
+    stream.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// The icon `icon_id` of the virtual server `server_id`, downloaded through file transfer on first
+/// use and cached for `lifetime`. Icon ids are checksums of their content, so icons rarely change.
+pub async fn fetch_icon(
+    server: &Server,
+    server_id: u64,
+    icon_id: u64,
+    lifetime: Duration,
+) -> Result<Option<web::Bytes>, String> {
+    if let Some((_, icon)) = server
+        .icons
+        .read()
+        .expect("can't readlock icons")
+        .get(&(server_id, icon_id))
+        .filter(|(downloaded, _)| downloaded.elapsed() <= lifetime)
+    {
+        return Ok(Some(icon.clone()));
+    }
+
+    info!(
+        "Downloading icon {} of {} server {}",
+        icon_id, server.cfg.name, server_id
+    );
+    let backend = server.backend.clone();
+    let server_cfg = server.cfg.clone();
+    let icon = web::block(move || {
+        let transfer = backend
+            .lock()
+            .expect("can't lock connection")
+            .init_download(server_id, icon_id)
+            .map_err(|e| format!("{:?}", e))?;
+
+        match transfer {
+            Some(transfer) if transfer.size > MAX_ICON_SIZE => Err(format!(
+                "Icon {} is too large ({} bytes)",
+                icon_id, transfer.size
+            )),
+            Some(transfer) => download(&server_cfg, &transfer)
+                .map(|data| Some(web::Bytes::from(data)))
+                .map_err(|e| format!("Can not download icon {}: {}", icon_id, e)),
+            None => Ok(None),
+        }
+    })
+    .await
+    .map_err(|e| match e {
+        BlockingError::Error(e) => e,
+        BlockingError::Canceled => "Icon download was canceled".to_string(),
+    })?;
+
+    if let Some(icon) = icon.as_ref() {
+        server
+            .icons
+            .write()
+            .expect("can't writelock icons")
+            .insert((server_id, icon_id), (Instant::now(), icon.clone()));
+    }
+    Ok(icon)
+}
+
+/// Guess the content type of an icon from its first bytes.
+pub fn content_type(icon: &[u8]) -> &'static str {
+    if icon.starts_with(b"\x89PNG") {
+        "image/png"
+    } else if icon.starts_with(b"GIF8") {
+        "image/gif"
+    } else if icon.starts_with(b"\xff\xd8") {
+        "image/jpeg"
+    } else if icon.starts_with(b"<svg") || icon.starts_with(b"<?xml") {
+        "image/svg+xml"
+    } else {
+        "application/octet-stream"
+    }
+}
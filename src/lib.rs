@@ -0,0 +1,2489 @@
+//! Status of TeamSpeak 3 servers, queried over ServerQuery and served over HTTP by the
+//! `ts3status` binary.
+//!
+//! The query side can be embedded in other applications: `config::Config` holds the settings,
+//! `query::Server` a TS3 host with its query connection and status caches, and
+//! `query::query_blocking` queries the status of one of its virtual servers. The async functions
+//! of `query` cache the status, but need the actix runtime the binary runs on.
+//!
+//! ```no_run
+//! use ts3status::{config::Config, query::{self, Server}};
+//!
+//! let cfg = Config::load(None, None, Vec::new())?;
+//! let server = Server::new(cfg.default_server().clone(), &cfg);
+//! let (status, _details) = query::query_blocking(&server, server.cfg.ts3_server_ids[0])?;
+//! println!("{} clients online on {}", status.clients().len(), status.name);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+//!
+//! `backend::StatusBackend` abstracts where the status comes from, the `mock` feature adds an
+//! in-memory backend for testing without a TS3 host.
+
+use access::AccessList;
+use actix_web::{
+    dev::HttpResponseBuilder,
+    error::BlockingError,
+    get,
+    http::{
+        header::{self, CacheControl, CacheDirective, LastModified},
+        StatusCode,
+    },
+    middleware::Condition,
+    post, rt,
+    web::{self, Bytes},
+    App, HttpRequest, HttpResponse, HttpServer, Result,
+};
+use actix_web_actors::ws;
+use auth::{AdminKey, ApiKey, BasicAuth};
+use banner::{BannerOptions, Theme};
+use clap::CommandFactory;
+use cli::{Cli, Command, OnceFormat};
+use config::{Config, ConfigError};
+use delta::Delta;
+use error::{ErrorCode, QueryError};
+use events::ServerEvent;
+use format::{Format, ToText};
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver},
+    future::{self, AbortHandle, Future, FutureExt},
+};
+use history::{ClientCounts, History, OnlineTime, Stats};
+use log::{debug, error, info, warn};
+use privacy::PrivacyMode;
+use query::{
+    ChannelNode, LoggedEvent, Rendered, Server, ServerDetails, ServerInfo, SharedCache,
+    StatusCache, StatusUpdate,
+};
+use ratelimit::{ClientIps, RateLimiter};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    process,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tracing::info_span;
+use utoipa::{IntoParams, ToSchema};
+use view::{ChannelClient, PageQuery, StatusView, ViewQuery};
+use websocket::StatusSocket;
+
+mod access;
+mod auth;
+pub mod backend;
+mod banner;
+mod breaker;
+mod check;
+pub mod cli;
+pub mod compat;
+mod conditional;
+pub mod config;
+mod cors;
+mod countries;
+mod csv_export;
+mod delta;
+mod discord;
+pub mod error;
+pub mod events;
+mod feed;
+mod format;
+mod graphql;
+mod history;
+mod icons;
+mod logging;
+mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod mqtt;
+mod notify;
+mod openapi;
+pub mod privacy;
+pub mod query;
+mod ratelimit;
+mod resolve;
+pub mod spacer;
+mod sse;
+mod ssh;
+#[cfg(unix)]
+mod systemd;
+mod telemetry;
+mod text;
+mod tls;
+mod validate;
+mod view;
+mod webhooks;
+mod webquery;
+mod websocket;
+
+/// Status page served at `/viewer`, it renders the channel tree from the JSON status
+const VIEWER_HTML: &str = include_str!("viewer.html");
+
+#[derive(Clone)]
+pub(crate) struct State {
+    cfg: Config,
+    servers: HashMap<String, Server>,
+    graphql: graphql::Schema,
+    /// Recorded client counts, `None` unless a history database is configured
+    history: Option<History>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct JsonResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    /// Cause of `error` if the TS3 host couldn't be queried
+    pub error_code: Option<ErrorCode>,
+    pub server_info: Option<StatusView>,
+    /// Whether the status outlived the cache lifetime and is being refreshed, or is the last known
+    /// status because the TS3 host couldn't be queried
+    pub stale: bool,
+    /// Seconds since the served status was queried, `None` without a status
+    pub cache_age_seconds: Option<u64>,
+    /// When the served status was queried, as RFC 3339 timestamp
+    pub last_updated: Option<String>,
+    /// Revision of the status, increases whenever it changes. Pass it to `/delta` to get the
+    /// changes since.
+    pub revision: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ServerResponse {
+    pub id: u64,
+    #[serde(flatten)]
+    pub response: JsonResponse,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ChannelResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    pub channel: Option<ChannelNode>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ClientsResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    /// Number of clients on the server, regardless of `offset` and `limit`
+    pub total: usize,
+    pub clients: Option<Vec<ChannelClient>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DeltaResponse {
+    pub success: bool,
+    /// Error of the last refresh, the changes are up to the last known status then
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    /// Revision of the current status, pass it as `since` to get the following changes
+    pub revision: u64,
+    /// Whether `since` is unknown or too old, the changes are relative to an empty server then
+    pub reset: bool,
+    #[serde(flatten)]
+    pub delta: Delta,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct HistoryResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub server_id: u64,
+    /// Unix timestamps of the covered time range
+    pub from: i64,
+    pub to: i64,
+    /// Seconds covered by each entry of `clients`
+    pub resolution: u64,
+    pub clients: Vec<ClientCounts>,
+}
+
+/// Client count of `/count`, for integrations such as stream overlays that need nothing else.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CountResponse {
+    /// Clients online, without ServerQuery clients unless configured otherwise
+    pub online: usize,
+    pub max: u32,
+}
+
+/// Badge of `/shield.json` in the endpoint schema of shields.io, which renders it as image.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ShieldResponse {
+    /// Version of the schema, always 1
+    pub schema_version: u8,
+    pub label: String,
+    /// `<online>/<max> online`, or `offline` if the TS3 host can't be queried
+    pub message: String,
+    pub color: String,
+    pub is_error: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct StatsResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub server_id: u64,
+    #[serde(flatten)]
+    pub stats: Stats,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct LeaderboardResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub server_id: u64,
+    /// Unix timestamps of the covered time range
+    pub from: i64,
+    pub to: i64,
+    /// Clients that were online the longest, longest first
+    pub clients: Vec<OnlineTime>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct DetailsResponse {
+    pub success: bool,
+    pub error: Option<String>,
+    pub error_code: Option<ErrorCode>,
+    pub server_details: Option<ServerDetails>,
+}
+
+/// Readiness of the status cache of a single virtual server.
+#[derive(Serialize, ToSchema)]
+pub(crate) struct CacheReadiness {
+    pub name: String,
+    pub id: u64,
+    pub ready: bool,
+    /// Error of the last refresh, if it failed
+    pub error: Option<String>,
+    /// Seconds since the last successful refresh, `None` if the last refresh failed
+    pub age_seconds: Option<u64>,
+    /// Whether queries of the TS3 host are stopped after consecutive failures
+    pub circuit_open: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ReadinessResponse {
+    /// Whether every virtual server is ready
+    pub ready: bool,
+    pub servers: Vec<CacheReadiness>,
+}
+
+/// Serialize `value` as JSON response body within its own tracing span.
+fn json_response<T: Serialize>(mut builder: HttpResponseBuilder, value: &T) -> HttpResponse {
+    let _span = info_span!("serialize").entered();
+    builder.json(value)
+}
+
+/// Builder for responses from the status cache of the virtual server `server_id`. `Last-Modified`
+/// and `Age` describe its last refresh, and `Cache-Control` allows caching until it is `lifetime`
+/// seconds old.
+fn cached_response(lifetime: u64, server: &Server, server_id: u64) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    let age = server
+        .caches
+        .get(&server_id)
+        .and_then(|cache| cache.load().age());
+    match age {
+        Some(age) => {
+            let max_age = lifetime.saturating_sub(age.as_secs());
+            builder
+                .set(LastModified((SystemTime::now() - age).into()))
+                .set(CacheControl(vec![
+                    CacheDirective::Public,
+                    CacheDirective::MaxAge(max_age as u32),
+                ]))
+                .header(header::AGE, age.as_secs());
+        }
+        // Failed refreshes are retried on the next request
+        None => {
+            builder.set(CacheControl(vec![CacheDirective::NoCache]));
+        }
+    }
+    builder
+}
+
+impl ToText for JsonResponse {
+    fn to_text(&self) -> String {
+        match (self.server_info.as_ref(), self.error.as_ref()) {
+            (Some(server_info), _) => text::status(server_info),
+            (None, Some(error)) => format!("Error: {}\n", error),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+impl ToText for ServerResponse {
+    fn to_text(&self) -> String {
+        self.response.to_text()
+    }
+}
+
+impl JsonResponse {
+    pub fn error(error: String) -> Self {
+        JsonResponse {
+            success: false,
+            error: Some(error),
+            error_code: None,
+            server_info: None,
+            stale: false,
+            cache_age_seconds: None,
+            last_updated: None,
+            revision: None,
+        }
+    }
+
+    fn not_found(error: String) -> HttpResponse {
+        HttpResponse::NotFound().json(JsonResponse::error(error))
+    }
+
+    /// HTTP status of this response. Stale ones still carry a status and are served as OK, failed
+    /// ones with the status of their cause.
+    fn http_status(&self) -> StatusCode {
+        match (&self.server_info, self.error_code) {
+            (None, Some(code)) => code.status(),
+            _ => StatusCode::OK,
+        }
+    }
+
+    /// Response for the failed query `e`, with the HTTP status of its cause.
+    fn query_error(e: QueryError) -> HttpResponse {
+        let builder = HttpResponse::build(e.code.status());
+        let response = JsonResponse {
+            error_code: Some(e.code),
+            ..JsonResponse::error(e.message)
+        };
+        json_response(builder, &response)
+    }
+}
+
+/// Fetch the status of the virtual server `server_id`, `None` if it isn't configured.
+/// ServerQuery clients are left out unless `include_query` or the configuration default says so.
+async fn fetch_server(
+    cfg: &Config,
+    server: &Server,
+    server_id: u64,
+    include_query: Option<bool>,
+) -> Option<Result<ServerInfo, QueryError>> {
+    let cache = server.caches.get(&server_id)?;
+    let mut result = query::fetch_status(cfg, server, server_id, cache).await;
+
+    if let Ok(server_info) = result.as_mut() {
+        strip_query_clients(server, include_query, server_info);
+    }
+
+    if let Err(e) = result.as_ref() {
+        error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+    }
+    Some(result)
+}
+
+/// Leave out ServerQuery clients unless `include_query` or the configuration default says so.
+fn strip_query_clients(server: &Server, include_query: Option<bool>, server_info: &mut ServerInfo) {
+    if !include_query.unwrap_or(server.client_options.include_query) {
+        server_info.remove_query_clients();
+    }
+}
+
+/// Status of the virtual server `server_id` for status responses. If the TS3 host can't be
+/// queried, the last known status is served as stale along with the error.
+async fn server_status(
+    cfg: &Config,
+    server: &Server,
+    server_id: u64,
+    view: &ViewQuery,
+    format: Format,
+) -> Option<(JsonResponse, String)> {
+    let cache = server.caches.get(&server_id)?;
+    if let Err(e) = query::refresh_if_expired(cfg, server, server_id, cache).await {
+        error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+    }
+    let cache = cache.load();
+    let response = status_response(cfg, server, &cache, view);
+    let etag = status_etag(&cache, &(format, format!("{:?}", view)), response.stale);
+    Some((response, etag))
+}
+
+/// Entity tag of status responses rendered from `cache` with `key`. Unlike the body, it doesn't
+/// change as the status ages, only when the status or error changes or it becomes stale.
+fn status_etag(cache: &StatusCache, key: &(Format, String), stale: bool) -> String {
+    conditional::entity_tag(&(cache.generation(), key, stale))
+}
+
+/// Response with the status in `cache`, or the last known one along with the error of the last
+/// refresh.
+fn status_response(
+    cfg: &Config,
+    server: &Server,
+    cache: &StatusCache,
+    view: &ViewQuery,
+) -> JsonResponse {
+    let error = cache.error.clone();
+    let (server_info, age) = match cache.last_good() {
+        Some((server_info, age)) => {
+            let mut server_info = server_info.clone();
+            strip_query_clients(server, view.include_query, &mut server_info);
+            (Some(server_info), Some(age))
+        }
+        None => (None, None),
+    };
+
+    let age_seconds = age.map(|age| age.as_secs());
+    JsonResponse {
+        success: error.is_none(),
+        stale: (error.is_some() && server_info.is_some())
+            || age_seconds.is_some_and(|age| age > cfg.cache_lifetime),
+        error_code: error.as_ref().map(|e| e.code),
+        error: error.map(|e| e.message),
+        revision: server_info.is_some().then_some(cache.revision),
+        server_info: server_info.map(|server_info| view::apply(view, server_info)),
+        cache_age_seconds: age_seconds,
+        last_updated: age.map(|age| feed::rfc3339(SystemTime::now() - age)),
+    }
+}
+
+/// Like `server_status`, but rendered in `format` along with its HTTP status. The body rendered
+/// for the same view is reused until the status changes or ages by a second, so busy status
+/// pages don't copy and serialize the status on every request.
+async fn rendered_status(
+    cfg: &Config,
+    server: &Server,
+    server_id: u64,
+    view: &ViewQuery,
+    format: Format,
+) -> Option<Rendered> {
+    let cache = server.caches.get(&server_id)?;
+    if let Err(e) = query::refresh_if_expired(cfg, server, server_id, cache).await {
+        error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+    }
+
+    let key = (format, format!("{:?}", view));
+    let snapshot = cache.load();
+    let age = snapshot.last_good().map(|(_, age)| age.as_secs());
+    if let Some(rendered) = snapshot.rendered(&key, age) {
+        return Some(rendered);
+    }
+    let response = status_response(cfg, server, &snapshot, view);
+    let etag = status_etag(&snapshot, &key, response.stale);
+    match format.render(&response) {
+        Ok(body) => {
+            let rendered = Rendered {
+                status: response.http_status(),
+                etag,
+                body,
+            };
+            cache.keep_rendered(&snapshot, key, age, rendered.clone());
+            Some(rendered)
+        }
+        Err(e) => {
+            error!("{}", e);
+            Some(Rendered {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                etag,
+                body: Bytes::new(),
+            })
+        }
+    }
+}
+
+/// Status of the first virtual server of the TS3 host `name`.
+async fn host_status(state: &State, name: &str, view: &ViewQuery, format: Format) -> HttpResponse {
+    match state.servers.get(name) {
+        Some(server) => {
+            let id = server.cfg.ts3_server_ids[0];
+            let rendered = rendered_status(&state.cfg, server, id, view, format)
+                .await
+                .expect("no cache for first server");
+            let mut builder = cached_response(state.cfg.cache_lifetime, server, id);
+            builder
+                .status(rendered.status)
+                .header(header::ETAG, rendered.etag);
+            format.body(builder, rendered.body)
+        }
+        None => JsonResponse::not_found(format!("Unknown server {}", name)),
+    }
+}
+
+/// Clients online and maximum clients of the virtual server `server_id`. The last known counts
+/// are returned if the TS3 host can't be queried.
+async fn client_counts(
+    cfg: &Config,
+    server: &Server,
+    server_id: u64,
+) -> Result<(usize, u32), QueryError> {
+    let cache = &server.caches[&server_id];
+    if let Err(e) = query::refresh_if_expired(cfg, server, server_id, cache).await {
+        error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+    }
+    let cache = cache.load();
+    match (cache.last_good(), &cache.error) {
+        (Some((server_info, _)), _) => {
+            let include_query = server.client_options.include_query;
+            let online = server_info
+                .clients()
+                .iter()
+                .filter(|(_, client)| include_query || !client.is_query)
+                .count();
+            Ok((online, server_info.max_clients))
+        }
+        (None, Some(e)) => Err(e.clone()),
+        (None, None) => Err(QueryError::new(
+            ErrorCode::ConnectFailed,
+            "Status not queried yet",
+        )),
+    }
+}
+
+/// Client count of the first virtual server of the TS3 host `name`.
+async fn host_count(state: &State, name: &str) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    match client_counts(&state.cfg, server, id).await {
+        Ok((online, max)) => json_response(
+            cached_response(state.cfg.cache_lifetime, server, id),
+            &CountResponse { online, max },
+        ),
+        Err(e) => JsonResponse::query_error(e),
+    }
+}
+
+/// shields.io badge with the client count of the first virtual server of the TS3 host `name`,
+/// labeled `label` or `teamspeak`. Failed queries are shown on the badge, so it is always served
+/// with `200 OK`.
+async fn host_shield(state: &State, name: &str, label: Option<&str>) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let (message, color, is_error) = match client_counts(&state.cfg, server, id).await {
+        Ok((0, max)) => (format!("0/{} online", max), "lightgrey", false),
+        Ok((online, max)) => (format!("{}/{} online", online, max), "brightgreen", false),
+        Err(_) => ("offline".to_string(), "red", true),
+    };
+    json_response(
+        cached_response(state.cfg.cache_lifetime, server, id),
+        &ShieldResponse {
+            schema_version: 1,
+            label: label.unwrap_or("teamspeak").to_string(),
+            message,
+            color: color.to_string(),
+            is_error,
+        },
+    )
+}
+
+/// Status of all virtual servers of the TS3 host `name`.
+async fn host_servers(state: &State, name: &str, view: &ViewQuery, format: Format) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let mut responses = Vec::new();
+    let mut etags = Vec::new();
+    for &id in &server.cfg.ts3_server_ids {
+        if let Some((response, etag)) = server_status(&state.cfg, server, id, view, format).await {
+            responses.push(ServerResponse { id, response });
+            etags.push(etag);
+        }
+    }
+
+    let mut builder = HttpResponse::Ok();
+    builder.header(header::ETAG, conditional::entity_tag(&etags));
+    format.response(builder, &responses)
+}
+
+/// Status of the virtual server `id` of the TS3 host `name`.
+async fn host_server(
+    state: &State,
+    name: &str,
+    id: u64,
+    view: &ViewQuery,
+    format: Format,
+) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    match rendered_status(&state.cfg, server, id, view, format).await {
+        Some(rendered) => {
+            let mut builder = cached_response(state.cfg.cache_lifetime, server, id);
+            builder
+                .status(rendered.status)
+                .header(header::ETAG, rendered.etag);
+            format.body(builder, rendered.body)
+        }
+        None => JsonResponse::not_found(format!("Unknown server id {}", id)),
+    }
+}
+
+/// Status of the channel `cid` on the first virtual server of the TS3 host `name`.
+async fn host_channel(state: &State, name: &str, cid: u64) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let result = fetch_server(&state.cfg, server, server.cfg.ts3_server_ids[0], None)
+        .await
+        .expect("no cache for first server");
+    match result {
+        Ok(server_info) => match server_info.channel(cid) {
+            Some(channel) => json_response(
+                cached_response(
+                    state.cfg.cache_lifetime,
+                    server,
+                    server.cfg.ts3_server_ids[0],
+                ),
+                &ChannelResponse {
+                    success: true,
+                    error: None,
+                    error_code: None,
+                    channel: Some(channel.clone()),
+                },
+            ),
+            None => json_response(
+                HttpResponse::NotFound(),
+                &ChannelResponse {
+                    success: false,
+                    error: Some(format!("Unknown channel {}", cid)),
+                    error_code: None,
+                    channel: None,
+                },
+            ),
+        },
+        Err(e) => json_response(
+            HttpResponse::build(e.code.status()),
+            &ChannelResponse {
+                success: false,
+                error_code: Some(e.code),
+                error: Some(e.message),
+                channel: None,
+            },
+        ),
+    }
+}
+
+/// Clients of the first virtual server of the TS3 host `name`.
+async fn host_clients(state: &State, name: &str, page: &PageQuery) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let result = fetch_server(
+        &state.cfg,
+        server,
+        server.cfg.ts3_server_ids[0],
+        page.include_query,
+    )
+    .await
+    .expect("no cache for first server");
+    let response = match result {
+        Ok(server_info) => {
+            let (total, clients) = view::clients(page, &server_info);
+            ClientsResponse {
+                success: true,
+                error: None,
+                error_code: None,
+                total,
+                clients: Some(clients),
+            }
+        }
+        Err(e) => {
+            return json_response(
+                HttpResponse::build(e.code.status()),
+                &ClientsResponse {
+                    success: false,
+                    error_code: Some(e.code),
+                    error: Some(e.message),
+                    total: 0,
+                    clients: None,
+                },
+            )
+        }
+    };
+    json_response(
+        cached_response(
+            state.cfg.cache_lifetime,
+            server,
+            server.cfg.ts3_server_ids[0],
+        ),
+        &response,
+    )
+}
+
+/// Clients of the first virtual server of the TS3 host `name` as CSV.
+async fn host_clients_csv(state: &State, name: &str, page: &PageQuery) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let result = fetch_server(&state.cfg, server, id, page.include_query)
+        .await
+        .expect("no cache for first server");
+    let server_info = match result {
+        Ok(server_info) => server_info,
+        Err(e) => return JsonResponse::query_error(e),
+    };
+
+    match csv_export::clients(page, &server_info) {
+        Ok(csv) => cached_response(state.cfg.cache_lifetime, server, id)
+            .content_type("text/csv; charset=utf-8")
+            .header(
+                "content-disposition",
+                "attachment; filename=\"clients.csv\"",
+            )
+            .body(csv),
+        Err(e) => {
+            error!("Can not export clients as CSV: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Changes of the first virtual server of the TS3 host `name` since the revision `since`, no
+/// content if there are none.
+async fn host_delta(state: &State, name: &str, query: &DeltaQuery) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let cache = &server.caches[&id];
+    if let Err(e) = query::fetch_status(&state.cfg, server, id, cache).await {
+        error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+    }
+    let (revision, error, previous, mut current) = {
+        let cache = cache.load();
+        match cache.last_good() {
+            Some((server_info, _)) => (
+                cache.revision,
+                cache.error.clone(),
+                cache.snapshot(query.since),
+                server_info.clone(),
+            ),
+            None => {
+                let error = cache.error.clone().expect("no status without error");
+                return JsonResponse::query_error(error);
+            }
+        }
+    };
+    if revision == query.since {
+        return HttpResponse::NoContent().finish();
+    }
+
+    strip_query_clients(server, query.include_query, &mut current);
+    let reset = previous.is_none();
+    let previous = previous
+        .map(|previous| {
+            let mut previous = (*previous).clone();
+            strip_query_clients(server, query.include_query, &mut previous);
+            previous
+        })
+        .unwrap_or_default();
+    json_response(
+        HttpResponse::Ok(),
+        &DeltaResponse {
+            success: error.is_none(),
+            error_code: error.as_ref().map(|e| e.code),
+            error: error.map(|e| e.message),
+            revision,
+            reset,
+            delta: delta::delta(&previous, &current),
+        },
+    )
+}
+
+/// Recorded client counts of the first virtual server of the TS3 host `name`.
+async fn host_history(state: &State, name: &str, query: &HistoryQuery) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+    let history = match &state.history {
+        Some(history) => history,
+        None => return JsonResponse::not_found("History is not enabled".to_string()),
+    };
+
+    let to = query.to.unwrap_or_else(history::now);
+    let range = history::time_range(query.from, to, 86400).and_then(|(from, span)| {
+        let resolution = query
+            .resolution
+            .unwrap_or_else(|| (span / history::DEFAULT_ENTRIES).max(1));
+        (resolution > 0 && span / resolution <= history::MAX_ENTRIES).then_some((from, resolution))
+    });
+    let (from, resolution) = match range {
+        Some(range) => range,
+        None => {
+            return json_response(
+                HttpResponse::BadRequest(),
+                &JsonResponse::error(format!(
+                    "Invalid time range, from must be before to and at most {} entries of \
+                     the resolution apart",
+                    history::MAX_ENTRIES
+                )),
+            )
+        }
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    match history
+        .client_counts(
+            server.cfg.name.clone(),
+            id,
+            from,
+            to,
+            resolution,
+            query.channels,
+        )
+        .await
+    {
+        Ok(clients) => json_response(
+            HttpResponse::Ok(),
+            &HistoryResponse {
+                success: true,
+                error: None,
+                server_id: id,
+                from,
+                to,
+                resolution,
+                clients,
+            },
+        ),
+        Err(e) => {
+            error!("Can not query history: {}", e);
+            json_response(
+                HttpResponse::InternalServerError(),
+                &JsonResponse::error(format!("Can not query history: {}", e)),
+            )
+        }
+    }
+}
+
+/// Peak and average client counts of the first virtual server of the TS3 host `name`.
+async fn host_stats(state: &State, name: &str) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+    let history = match &state.history {
+        Some(history) => history,
+        None => return JsonResponse::not_found("History is not enabled".to_string()),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let mut stats = match history.stats(server.cfg.name.clone(), id).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            error!("Can not query history: {}", e);
+            return json_response(
+                HttpResponse::InternalServerError(),
+                &JsonResponse::error(format!("Can not query history: {}", e)),
+            );
+        }
+    };
+    {
+        let cache = server.caches[&id].load();
+        if let Some((server_info, _)) = cache.last_good() {
+            stats.name_channels(|cid| server_info.channel(cid).map(|channel| channel.name.clone()));
+        }
+    }
+    json_response(
+        HttpResponse::Ok(),
+        &StatsResponse {
+            success: true,
+            error: None,
+            server_id: id,
+            stats,
+        },
+    )
+}
+
+/// Clients of the first virtual server of the TS3 host `name` that were online the longest.
+async fn host_leaderboard(state: &State, name: &str, query: &LeaderboardQuery) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+    let history = match &state.history {
+        Some(history) => history,
+        None => return JsonResponse::not_found("History is not enabled".to_string()),
+    };
+
+    let to = query.to.unwrap_or_else(history::now);
+    let from = match history::time_range(query.from, to, 7 * 86400) {
+        Some((from, _)) => from,
+        None => {
+            return json_response(
+                HttpResponse::BadRequest(),
+                &JsonResponse::error("Invalid time range, from must be before to".to_string()),
+            )
+        }
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let limit = query.limit.unwrap_or(10).min(100);
+    match history
+        .leaderboard(server.cfg.name.clone(), id, from, to, limit)
+        .await
+    {
+        Ok(clients) => json_response(
+            HttpResponse::Ok(),
+            &LeaderboardResponse {
+                success: true,
+                error: None,
+                server_id: id,
+                from,
+                to,
+                clients,
+            },
+        ),
+        Err(e) => {
+            error!("Can not query history: {}", e);
+            json_response(
+                HttpResponse::InternalServerError(),
+                &JsonResponse::error(format!("Can not query history: {}", e)),
+            )
+        }
+    }
+}
+
+/// Atom feed of the recent changes of the first virtual server of the TS3 host `name`, served
+/// for `req`.
+async fn host_feed(state: &State, name: &str, req: &HttpRequest) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let cache = &server.caches[&id];
+    if let Err(e) = query::fetch_status(&state.cfg, server, id, cache).await {
+        error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+    }
+    let cache = cache.load();
+    let title = match cache.last_good() {
+        Some((server_info, _)) => server_info.name.clone(),
+        None => {
+            let error = cache.error.clone().expect("no status without error");
+            return JsonResponse::query_error(error);
+        }
+    };
+    let include_query = server.client_options.include_query;
+    let events: Vec<&LoggedEvent> = cache
+        .events
+        .iter()
+        .rev()
+        .filter(|logged| match &logged.event {
+            ServerEvent::Client(event) => include_query || !event.client.is_query,
+            _ => true,
+        })
+        .collect();
+
+    let connection = req.connection_info();
+    let url = format!(
+        "{}://{}{}",
+        connection.scheme(),
+        connection.host(),
+        req.path()
+    );
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(feed::render(
+            &format!("urn:ts3status:{}:{}", server.cfg.name, id),
+            &title,
+            &url,
+            &events,
+        ))
+}
+
+/// Re-query every virtual server of the TS3 host `name` right away, regardless of the cache
+/// lifetime, and respond with their fresh status.
+async fn host_refresh(state: &State, name: &str, view: &ViewQuery, format: Format) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    for (&id, cache) in &server.caches {
+        info!(
+            "Refreshing TS3 server status for {} server {} on request",
+            name, id
+        );
+        if let Err(e) = query::refresh_shared(server, id, cache).await {
+            error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+        }
+    }
+    host_servers(state, name, view, format).await
+}
+
+/// Details of the first virtual server of the TS3 host `name`.
+async fn host_details(state: &State, name: &str) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let result = query::fetch_details(&state.cfg, server, id, &server.caches[&id]).await;
+    let server_details = match result {
+        Ok(server_details) => server_details,
+        Err(e) => {
+            error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+            return json_response(
+                HttpResponse::build(e.code.status()),
+                &DetailsResponse {
+                    success: false,
+                    error_code: Some(e.code),
+                    error: Some(e.message),
+                    server_details: None,
+                },
+            );
+        }
+    };
+
+    json_response(
+        cached_response(state.cfg.cache_lifetime, server, id),
+        &DetailsResponse {
+            success: true,
+            error: None,
+            error_code: None,
+            server_details: Some(server_details),
+        },
+    )
+}
+
+/// Subscribe to the updates of `cache`, returns them along with its current status if it holds
+/// one. ServerQuery clients are left out unless `include_query` is set.
+fn subscribe(
+    cache: &SharedCache,
+    include_query: bool,
+) -> (Option<ServerInfo>, UnboundedReceiver<StatusUpdate>) {
+    let (cache, updates) = cache.subscribe();
+    let current = cache
+        .error
+        .is_none()
+        .then(|| cache.server_info.visible(include_query));
+    (current, updates)
+}
+
+/// WebSocket pushing the status of the first virtual server of the TS3 host `name`.
+fn host_socket(
+    state: &State,
+    name: &str,
+    req: &HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse> {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return Ok(JsonResponse::not_found(format!("Unknown server {}", name))),
+    };
+
+    let include_query = server.client_options.include_query;
+    let (current, updates) =
+        subscribe(&server.caches[&server.cfg.ts3_server_ids[0]], include_query);
+
+    ws::start(
+        StatusSocket::new(current, updates, include_query),
+        req,
+        stream,
+    )
+}
+
+/// Server-Sent Events of the first virtual server of the TS3 host `name`.
+fn host_events(state: &State, name: &str) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let include_query = server.client_options.include_query;
+    let (current, updates) =
+        subscribe(&server.caches[&server.cfg.ts3_server_ids[0]], include_query);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(sse::event_stream(current, updates, include_query))
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct DeltaQuery {
+    /// `revision` of a previous status or delta response
+    since: u64,
+    /// Show ServerQuery clients, defaults to `include_query_clients` of the configuration
+    include_query: Option<bool>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct HistoryQuery {
+    /// Unix timestamp of the start of the time range, defaults to a day before `to`
+    from: Option<i64>,
+    /// Unix timestamp of the end of the time range, defaults to now
+    to: Option<i64>,
+    /// Seconds covered by each entry, defaults to splitting the time range into 288 entries
+    resolution: Option<u64>,
+    /// Include the client counts per channel
+    #[serde(default)]
+    channels: bool,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct LeaderboardQuery {
+    /// Unix timestamp of the start of the time range, defaults to a week before `to`
+    from: Option<i64>,
+    /// Unix timestamp of the end of the time range, defaults to now
+    to: Option<i64>,
+    /// Number of clients, 10 by default and 100 at most
+    limit: Option<u32>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ShieldQuery {
+    /// Text on the left of the badge, `teamspeak` by default
+    label: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct BannerQuery {
+    width: Option<u32>,
+    height: Option<u32>,
+    theme: Option<Theme>,
+}
+
+/// PNG banner of the first virtual server of the TS3 host `name`.
+async fn host_banner(state: &State, name: &str, query: &BannerQuery) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let result = fetch_server(&state.cfg, server, server.cfg.ts3_server_ids[0], None)
+        .await
+        .expect("no cache for first server");
+    let options = BannerOptions {
+        width: query.width.unwrap_or(state.cfg.banner.width),
+        height: query.height.unwrap_or(state.cfg.banner.height),
+        theme: query.theme.unwrap_or(state.cfg.banner.theme),
+    };
+
+    match banner::render(result.as_ref().ok(), options) {
+        Ok(png) => HttpResponse::Ok().content_type("image/png").body(png),
+        Err(e) => {
+            error!("Can not render banner: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Icon `icon_id` of the first virtual server of the TS3 host `name`.
+async fn host_icon(state: &State, name: &str, icon_id: u64) -> HttpResponse {
+    let server = match state.servers.get(name) {
+        Some(server) => server,
+        None => return JsonResponse::not_found(format!("Unknown server {}", name)),
+    };
+
+    let lifetime = Duration::from_secs(state.cfg.icon_cache_lifetime);
+    match icons::fetch_icon(server, server.cfg.ts3_server_ids[0], icon_id, lifetime).await {
+        Ok(Some(icon)) => HttpResponse::Ok()
+            .content_type(icons::content_type(&icon))
+            .header(
+                "cache-control",
+                format!(
+                    "public, max-age={}, immutable",
+                    state.cfg.icon_cache_lifetime
+                ),
+            )
+            .body(icon),
+        Ok(None) => JsonResponse::not_found(format!("Unknown icon {}", icon_id)),
+        Err(e) => {
+            error!(ts3_error = e.as_str(); "TS3 Error: {}", e);
+            json_response(HttpResponse::BadGateway(), &JsonResponse::error(e))
+        }
+    }
+}
+
+/// Self-contained HTML page rendering the status of the TS3 host `name`.
+fn host_viewer(state: &State, name: &str) -> HttpResponse {
+    if !state.servers.contains_key(name) {
+        return JsonResponse::not_found(format!("Unknown server {}", name));
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(VIEWER_HTML)
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "status",
+    params(ViewQuery, ("format" = Option<Format>, Query, description = "Output format, negotiated with `Accept` if unset")),
+    responses(
+        (status = 200, description = "Status of the first virtual server", body = JsonResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = JsonResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = JsonResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = JsonResponse),
+    )
+)]
+#[get("/")]
+async fn status(
+    state: web::Data<State>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("status {}", state.cfg.default_server().name);
+    Ok(host_status(&state, &state.cfg.default_server().name, &view, format).await)
+}
+
+#[get("/status")]
+async fn status_v1(
+    state: web::Data<State>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("status {}", state.cfg.default_server().name);
+    Ok(host_status(&state, &state.cfg.default_server().name, &view, format).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/servers",
+    tag = "status",
+    params(ViewQuery, ("format" = Option<Format>, Query, description = "Output format, negotiated with `Accept` if unset")),
+    responses(
+        (status = 200, description = "Status of all virtual servers", body = Vec<ServerResponse>),
+    )
+)]
+#[get("/servers")]
+async fn server_list(
+    state: web::Data<State>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("servers {}", state.cfg.default_server().name);
+    Ok(host_servers(&state, &state.cfg.default_server().name, &view, format).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/servers/{id}",
+    tag = "status",
+    params(("id" = u64, Path, description = "Virtual server id"), ViewQuery, ("format" = Option<Format>, Query, description = "Output format, negotiated with `Accept` if unset")),
+    responses(
+        (status = 200, description = "Status of the virtual server", body = JsonResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = JsonResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = JsonResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = JsonResponse),
+        (status = 404, description = "Unknown virtual server", body = JsonResponse),
+    )
+)]
+#[get("/servers/{id}")]
+async fn server_by_id(
+    state: web::Data<State>,
+    id: web::Path<u64>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("server {} {}", state.cfg.default_server().name, id);
+    Ok(host_server(&state, &state.cfg.default_server().name, *id, &view, format).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/channels/{cid}",
+    tag = "status",
+    params(("cid" = u64, Path, description = "Channel id")),
+    responses(
+        (status = 200, description = "The channel with its subchannels", body = ChannelResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = ChannelResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = ChannelResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = ChannelResponse),
+        (status = 404, description = "Unknown channel", body = ChannelResponse),
+    )
+)]
+#[get("/channels/{cid}")]
+async fn channel_by_id(state: web::Data<State>, cid: web::Path<u64>) -> Result<HttpResponse> {
+    debug!("channel {} {}", state.cfg.default_server().name, cid);
+    Ok(host_channel(&state, &state.cfg.default_server().name, *cid).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/clients",
+    tag = "clients",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "Clients of the first virtual server", body = ClientsResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = ClientsResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = ClientsResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = ClientsResponse),
+    )
+)]
+#[get("/clients")]
+async fn client_list(state: web::Data<State>, page: web::Query<PageQuery>) -> Result<HttpResponse> {
+    debug!("clients {}", state.cfg.default_server().name);
+    Ok(host_clients(&state, &state.cfg.default_server().name, &page).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/clients.csv",
+    tag = "clients",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "Clients of the first virtual server", content_type = "text/csv", body = String),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = JsonResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = JsonResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = JsonResponse),
+    )
+)]
+#[get("/clients.csv")]
+async fn client_list_csv(
+    state: web::Data<State>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    debug!("clients.csv {}", state.cfg.default_server().name);
+    Ok(host_clients_csv(&state, &state.cfg.default_server().name, &page).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/count",
+    tag = "clients",
+    responses(
+        (status = 200, description = "Client count of the first virtual server", body = CountResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = JsonResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = JsonResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = JsonResponse),
+    )
+)]
+#[get("/count")]
+async fn client_count(state: web::Data<State>) -> Result<HttpResponse> {
+    debug!("count {}", state.cfg.default_server().name);
+    Ok(host_count(&state, &state.cfg.default_server().name).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/shield.json",
+    tag = "clients",
+    params(ShieldQuery),
+    responses(
+        (status = 200, description = "shields.io badge with the client count of the first virtual server", body = ShieldResponse),
+    )
+)]
+#[get("/shield.json")]
+async fn shield(state: web::Data<State>, query: web::Query<ShieldQuery>) -> Result<HttpResponse> {
+    debug!("shield.json {}", state.cfg.default_server().name);
+    let label = query.label.as_deref();
+    Ok(host_shield(&state, &state.cfg.default_server().name, label).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/serverinfo",
+    tag = "status",
+    security((), ("api_key" = [])),
+    responses(
+        (status = 200, description = "Details of the first virtual server", body = DetailsResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = DetailsResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = DetailsResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = DetailsResponse),
+        (status = 401, description = "Missing or invalid API key", body = JsonResponse),
+    )
+)]
+#[get("/serverinfo")]
+async fn details(state: web::Data<State>, _: ApiKey) -> Result<HttpResponse> {
+    debug!("serverinfo {}", state.cfg.default_server().name);
+    Ok(host_details(&state, &state.cfg.default_server().name).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/refresh",
+    tag = "admin",
+    security(("api_key" = [])),
+    params(ViewQuery, ("format" = Option<Format>, Query, description = "Output format, negotiated with `Accept` if unset")),
+    responses(
+        (status = 200, description = "Fresh status of every virtual server", body = Vec<ServerResponse>),
+        (status = 401, description = "Missing or invalid API key", body = JsonResponse),
+        (status = 404, description = "No API keys are configured", body = JsonResponse),
+    )
+)]
+#[post("/admin/refresh")]
+async fn admin_refresh(
+    state: web::Data<State>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+    _: AdminKey,
+) -> Result<HttpResponse> {
+    debug!("admin refresh {}", state.cfg.default_server().name);
+    Ok(host_refresh(&state, &state.cfg.default_server().name, &view, format).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/delta",
+    tag = "status",
+    params(DeltaQuery),
+    responses(
+        (status = 200, description = "Clients and channels of the first virtual server that changed since the revision", body = DeltaResponse),
+        (status = 500, description = "The query login was rejected or the virtual server doesn't exist", body = JsonResponse),
+        (status = 502, description = "The TS3 server answered with an error", body = JsonResponse),
+        (status = 503, description = "The TS3 server is unreachable or banned this service", body = JsonResponse),
+        (status = 204, description = "Nothing changed since the revision"),
+    )
+)]
+#[get("/delta")]
+async fn status_delta(
+    state: web::Data<State>,
+    query: web::Query<DeltaQuery>,
+) -> Result<HttpResponse> {
+    debug!("delta {}", state.cfg.default_server().name);
+    Ok(host_delta(&state, &state.cfg.default_server().name, &query).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/history/clients",
+    tag = "status",
+    security((), ("api_key" = [])),
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "Recorded client counts of the first virtual server", body = HistoryResponse),
+        (status = 400, description = "Invalid time range or resolution", body = JsonResponse),
+        (status = 404, description = "No history database is configured", body = JsonResponse),
+        (status = 401, description = "Missing or invalid API key", body = JsonResponse),
+    )
+)]
+#[get("/history/clients")]
+async fn client_history(
+    state: web::Data<State>,
+    query: web::Query<HistoryQuery>,
+    _: ApiKey,
+) -> Result<HttpResponse> {
+    debug!("history {}", state.cfg.default_server().name);
+    Ok(host_history(&state, &state.cfg.default_server().name, &query).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "status",
+    security((), ("api_key" = [])),
+    responses(
+        (status = 200, description = "Peak and average client counts of the first virtual server", body = StatsResponse),
+        (status = 404, description = "No history database is configured", body = JsonResponse),
+        (status = 401, description = "Missing or invalid API key", body = JsonResponse),
+    )
+)]
+#[get("/stats")]
+async fn statistics(state: web::Data<State>, _: ApiKey) -> Result<HttpResponse> {
+    debug!("stats {}", state.cfg.default_server().name);
+    Ok(host_stats(&state, &state.cfg.default_server().name).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/leaderboard",
+    tag = "status",
+    security((), ("api_key" = [])),
+    params(LeaderboardQuery),
+    responses(
+        (status = 200, description = "Clients of the first virtual server that were online the longest", body = LeaderboardResponse),
+        (status = 400, description = "Invalid time range", body = JsonResponse),
+        (status = 404, description = "No history database is configured", body = JsonResponse),
+        (status = 401, description = "Missing or invalid API key", body = JsonResponse),
+    )
+)]
+#[get("/leaderboard")]
+async fn leaderboard(
+    state: web::Data<State>,
+    query: web::Query<LeaderboardQuery>,
+    _: ApiKey,
+) -> Result<HttpResponse> {
+    debug!("leaderboard {}", state.cfg.default_server().name);
+    Ok(host_leaderboard(&state, &state.cfg.default_server().name, &query).await)
+}
+
+#[get("/ws")]
+async fn socket(
+    state: web::Data<State>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse> {
+    debug!("socket {}", state.cfg.default_server().name);
+    host_socket(&state, &state.cfg.default_server().name, &req, stream)
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "status",
+    responses(
+        (status = 200, description = "Server-Sent Events with the status and joining or leaving clients", content_type = "text/event-stream", body = String),
+    )
+)]
+#[get("/events")]
+async fn event_source(state: web::Data<State>) -> Result<HttpResponse> {
+    debug!("events {}", state.cfg.default_server().name);
+    Ok(host_events(&state, &state.cfg.default_server().name))
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Prometheus metrics", content_type = "text/plain", body = String),
+    )
+)]
+#[get("/metrics")]
+async fn metrics_export(state: web::Data<State>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics::render(&state.servers)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses(
+        (status = 200, description = "The service is running", content_type = "text/plain", body = String),
+    )
+)]
+#[get("/healthz")]
+async fn liveness() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain").body("ok")
+}
+
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "The last refresh of every virtual server succeeded and is recent, taken from the caches without querying", body = ReadinessResponse),
+        (status = 503, description = "A refresh failed or is overdue, or a TS3 host is no longer queried after consecutive failures", body = ReadinessResponse),
+    )
+)]
+#[get("/readyz")]
+async fn readiness(state: web::Data<State>) -> Result<HttpResponse> {
+    // Readiness is taken from the caches as they are, so probes never query the TS3 hosts. With
+    // background refreshes, a cache is stale once it missed a refresh. Otherwise caches are only
+    // refreshed on request, so they may be old or not queried yet without anything being wrong.
+    let background = state.cfg.background_refresh;
+    let stale = 2 * state.cfg.cache_lifetime.max(1);
+    let mut servers = Vec::new();
+    for server in state.servers.values() {
+        let circuit_open = server
+            .breaker
+            .lock()
+            .expect("can't lock circuit breaker")
+            .is_open();
+        for (&id, cache) in &server.caches {
+            let cache = cache.load();
+            let age_seconds = cache.age().map(|age| age.as_secs());
+            let ready = if cache.attempted() {
+                !circuit_open && age_seconds.is_some_and(|age| !background || age <= stale)
+            } else {
+                !background
+            };
+            servers.push(CacheReadiness {
+                name: server.cfg.name.clone(),
+                id,
+                ready,
+                error: cache.error.as_ref().map(|e| e.message.clone()),
+                age_seconds,
+                circuit_open,
+            });
+        }
+    }
+    servers.sort_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+
+    let ready = servers.iter().all(|server| server.ready);
+    let mut builder = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(builder
+        .set(CacheControl(vec![CacheDirective::NoCache]))
+        .json(ReadinessResponse { ready, servers }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/banner.png",
+    tag = "images",
+    params(BannerQuery),
+    responses(
+        (status = 200, description = "Banner with the server name and number of clients", content_type = "image/png", body = Vec<u8>),
+    )
+)]
+#[get("/banner.png")]
+async fn banner_image(
+    state: web::Data<State>,
+    query: web::Query<BannerQuery>,
+) -> Result<HttpResponse> {
+    debug!("banner {}", state.cfg.default_server().name);
+    Ok(host_banner(&state, &state.cfg.default_server().name, &query).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/icons/{icon_id}",
+    tag = "images",
+    params(("icon_id" = u64, Path, description = "Icon id of a channel or server")),
+    responses(
+        (status = 200, description = "The icon image", body = Vec<u8>),
+        (status = 404, description = "Unknown icon", body = JsonResponse),
+        (status = 502, description = "The icon can't be downloaded", body = JsonResponse),
+    )
+)]
+#[get("/icons/{icon_id}")]
+async fn icon_image(state: web::Data<State>, icon_id: web::Path<u64>) -> Result<HttpResponse> {
+    debug!("icon {} {}", state.cfg.default_server().name, icon_id);
+    Ok(host_icon(&state, &state.cfg.default_server().name, *icon_id).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/feed.atom",
+    tag = "status",
+    responses(
+        (status = 200, description = "Atom feed of clients joining and leaving and channels being created and deleted on the first virtual server", content_type = "application/atom+xml", body = String),
+        (status = 502, description = "The TS3 server can't be queried", body = JsonResponse),
+    )
+)]
+#[get("/feed.atom")]
+async fn event_feed(state: web::Data<State>, req: HttpRequest) -> Result<HttpResponse> {
+    debug!("feed {}", state.cfg.default_server().name);
+    Ok(host_feed(&state, &state.cfg.default_server().name, &req).await)
+}
+
+#[get("/viewer")]
+async fn viewer(state: web::Data<State>) -> Result<HttpResponse> {
+    debug!("viewer {}", state.cfg.default_server().name);
+    Ok(host_viewer(&state, &state.cfg.default_server().name))
+}
+
+#[get("/{name}")]
+async fn named_status(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("status {}", name);
+    Ok(host_status(&state, &name, &view, format).await)
+}
+
+#[get("/{name}/status")]
+async fn named_status_v1(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("status {}", name);
+    Ok(host_status(&state, &name, &view, format).await)
+}
+
+#[get("/{name}/servers")]
+async fn named_server_list(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    debug!("servers {}", name);
+    Ok(host_servers(&state, &name, &view, format).await)
+}
+
+#[get("/{name}/servers/{id}")]
+async fn named_server_by_id(
+    state: web::Data<State>,
+    path: web::Path<(String, u64)>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+) -> Result<HttpResponse> {
+    let (name, id) = path.into_inner();
+    debug!("server {} {}", name, id);
+    Ok(host_server(&state, &name, id, &view, format).await)
+}
+
+#[get("/{name}/channels/{cid}")]
+async fn named_channel_by_id(
+    state: web::Data<State>,
+    path: web::Path<(String, u64)>,
+) -> Result<HttpResponse> {
+    let (name, cid) = path.into_inner();
+    debug!("channel {} {}", name, cid);
+    Ok(host_channel(&state, &name, cid).await)
+}
+
+#[get("/{name}/clients")]
+async fn named_client_list(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    debug!("clients {}", name);
+    Ok(host_clients(&state, &name, &page).await)
+}
+
+#[get("/{name}/clients.csv")]
+async fn named_client_list_csv(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    page: web::Query<PageQuery>,
+) -> Result<HttpResponse> {
+    debug!("clients.csv {}", name);
+    Ok(host_clients_csv(&state, &name, &page).await)
+}
+
+#[get("/{name}/count")]
+async fn named_client_count(
+    state: web::Data<State>,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    debug!("count {}", name);
+    Ok(host_count(&state, &name).await)
+}
+
+#[get("/{name}/shield.json")]
+async fn named_shield(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    query: web::Query<ShieldQuery>,
+) -> Result<HttpResponse> {
+    debug!("shield.json {}", name);
+    Ok(host_shield(&state, &name, query.label.as_deref()).await)
+}
+
+#[get("/{name}/serverinfo")]
+async fn named_details(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    _: ApiKey,
+) -> Result<HttpResponse> {
+    debug!("serverinfo {}", name);
+    Ok(host_details(&state, &name).await)
+}
+
+#[post("/{name}/admin/refresh")]
+async fn named_admin_refresh(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    view: web::Query<ViewQuery>,
+    format: Format,
+    _: AdminKey,
+) -> Result<HttpResponse> {
+    debug!("admin refresh {}", name);
+    Ok(host_refresh(&state, &name, &view, format).await)
+}
+
+#[get("/{name}/delta")]
+async fn named_status_delta(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    query: web::Query<DeltaQuery>,
+) -> Result<HttpResponse> {
+    debug!("delta {}", name);
+    Ok(host_delta(&state, &name, &query).await)
+}
+
+#[get("/{name}/history/clients")]
+async fn named_client_history(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+    _: ApiKey,
+) -> Result<HttpResponse> {
+    debug!("history {}", name);
+    Ok(host_history(&state, &name, &query).await)
+}
+
+#[get("/{name}/stats")]
+async fn named_statistics(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    _: ApiKey,
+) -> Result<HttpResponse> {
+    debug!("stats {}", name);
+    Ok(host_stats(&state, &name).await)
+}
+
+#[get("/{name}/leaderboard")]
+async fn named_leaderboard(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    query: web::Query<LeaderboardQuery>,
+    _: ApiKey,
+) -> Result<HttpResponse> {
+    debug!("leaderboard {}", name);
+    Ok(host_leaderboard(&state, &name, &query).await)
+}
+
+#[get("/{name}/feed.atom")]
+async fn named_event_feed(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    debug!("feed {}", name);
+    Ok(host_feed(&state, &name, &req).await)
+}
+
+#[get("/{name}/ws")]
+async fn named_socket(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    req: HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse> {
+    debug!("socket {}", name);
+    host_socket(&state, &name, &req, stream)
+}
+
+#[get("/{name}/banner.png")]
+async fn named_banner_image(
+    state: web::Data<State>,
+    name: web::Path<String>,
+    query: web::Query<BannerQuery>,
+) -> Result<HttpResponse> {
+    debug!("banner {}", name);
+    Ok(host_banner(&state, &name, &query).await)
+}
+
+#[get("/{name}/events")]
+async fn named_event_source(
+    state: web::Data<State>,
+    name: web::Path<String>,
+) -> Result<HttpResponse> {
+    debug!("events {}", name);
+    Ok(host_events(&state, &name))
+}
+
+#[get("/{name}/icons/{icon_id}")]
+async fn named_icon_image(
+    state: web::Data<State>,
+    path: web::Path<(String, u64)>,
+) -> Result<HttpResponse> {
+    let (name, icon_id) = path.into_inner();
+    debug!("icon {} {}", name, icon_id);
+    Ok(host_icon(&state, &name, icon_id).await)
+}
+
+#[get("/{name}/viewer")]
+async fn named_viewer(state: web::Data<State>, name: web::Path<String>) -> Result<HttpResponse> {
+    debug!("viewer {}", name);
+    Ok(host_viewer(&state, &name))
+}
+
+/// Remove the socket file left at `path` by a previous run, binding fails otherwise.
+#[cfg(unix)]
+fn remove_stale_socket(path: &str) {
+    use std::os::unix::fs::FileTypeExt;
+
+    let is_socket = std::fs::metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket {
+        if let Err(e) = std::fs::remove_file(path) {
+            error!("Can't remove stale socket {}: {}", path, e);
+        }
+    }
+}
+
+fn build_state(mut cfg: Config) -> Result<State, String> {
+    let history = match &cfg.history.database {
+        Some(path) => Some(
+            History::open(path, cfg.history.retention_days)
+                .map_err(|e| format!("can't open history database {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+
+    if cfg.privacy_mode == PrivacyMode::Hash && cfg.privacy_secret.is_none() {
+        cfg.privacy_secret = Some(match &history {
+            Some(history) => history
+                .privacy_secret()
+                .map_err(|e| format!("can't read the privacy secret: {}", e))?,
+            None => {
+                warn!("privacy_secret is not set, hashed nicknames change with every start");
+                privacy::random_secret()
+            }
+        });
+    }
+
+    let servers = cfg
+        .servers
+        .iter()
+        .map(|(name, server_cfg)| {
+            let server = Server::new(server_cfg.clone(), &cfg);
+            (name.clone(), server)
+        })
+        .collect();
+
+    Ok(State {
+        cfg,
+        servers,
+        graphql: graphql::schema(),
+        history,
+    })
+}
+
+/// Spawn `task` in the background, it runs until aborted through the returned handle.
+fn spawn_abortable<F: Future<Output = ()> + 'static>(task: F) -> AbortHandle {
+    let (task, handle) = future::abortable(task);
+    rt::spawn(task.map(drop));
+    handle
+}
+
+/// Spawn a background task per virtual server that keeps its cache up to date.
+fn spawn_refresh_tasks(state: &State) -> Vec<AbortHandle> {
+    let mut tasks = Vec::new();
+    for server in state.servers.values() {
+        for (&id, cache) in &server.caches {
+            tasks.push(spawn_abortable(query::refresh_periodically(
+                state.cfg.clone(),
+                server.clone(),
+                id,
+                cache.clone(),
+            )));
+        }
+    }
+    tasks
+}
+
+/// Spawn a background task per virtual server that updates its cache from notifications.
+fn spawn_notification_tasks(state: &State) -> Vec<AbortHandle> {
+    let mut tasks = Vec::new();
+    for server in state.servers.values() {
+        if !server.cfg.ts3_query_protocol.notifications() {
+            warn!(
+                "{} is queried by WebQuery, which doesn't notify about events",
+                server.cfg.name
+            );
+            continue;
+        }
+        for (&id, cache) in &server.caches {
+            tasks.push(spawn_abortable(notify::follow(
+                state.cfg.clone(),
+                server.clone(),
+                id,
+                cache.clone(),
+            )));
+        }
+    }
+    tasks
+}
+
+/// Spawn a background task per virtual server that notifies the webhooks about its clients.
+fn spawn_webhook_tasks(state: &State) -> Vec<AbortHandle> {
+    let mut tasks = Vec::new();
+    for server in state.servers.values() {
+        for (&id, cache) in &server.caches {
+            let include_query = server.client_options.include_query;
+            let (current, updates) = subscribe(cache, include_query);
+            tasks.push(spawn_abortable(webhooks::notify(
+                state.cfg.webhooks.clone(),
+                server.cfg.name.clone(),
+                id,
+                current,
+                updates,
+                include_query,
+            )));
+        }
+    }
+    tasks
+}
+
+/// Spawn a background task per virtual server that announces its clients on Discord, and one
+/// that sends the announcements.
+fn spawn_discord_tasks(state: &State) -> Vec<AbortHandle> {
+    let (lines, queued) = mpsc::unbounded();
+    let mut tasks = vec![spawn_abortable(discord::send_messages(
+        state.cfg.discord.clone(),
+        queued,
+    ))];
+    for server in state.servers.values() {
+        for cache in server.caches.values() {
+            let include_query = server.client_options.include_query;
+            let (current, updates) = subscribe(cache, include_query);
+            tasks.push(spawn_abortable(discord::announce(
+                state.cfg.discord.clone(),
+                current,
+                updates,
+                include_query,
+                lines.clone(),
+            )));
+        }
+    }
+    tasks
+}
+
+/// Connect to the MQTT broker and spawn a background task per virtual server that publishes its
+/// status there.
+fn spawn_mqtt_tasks(state: &State) -> Vec<AbortHandle> {
+    let publisher = mqtt::Publisher::connect(&state.cfg.mqtt);
+    let mut tasks = Vec::new();
+    for server in state.servers.values() {
+        for (&id, cache) in &server.caches {
+            let include_query = server.client_options.include_query;
+            let (current, updates) = subscribe(cache, include_query);
+            tasks.push(spawn_abortable(mqtt::publish_status(
+                publisher.clone(),
+                server.cfg.name.clone(),
+                id,
+                current,
+                updates,
+                include_query,
+                state.cfg.mqtt.discovery_prefix.clone(),
+            )));
+        }
+    }
+    tasks
+}
+
+/// Spawn a background task per virtual server that records its client counts in `history`, and
+/// one that deletes expired samples.
+fn spawn_history_tasks(state: &State, history: &History) -> Vec<AbortHandle> {
+    let mut tasks = vec![spawn_abortable(history::prune_periodically(
+        history.clone(),
+    ))];
+    for server in state.servers.values() {
+        for (&id, cache) in &server.caches {
+            let include_query = server.client_options.include_query;
+            let (_, updates) = subscribe(cache, include_query);
+            tasks.push(spawn_abortable(history::record(
+                history.clone(),
+                server.cfg.name.clone(),
+                id,
+                updates,
+                include_query,
+            )));
+        }
+    }
+    tasks
+}
+
+/// Spawn a background task per TS3 host that keeps its query connection alive.
+fn spawn_keepalive_tasks(state: &State) -> Vec<AbortHandle> {
+    state
+        .servers
+        .values()
+        .map(|server| spawn_abortable(query::keepalive_periodically(server.clone())))
+        .collect()
+}
+
+/// The TS3 host `name` selected with `--server` for subcommands, the default one if none is
+/// passed.
+fn selected_server(cfg: &Config, name: Option<String>) -> Result<Server, String> {
+    let server_cfg = match name {
+        Some(name) => cfg
+            .servers
+            .get(&name)
+            .ok_or_else(|| format!("unknown server {}", name))?,
+        None => cfg.default_server(),
+    };
+    Ok(Server::new(server_cfg.clone(), cfg))
+}
+
+/// Run `ts3status check`: query the first virtual server of `--server`, or the default one, print
+/// the result as monitoring plugin output and return its exit code.
+async fn run_check(cfg: Config, name: Option<String>, thresholds: check::Thresholds) -> i32 {
+    let server = match selected_server(&cfg, name) {
+        Ok(server) => server,
+        Err(e) => {
+            println!("{}", check::output(check::Status::Unknown, &e));
+            return check::Status::Unknown.code();
+        }
+    };
+
+    let (result, output) = check::check(&server, thresholds).await;
+    shutdown(vec![server], Vec::new()).await;
+    println!("{}", output);
+    result.code()
+}
+
+/// Run `ts3status once`: query the first virtual server of `--server`, or the default one, and
+/// print its status as JSON, or as text tree with `--format text`. Returns the exit code.
+async fn run_once(cfg: Config, name: Option<String>, format: OnceFormat) -> i32 {
+    let server = match selected_server(&cfg, name) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+
+    let id = server.cfg.ts3_server_ids[0];
+    let result = query::refresh_shared(&server, id, &server.caches[&id]).await;
+    shutdown(vec![server.clone()], Vec::new()).await;
+    let server_info = match result {
+        Ok(server_info) => server_info.visible(server.client_options.include_query),
+        Err(e) => {
+            eprintln!("TS3 Error: {}", e);
+            return 1;
+        }
+    };
+    if format == OnceFormat::Text {
+        print!("{}", text::status(&StatusView::Tree(server_info)));
+    } else {
+        match serde_json::to_string_pretty(&server_info) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Can not serialize status: {}", e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// Run `ts3status validate-config` and, with `connect`, `ts3status validate`: report problems of
+/// the settings and of logging in to every TS3 host, or summarize them if there are none. Returns
+/// the exit code.
+async fn run_validate(cfg: &Config, connect: bool) -> i32 {
+    let mut problems = validate::settings(cfg);
+    for server in cfg.servers.values() {
+        println!(
+            "{}: {}:{} as {}, virtual servers {:?}",
+            server.name, server.ts3_host, server.ts3_port, server.user, server.ts3_server_ids
+        );
+        if connect {
+            let (server_cfg, validated_cfg) = (server.clone(), cfg.clone());
+            let result = web::block(move || validate::connect(&server_cfg, &validated_cfg)).await;
+            match result {
+                Ok(()) => {}
+                Err(BlockingError::Error(e)) => problems.push(format!("{}: {}", server.name, e)),
+                Err(BlockingError::Canceled) => {
+                    problems.push(format!("{}: connection test was canceled", server.name))
+                }
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("error: {}", problem);
+        }
+        return 1;
+    }
+    println!("Configuration is valid");
+    0
+}
+
+/// Report `problem` the way configuration problems are and exit, for ones that only show up
+/// when the server starts.
+fn exit_with<T>(problem: String) -> T {
+    eprintln!(
+        "{}",
+        ConfigError {
+            problems: vec![problem]
+        }
+    );
+    process::exit(1)
+}
+
+/// Stop the background `tasks` and log out of every TS3 host, so no ServerQuery sessions are
+/// left behind until they time out.
+async fn shutdown(servers: Vec<Server>, tasks: Vec<AbortHandle>) {
+    for task in tasks {
+        task.abort();
+    }
+    for server in servers {
+        let backend = server.backend.clone();
+        let result =
+            web::block(move || backend.lock().expect("can't lock connection").disconnect()).await;
+        if let Err(e) = result {
+            error!("Logout from {} failed: {:?}", server.cfg.name, e);
+        }
+    }
+}
+
+#[post("/graphql")]
+async fn graphql_query(
+    state: web::Data<State>,
+    request: web::Json<async_graphql::Request>,
+    api_key: Option<ApiKey>,
+) -> Result<HttpResponse> {
+    let mut request = request.into_inner().data(state.clone());
+    if let Some(api_key) = api_key {
+        request = request.data(api_key);
+    }
+    Ok(HttpResponse::Ok().json(state.graphql.execute(request).await))
+}
+
+#[get("/openapi.json")]
+async fn openapi_spec(state: web::Data<State>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(openapi::spec(&state.cfg.base_path)))
+}
+
+#[get("/docs")]
+async fn api_docs(state: web::Data<State>) -> Result<HttpResponse> {
+    if !state.cfg.swagger_ui {
+        return Ok(JsonResponse::not_found(
+            "Swagger UI is disabled".to_string(),
+        ));
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(openapi::SWAGGER_UI_HTML))
+}
+
+/// Register the API routes. They are served unversioned at `/` and at `/v1`, whose response
+/// shapes stay stable. Breaking changes go to a new version instead.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(status)
+        .service(server_list)
+        .service(server_by_id)
+        .service(channel_by_id)
+        .service(client_list)
+        .service(client_list_csv)
+        .service(client_count)
+        .service(shield)
+        .service(details)
+        .service(admin_refresh)
+        .service(status_delta)
+        .service(client_history)
+        .service(statistics)
+        .service(leaderboard)
+        .service(event_feed)
+        .service(socket)
+        .service(event_source)
+        .service(banner_image)
+        .service(icon_image)
+        .service(viewer)
+        .service(openapi_spec)
+        .service(graphql_query)
+        .service(api_docs)
+        // Named routes match any path, keep them last
+        .service(named_status)
+        .service(named_server_list)
+        .service(named_server_by_id)
+        .service(named_channel_by_id)
+        .service(named_client_list)
+        .service(named_client_list_csv)
+        .service(named_client_count)
+        .service(named_shield)
+        .service(named_details)
+        .service(named_admin_refresh)
+        .service(named_status_delta)
+        .service(named_client_history)
+        .service(named_statistics)
+        .service(named_leaderboard)
+        .service(named_event_feed)
+        .service(named_socket)
+        .service(named_event_source)
+        .service(named_banner_image)
+        .service(named_icon_image)
+        .service(named_viewer);
+}
+
+/// Run the `ts3status` command line `cli`: serve the status over HTTP until stopped by SIGINT or
+/// SIGTERM, or run one of the other subcommands and exit with its result.
+pub async fn run(cli: Cli) -> std::io::Result<()> {
+    let mut listen = cli.serve.listen;
+    match &cli.command {
+        Some(Command::Serve(args)) => listen.extend(args.listen.iter().cloned()),
+        Some(Command::Validate(args) | Command::ValidateConfig(args)) => {
+            let connect = matches!(cli.command, Some(Command::Validate(_)));
+            listen.extend(args.listen.iter().cloned());
+            let code = match Config::load(cli.config.as_deref(), cli.env_file.as_deref(), listen) {
+                Ok(cfg) => run_validate(&cfg, connect).await,
+                Err(e) => {
+                    for problem in &e.problems {
+                        eprintln!("error: {}", problem);
+                    }
+                    1
+                }
+            };
+            process::exit(code)
+        }
+        _ => {}
+    }
+    let cfg = match Config::load(cli.config.as_deref(), cli.env_file.as_deref(), listen) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1)
+        }
+    };
+    // Subcommands other than serve only print their result
+    match cli.command {
+        None | Some(Command::Serve(_)) => {}
+        Some(Command::Once { server, format }) => {
+            process::exit(run_once(cfg, server.server, format).await)
+        }
+        Some(Command::Check {
+            server,
+            warning,
+            critical,
+        }) => {
+            let thresholds = check::Thresholds { warning, critical };
+            process::exit(run_check(cfg, server.server, thresholds).await)
+        }
+        Some(Command::Validate(_) | Command::ValidateConfig(_)) => unreachable!(),
+    }
+    logging::init(&cfg);
+    let tracer_provider = telemetry::init(&cfg);
+
+    let listen = cfg.listen.clone();
+    // Loading the configuration checked these already, but the files may have changed since
+    let tls = match (&cfg.tls_cert, &cfg.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::server_config(cert, key).unwrap_or_else(exit_with)),
+        _ => None,
+    };
+    #[cfg(unix)]
+    let listeners = systemd::listeners();
+    #[cfg(unix)]
+    if tls.is_some()
+        && listeners
+            .iter()
+            .any(|listener| matches!(listener, systemd::Listener::Unix(_)))
+    {
+        exit_with::<()>("TLS and unix sockets can't be combined".to_string());
+    }
+    let hostname = cfg.hostname.clone();
+
+    let client_ips = ClientIps {
+        header: cfg.client_ip_header.clone(),
+        trusted_proxies: cfg.trusted_proxies.clone(),
+    };
+    let limiter = Arc::new(RateLimiter::new(cfg.rate_limit, client_ips.clone()));
+    let access = Arc::new(AccessList {
+        allow: cfg.allowed_ips.clone(),
+        deny: cfg.denied_ips.clone(),
+        client_ips: client_ips.clone(),
+    });
+    let basic_auth = Arc::new(BasicAuth::new(&cfg.basic_auth).unwrap_or_else(exit_with));
+    let state = build_state(cfg).unwrap_or_else(exit_with);
+    let mut tasks = spawn_keepalive_tasks(&state);
+    if state.cfg.background_refresh {
+        tasks.extend(spawn_refresh_tasks(&state));
+    }
+    if state.cfg.event_updates {
+        tasks.extend(spawn_notification_tasks(&state));
+    }
+    if state.cfg.webhooks.enabled() {
+        tasks.extend(spawn_webhook_tasks(&state));
+    }
+    if state.cfg.discord.enabled() {
+        tasks.extend(spawn_discord_tasks(&state));
+    }
+    if state.cfg.mqtt.enabled() {
+        tasks.extend(spawn_mqtt_tasks(&state));
+    }
+    if let Some(history) = &state.history {
+        tasks.extend(spawn_history_tasks(&state, history));
+    }
+    #[cfg(unix)]
+    tasks.push(spawn_abortable(systemd::watchdog(state.clone())));
+    let servers = state.servers.values().cloned().collect();
+    let server = HttpServer::new(move || {
+        let limiter = limiter.clone();
+        let basic_auth = basic_auth.clone();
+        let access = access.clone();
+        let client_ips = client_ips.clone();
+        let access_log = state.cfg.access_log;
+        App::new()
+            .data(state.clone())
+            .wrap_fn(conditional::conditional_get)
+            .wrap_fn(move |req, srv| auth::require_basic_auth(&basic_auth, req, srv))
+            .wrap(Condition::new(
+                state.cfg.cors.enabled(),
+                state.cfg.cors.middleware(),
+            ))
+            .wrap_fn(move |req, srv| ratelimit::limit_requests(&limiter, req, srv))
+            .wrap_fn(move |req, srv| access::restrict_ips(&access, req, srv))
+            .wrap_fn(move |req, srv| logging::log_request(access_log, &client_ips, req, srv))
+            .wrap_fn(telemetry::trace_request)
+            .service(
+                web::scope(&state.cfg.base_path)
+                    .service(metrics_export)
+                    .service(liveness)
+                    .service(readiness)
+                    .service(
+                        web::scope("/v1")
+                            .service(status_v1)
+                            .service(named_status_v1)
+                            .configure(routes),
+                    )
+                    .configure(routes),
+            )
+    })
+    .server_hostname(hostname);
+    let mut server = server;
+    let mut activated = false;
+    #[cfg(unix)]
+    for listener in listeners {
+        activated = true;
+        server = match (listener, &tls) {
+            (systemd::Listener::Tcp(listener), Some(tls)) => {
+                server.listen_rustls(listener, tls.clone())?
+            }
+            (systemd::Listener::Tcp(listener), None) => server.listen(listener)?,
+            (systemd::Listener::Unix(listener), _) => server.listen_uds(listener)?,
+        };
+    }
+    // Configured addresses are only used without socket activation
+    if !activated && listen.is_empty() {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "no address to listen on, pass --listen or set LISTEN_ADDR",
+            )
+            .exit();
+    }
+    for listen in listen.into_iter().filter(|_| !activated) {
+        server = match (listen.strip_prefix("unix:"), &tls) {
+            // Unix sockets with TLS were rejected when loading the configuration
+            #[cfg(unix)]
+            (Some(path), _) => {
+                remove_stale_socket(path);
+                server.bind_uds(path)?
+            }
+            #[cfg(not(unix))]
+            (Some(_), _) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "unix sockets are only supported on unix",
+                ))
+            }
+            (None, Some(tls)) => server.bind_rustls(listen, tls.clone())?,
+            (None, None) => server.bind(listen)?,
+        };
+    }
+    let server = server.run();
+    #[cfg(unix)]
+    systemd::notify_ready();
+    // Stops accepting connections on SIGINT and SIGTERM and finishes requests in flight first
+    server.await?;
+    #[cfg(unix)]
+    systemd::notify_stopping();
+    shutdown(servers, tasks).await;
+
+    if let Some(provider) = tracer_provider {
+        telemetry::shutdown(provider);
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::{header, HeaderName, HeaderValue},
+    Error,
+};
+use log::{info, kv, log, Level, LevelFilter};
+use pretty_env_logger::env_logger::fmt::Formatter;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    future::Future,
+    io::{self, Write},
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+use crate::{config::Config, ratelimit::ClientIps};
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown log format {}, expected text or json", s)),
+        }
+    }
+}
+
+/// Format of the access log, which logs every request with the `access` target.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLog {
+    Off,
+    /// Client IP, method, path, status and latency
+    Common,
+    /// `Common` with the referer and user agent
+    Combined,
+}
+
+impl FromStr for AccessLog {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(AccessLog::Off),
+            "common" => Ok(AccessLog::Common),
+            "combined" => Ok(AccessLog::Combined),
+            _ => Err(format!(
+                "unknown access log format {}, expected off, common or combined",
+                s
+            )),
+        }
+    }
+}
+
+/// Collects the key-value pairs of a log record into a JSON object.
+struct JsonFields<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> kv::VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        let value = serde_json::to_value(&value).unwrap_or_else(|_| value.to_string().into());
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Write a log record as a single line of JSON.
+fn format_json(buf: &mut Formatter, record: &log::Record) -> io::Result<()> {
+    let mut line = Map::new();
+    line.insert(
+        "timestamp".to_string(),
+        buf.timestamp_millis().to_string().into(),
+    );
+    line.insert("level".to_string(), record.level().as_str().into());
+    line.insert("target".to_string(), record.target().into());
+    line.insert("message".to_string(), record.args().to_string().into());
+    let _ = record.key_values().visit(&mut JsonFields(&mut line));
+
+    writeln!(buf, "{}", Value::Object(line))
+}
+
+pub fn init(cfg: &Config) {
+    let mut builder = pretty_env_logger::formatted_builder();
+    if cfg.log_format == LogFormat::Json {
+        builder.format(format_json);
+    }
+    // Shown regardless of the log level, unless the filters say otherwise
+    if cfg.access_log != AccessLog::Off {
+        builder.filter_module("access", LevelFilter::Info);
+    }
+    if let Some(filters) = cfg.log_level.as_ref() {
+        builder.parse_filters(filters);
+    }
+    builder.init();
+}
+
+/// Middleware logging every request with its id, endpoint and duration, or in the access log
+/// `format` with the client IP taken from `client_ips` like the rate limit does, in which case
+/// the former is only logged for debugging. The id is also returned in the `X-Request-Id` header.
+pub fn log_request<S>(
+    format: AccessLog,
+    client_ips: &ClientIps,
+    req: ServiceRequest,
+    srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let request_id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    let endpoint = req.path().to_string();
+    let access = (format != AccessLog::Off).then(|| AccessEntry::new(client_ips, &req));
+    let start = Instant::now();
+    let response = srv.call(req);
+
+    async move {
+        let response = response.await;
+        let duration_ms = start.elapsed().as_millis() as u64;
+        // The access log already has a line per request, the id is only of interest when debugging
+        let level = match access {
+            Some(access) => {
+                let status = match &response {
+                    Ok(response) => response.status(),
+                    Err(e) => e.as_response_error().status_code(),
+                };
+                access.log(format, status.as_u16(), duration_ms);
+                Level::Debug
+            }
+            None => Level::Info,
+        };
+        let mut response = response?;
+        log!(
+            level,
+            request_id = request_id,
+            endpoint = endpoint.as_str(),
+            duration_ms = duration_ms;
+            "#{} {} {}ms",
+            request_id,
+            endpoint,
+            duration_ms
+        );
+        response.headers_mut().insert(
+            HeaderName::from_static("x-request-id"),
+            HeaderValue::from(request_id),
+        );
+        Ok(response)
+    }
+}
+
+/// What the access log records of a request, taken before it is handled.
+struct AccessEntry {
+    client_ip: String,
+    method: String,
+    path: String,
+    referer: String,
+    user_agent: String,
+}
+
+impl AccessEntry {
+    fn new(client_ips: &ClientIps, req: &ServiceRequest) -> Self {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or("-")
+                .to_string()
+        };
+        Self {
+            client_ip: client_ips
+                .of(req)
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            method: req.method().to_string(),
+            path: req
+                .uri()
+                .path_and_query()
+                .map(|path| path.as_str())
+                .unwrap_or_else(|| req.path())
+                .to_string(),
+            referer: header(header::REFERER),
+            user_agent: header(header::USER_AGENT),
+        }
+    }
+
+    /// Log the request in `format`, answered with `status` after `latency_ms`.
+    fn log(&self, format: AccessLog, status: u16, latency_ms: u64) {
+        let mut line = format!(
+            "{} \"{} {}\" {} {}ms",
+            self.client_ip, self.method, self.path, status, latency_ms
+        );
+        if format == AccessLog::Combined {
+            line = format!("{} \"{}\" \"{}\"", line, self.referer, self.user_agent);
+        }
+        info!(
+            target: "access",
+            client_ip = self.client_ip.as_str(),
+            method = self.method.as_str(),
+            path = self.path.as_str(),
+            status = status,
+            latency_ms = latency_ms,
+            referer = self.referer.as_str(),
+            user_agent = self.user_agent.as_str();
+            "{}",
+            line
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+use std::collections::HashMap;
+
+use crate::query::{ChannelNode, Server};
+
+lazy_static! {
+    pub static ref QUERY_DURATION: HistogramVec = register_histogram_vec!(
+        "ts3status_query_duration_seconds",
+        "Duration of TS3 status queries",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    pub static ref REFRESH_FAILURES: IntCounterVec = register_int_counter_vec!(
+        "ts3status_refresh_failures_total",
+        "Number of failed status refreshes",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    pub static ref CACHE_HITS: IntCounterVec = register_int_counter_vec!(
+        "ts3status_cache_hits_total",
+        "Number of requests served from the status cache",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    pub static ref CACHE_MISSES: IntCounterVec = register_int_counter_vec!(
+        "ts3status_cache_misses_total",
+        "Number of requests that had to query the TS3 server",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref UP: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_up",
+        "Whether the last status refresh succeeded",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref CLIENTS_ONLINE: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_clients_online",
+        "Number of clients online",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref MAX_CLIENTS: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_max_clients",
+        "Maximum number of clients allowed",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref PING: GaugeVec = register_gauge_vec!(
+        "ts3status_ping_milliseconds",
+        "Average ping of all clients",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref PACKET_LOSS: GaugeVec = register_gauge_vec!(
+        "ts3status_packet_loss_ratio",
+        "Average packet loss of all clients",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref BANDWIDTH_SENT: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_bandwidth_sent_bytes",
+        "Bytes sent during the last second",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref BANDWIDTH_RECEIVED: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_bandwidth_received_bytes",
+        "Bytes received during the last second",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref CHANNELS: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_channels",
+        "Number of channels",
+        &["server", "server_id"]
+    )
+    .expect("can't register metric");
+    static ref CHANNEL_CLIENTS: IntGaugeVec = register_int_gauge_vec!(
+        "ts3status_channel_clients",
+        "Number of clients per channel",
+        &["server", "server_id", "channel_id", "channel"]
+    )
+    .expect("can't register metric");
+}
+
+fn observe_channels(labels: &[&str], channels: &[ChannelNode]) -> i64 {
+    let mut count = 0;
+    for channel in channels {
+        let channel_id = channel.id.to_string();
+        CHANNEL_CLIENTS
+            .with_label_values(&[labels[0], labels[1], &channel_id, &channel.name])
+            .set(channel.clients.iter().filter(|c| !c.is_query).count() as i64);
+        count += 1 + observe_channels(labels, &channel.children);
+    }
+    count
+}
+
+/// Render all metrics in the Prometheus text format, updating the status gauges from the caches
+/// of `servers` first.
+pub fn render(servers: &HashMap<String, Server>) -> String {
+    for gauge in &[
+        &*UP,
+        &*CLIENTS_ONLINE,
+        &*MAX_CLIENTS,
+        &*BANDWIDTH_SENT,
+        &*BANDWIDTH_RECEIVED,
+        &*CHANNELS,
+        &*CHANNEL_CLIENTS,
+    ] {
+        gauge.reset();
+    }
+    PING.reset();
+    PACKET_LOSS.reset();
+
+    for (name, server) in servers {
+        for (id, cache) in &server.caches {
+            let id = id.to_string();
+            let labels = [name.as_str(), id.as_str()];
+            let cache = cache.load();
+
+            UP.with_label_values(&labels)
+                .set(cache.error.is_none() as i64);
+            if cache.error.is_some() {
+                continue;
+            }
+
+            let server_info = &cache.server_info;
+            CLIENTS_ONLINE.with_label_values(&labels).set(
+                server_info
+                    .clients()
+                    .iter()
+                    .filter(|(_, c)| !c.is_query)
+                    .count() as i64,
+            );
+            MAX_CLIENTS
+                .with_label_values(&labels)
+                .set(server_info.max_clients as i64);
+            let network = &server_info.network;
+            PING.with_label_values(&labels).set(network.ping_ms);
+            PACKET_LOSS
+                .with_label_values(&labels)
+                .set(network.packet_loss);
+            BANDWIDTH_SENT
+                .with_label_values(&labels)
+                .set(network.bandwidth_sent as i64);
+            BANDWIDTH_RECEIVED
+                .with_label_values(&labels)
+                .set(network.bandwidth_received as i64);
+            let channels = observe_channels(&labels, &server_info.channels);
+            CHANNELS.with_label_values(&labels).set(channels);
+        }
+    }
+
+    let mut buffer = Vec::new();
+    if let Err(e) = TextEncoder::new().encode(&prometheus::gather(), &mut buffer) {
+        log::error!("Can not encode metrics: {}", e);
+    }
+    String::from_utf8(buffer).unwrap_or_default()
+}
@@ -0,0 +1,246 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use actix_web::web;
+use ts3_query::*;
+
+use crate::{
+    backend::{RawServerInfo, RawStatus, StatusBackend},
+    config::Config,
+    graphql,
+    icons::Transfer,
+    query::Server,
+    routes, State,
+};
+
+/// A channel of a mock virtual server.
+#[derive(Clone, Debug, Default)]
+pub struct MockChannel {
+    pub id: u64,
+    /// Id of the parent channel, 0 for top level channels
+    pub parent_id: u64,
+    /// Id of the channel this one is sorted below, 0 for the first
+    pub order: u64,
+    pub name: String,
+    pub topic: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A client of a mock virtual server.
+#[derive(Clone, Debug, Default)]
+pub struct MockClient {
+    pub id: u16,
+    pub channel_id: u64,
+    pub nickname: String,
+    pub unique_id: String,
+    pub is_query: bool,
+    pub is_away: bool,
+    pub server_groups: Vec<u64>,
+    pub country: String,
+}
+
+/// A mock virtual server.
+#[derive(Clone, Debug, Default)]
+pub struct MockServer {
+    pub name: String,
+    pub max_clients: u32,
+    pub channels: Vec<MockChannel>,
+    pub clients: Vec<MockClient>,
+    /// Server group names by id
+    pub server_groups: HashMap<u64, String>,
+}
+
+/// In-memory `StatusBackend` that answers with the status of its mock virtual servers, so the
+/// layers above the query connection can be tested without a TS3 host. Clones share their state,
+/// so a test can keep one to change the servers and inspect the number of fetches.
+#[derive(Clone, Debug, Default)]
+pub struct MockBackend {
+    servers: Arc<Mutex<HashMap<u64, MockServer>>>,
+    /// Whether fetches fail as if the TS3 host was unreachable
+    down: Arc<Mutex<bool>>,
+    fetches: Arc<AtomicUsize>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace the virtual server `server_id`.
+    pub fn set_server(&self, server_id: u64, server: MockServer) {
+        self.servers
+            .lock()
+            .expect("can't lock mock servers")
+            .insert(server_id, server);
+    }
+
+    /// Change the virtual server `server_id` with `f`, if it exists.
+    pub fn modify<F: FnOnce(&mut MockServer)>(&self, server_id: u64, f: F) {
+        if let Some(server) = self
+            .servers
+            .lock()
+            .expect("can't lock mock servers")
+            .get_mut(&server_id)
+        {
+            f(server);
+        }
+    }
+
+    /// Let fetches fail, or succeed again.
+    pub fn set_down(&self, down: bool) {
+        *self.down.lock().expect("can't lock mock state") = down;
+    }
+
+    /// Number of fetches so far, including failed ones.
+    pub fn fetches(&self) -> usize {
+        self.fetches.load(Ordering::SeqCst)
+    }
+}
+
+impl StatusBackend for MockBackend {
+    fn fetch(&mut self, server_id: u64, descriptions: bool) -> Result<RawStatus, Ts3Error> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        if *self.down.lock().expect("can't lock mock state") {
+            return Err(
+                io::Error::new(ErrorKind::ConnectionRefused, "mock TS3 host is down").into(),
+            );
+        }
+        let servers = self.servers.lock().expect("can't lock mock servers");
+        let server = match servers.get(&server_id) {
+            Some(server) => server,
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("no mock virtual server {}", server_id),
+                )
+                .into())
+            }
+        };
+
+        let server_info = RawServerInfo {
+            name: server.name.clone(),
+            version: "3.13.7 [Build: 1655727713]".to_string(),
+            platform: "Linux".to_string(),
+            clients_online: server.clients.len() as u32,
+            max_clients: server.max_clients,
+            queryclients_online: server.clients.iter().filter(|c| c.is_query).count() as u32,
+            ..Default::default()
+        };
+
+        Ok(RawStatus {
+            server_info,
+            channels: server.channels.iter().map(channel).collect(),
+            clients: server.clients.iter().map(client).collect(),
+            connected_since: HashMap::new(),
+            channel_groups: HashMap::new(),
+            server_groups: server.server_groups.clone(),
+            descriptions: if descriptions {
+                server
+                    .channels
+                    .iter()
+                    .filter_map(|c| Some((c.id, c.description.clone()?)))
+                    .collect()
+            } else {
+                HashMap::new()
+            },
+        })
+    }
+
+    fn init_download(
+        &mut self,
+        _server_id: u64,
+        _icon_id: u64,
+    ) -> Result<Option<Transfer>, Ts3Error> {
+        Ok(None)
+    }
+
+    fn keepalive(&mut self) -> Result<(), Ts3Error> {
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<(), Ts3Error> {
+        Ok(())
+    }
+}
+
+/// Configure the routes of the binary for `servers`, usually with mock backends, without its
+/// middleware and background tasks, for `App::configure` in tests.
+pub fn routes_for(
+    cfg: Config,
+    servers: HashMap<String, Server>,
+) -> impl FnOnce(&mut web::ServiceConfig) {
+    let state = State {
+        cfg,
+        servers,
+        graphql: graphql::schema(),
+        history: None,
+    };
+    move |service| {
+        service.data(state);
+        routes(service);
+    }
+}
+
+fn channel(channel: &MockChannel) -> ChannelFull {
+    ChannelFull {
+        cid: channel.id,
+        pid: channel.parent_id,
+        channel_order: channel.order,
+        channel_name: channel.name.clone(),
+        total_clients: 0,
+        channel_needed_subscribe_power: 0,
+        channel_topic: channel.topic.clone(),
+        channel_flag_default: false,
+        channel_flag_password: false,
+        channel_flag_permanent: true,
+        channel_flag_semi_permanent: false,
+        channel_codec: 4,
+        channel_codec_quality: 6,
+        channel_needed_talk_power: 0,
+        channel_icon_id: 0,
+        seconds_empty: 0,
+        total_clients_family: 0,
+        channel_maxclients: -1,
+        channel_maxfamilyclients: -1,
+    }
+}
+
+fn client(client: &MockClient) -> OnlineClientFull {
+    OnlineClientFull {
+        clid: client.id,
+        cid: client.channel_id,
+        client_database_id: u64::from(client.id),
+        client_nickname: client.nickname.clone(),
+        client_type: client.is_query as i8,
+        client_away: client.is_away,
+        client_away_message: None,
+        client_flag_talking: false,
+        client_input_muted: false,
+        client_output_muted: false,
+        client_input_hardware: true,
+        client_output_hardware: true,
+        client_talk_power: 0,
+        client_is_talker: false,
+        client_is_priority_speaker: false,
+        client_is_recording: false,
+        client_is_channel_commander: false,
+        client_unique_identifier: client.unique_id.clone(),
+        client_servergroups: client.server_groups.clone(),
+        client_channel_group_id: 0,
+        client_channel_group_inherited_channel_id: client.channel_id,
+        client_version: "3.6.2".to_string(),
+        client_platform: "Linux".to_string(),
+        client_idle_time: 0,
+        client_created: 0,
+        client_lastconnected: 0,
+        client_country: client.country.clone(),
+        connection_client_ip: String::new(),
+        client_badges: None,
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::{HashMap, HashSet},
+    thread,
+    time::Duration,
+};
+
+use futures::{channel::mpsc::UnboundedReceiver, StreamExt};
+use log::{error, info, warn};
+use rumqttc::{Client, Connection, Event, LastWill, MqttOptions, Packet, QoS};
+use serde::Serialize;
+
+use crate::{
+    events::client_event_stream,
+    query::{ChannelNode, ServerInfo, StatusUpdate},
+};
+
+// Ping the broker this often
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+// Wait this long before reconnecting after the connection to the broker failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// Messages queued while the broker is unreachable, later ones are dropped
+const QUEUE_CAPACITY: usize = 1000;
+
+/// MQTT broker the status is published to.
+#[derive(Clone, Debug, Default)]
+pub struct BrokerOptions {
+    /// Host name of the broker, publishing is disabled if unset
+    pub host: Option<String>,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: String,
+    /// Prefix of every topic, without trailing slash
+    pub topic_prefix: String,
+    /// Prefix of the Home Assistant discovery topics, nothing is announced to Home Assistant if
+    /// unset
+    pub discovery_prefix: Option<String>,
+}
+
+impl BrokerOptions {
+    pub fn enabled(&self) -> bool {
+        self.host.is_some()
+    }
+}
+
+/// Connection to the MQTT broker, messages are sent by a background thread which reconnects
+/// whenever the connection fails.
+#[derive(Clone)]
+pub struct Publisher {
+    client: Client,
+    topic_prefix: String,
+}
+
+impl Publisher {
+    /// Connect to the broker of `options` in the background. Panics if no host is configured.
+    pub fn connect(options: &BrokerOptions) -> Self {
+        let host = options.host.clone().expect("MQTT_HOST not set");
+        let mut mqtt_options = MqttOptions::new(&options.client_id, host, options.port);
+        mqtt_options.set_keep_alive(KEEP_ALIVE);
+        if let (Some(username), Some(password)) = (&options.username, &options.password) {
+            mqtt_options.set_credentials(username, password);
+        }
+        let availability = format!("{}/available", options.topic_prefix);
+        mqtt_options.set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+
+        let (client, connection) = Client::new(mqtt_options, QUEUE_CAPACITY);
+        let publisher = Publisher {
+            client,
+            topic_prefix: options.topic_prefix.clone(),
+        };
+        let announcer = publisher.clone();
+        thread::Builder::new()
+            .name("mqtt".to_string())
+            .spawn(move || run(connection, announcer, availability))
+            .expect("can't spawn MQTT thread");
+        publisher
+    }
+
+    /// `topic` below the topic prefix.
+    fn topic(&self, topic: &str) -> String {
+        format!("{}/{}", self.topic_prefix, topic)
+    }
+
+    /// Queue `payload` for `topic` below the topic prefix, dropping it if the queue is full.
+    fn publish(&self, topic: &str, retain: bool, payload: impl Into<Vec<u8>>) {
+        self.publish_to(&self.topic(topic), retain, payload);
+    }
+
+    /// Like `publish`, but for `topic` as is instead of below the topic prefix.
+    fn publish_to(&self, topic: &str, retain: bool, payload: impl Into<Vec<u8>>) {
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtLeastOnce, retain, payload)
+        {
+            warn!("Can't publish {}: {}", topic, e);
+        }
+    }
+}
+
+/// Drive `connection`, announcing the service as available on `availability` after every
+/// (re)connect. The broker announces it as unavailable once the connection is lost.
+fn run(mut connection: Connection, publisher: Publisher, availability: String) {
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT broker");
+                if let Err(e) =
+                    publisher
+                        .client
+                        .try_publish(&availability, QoS::AtLeastOnce, true, "online")
+                {
+                    warn!("Can't publish {}: {}", availability, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("MQTT connection failed: {}", e);
+                thread::sleep(RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+/// Device of the sensors of a virtual server in Home Assistant.
+#[derive(Serialize)]
+struct Device<'a> {
+    identifiers: [&'a str; 1],
+    name: &'a str,
+    manufacturer: &'static str,
+    model: &'static str,
+    sw_version: &'a str,
+}
+
+/// Discovery payload of a Home Assistant sensor holding a client count.
+#[derive(Serialize)]
+struct Sensor<'a> {
+    name: &'a str,
+    unique_id: String,
+    state_topic: String,
+    availability_topic: String,
+    unit_of_measurement: &'static str,
+    state_class: &'static str,
+    icon: &'static str,
+    device: &'a Device<'a>,
+}
+
+/// Announces the client counts of a virtual server as sensors to Home Assistant with retained
+/// messages below `<discovery prefix>/sensor/<device id>/`.
+struct Discovery {
+    prefix: String,
+    device_id: String,
+    /// Payloads published so far by object id, only changed ones are published again
+    published: HashMap<String, Vec<u8>>,
+}
+
+impl Discovery {
+    fn new(prefix: String, publisher: &Publisher, base: &str) -> Self {
+        // Ids in discovery topics may only contain alphanumerics, underscores and hyphens
+        let device_id = publisher
+            .topic(base)
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => c,
+                _ => '_',
+            })
+            .collect();
+        Self {
+            prefix,
+            device_id,
+            published: HashMap::new(),
+        }
+    }
+
+    /// Announce the sensors for `server_info`, whose states are published below `base`. Sensors
+    /// of deleted channels are removed and those of renamed ones updated.
+    fn announce(&mut self, publisher: &Publisher, base: &str, server_info: &ServerInfo) {
+        fn collect<'a>(channels: &'a [ChannelNode], out: &mut Vec<&'a ChannelNode>) {
+            for channel in channels {
+                out.push(channel);
+                collect(&channel.children, out);
+            }
+        }
+
+        let device = Device {
+            identifiers: [&self.device_id],
+            name: &server_info.name,
+            manufacturer: "TeamSpeak",
+            model: "Virtual server",
+            sw_version: &server_info.version,
+        };
+        let sensor = |object_id: &str, name: &str, topic: &str| {
+            let sensor = Sensor {
+                name,
+                unique_id: format!("{}_{}", self.device_id, object_id),
+                state_topic: publisher.topic(&format!("{}/{}", base, topic)),
+                availability_topic: publisher.topic("available"),
+                unit_of_measurement: "clients",
+                state_class: "measurement",
+                icon: "mdi:account-multiple",
+                device: &device,
+            };
+            serde_json::to_vec(&sensor).map(|payload| (object_id.to_string(), payload))
+        };
+
+        let mut channels = Vec::new();
+        collect(&server_info.channels, &mut channels);
+        let sensors: Result<HashMap<String, Vec<u8>>, _> =
+            std::iter::once(sensor("clients_online", "Clients online", "clients_online"))
+                .chain(channels.iter().filter(|c| !c.is_spacer).map(|channel| {
+                    sensor(
+                        &format!("channel_{}", channel.id),
+                        &channel.name,
+                        &format!("channels/{}/clients", channel.id),
+                    )
+                }))
+                .collect();
+        let sensors = match sensors {
+            Ok(sensors) => sensors,
+            Err(e) => {
+                error!("Can not serialize discovery payload: {}", e);
+                return;
+            }
+        };
+
+        for (object_id, payload) in &sensors {
+            if self.published.get(object_id) != Some(payload) {
+                publisher.publish_to(&self.topic(object_id), true, payload.clone());
+            }
+        }
+        // An empty retained payload removes the sensor from Home Assistant
+        for object_id in self.published.keys() {
+            if !sensors.contains_key(object_id) {
+                publisher.publish_to(&self.topic(object_id), true, "");
+            }
+        }
+        self.published = sensors;
+    }
+
+    fn topic(&self, object_id: &str) -> String {
+        format!(
+            "{}/sensor/{}/{}/config",
+            self.prefix, self.device_id, object_id
+        )
+    }
+}
+
+/// Publish the status of the virtual server `server_id` of the TS3 host `server` after every
+/// refresh, below `<prefix>/<server>/<server_id>/`:
+///
+/// * `clients_online`, the number of clients, retained
+/// * `channels/<channel id>/clients`, the number of clients in each channel, retained
+/// * `events`, a JSON object for every client joining or leaving
+///
+/// With a `discovery_prefix`, the client counts are announced as sensors to Home Assistant.
+pub async fn publish_status(
+    publisher: Publisher,
+    server: String,
+    server_id: u64,
+    current: Option<ServerInfo>,
+    updates: UnboundedReceiver<StatusUpdate>,
+    include_query: bool,
+    discovery_prefix: Option<String>,
+) {
+    let base = format!("{}/{}", server, server_id);
+    let mut discovery = discovery_prefix.map(|prefix| Discovery::new(prefix, &publisher, &base));
+    let mut channels: HashSet<u64> = HashSet::new();
+    let mut changes = Box::pin(client_event_stream(current, updates, include_query));
+    while let Some((server_info, events)) = changes.next().await {
+        // Announce sensors before their first state, so Home Assistant picks it up
+        if let Some(discovery) = discovery.as_mut() {
+            discovery.announce(&publisher, &base, &server_info);
+        }
+        let clients = server_info.clients().len();
+        publisher.publish(
+            &format!("{}/clients_online", base),
+            true,
+            clients.to_string(),
+        );
+
+        let counts = server_info.channel_counts();
+        let current: HashSet<u64> = counts.iter().map(|(id, _)| *id).collect();
+        for (id, count) in counts {
+            publisher.publish(
+                &format!("{}/channels/{}/clients", base, id),
+                true,
+                count.to_string(),
+            );
+        }
+        // An empty retained message removes the count of deleted channels from the broker
+        for id in channels.difference(&current) {
+            publisher.publish(&format!("{}/channels/{}/clients", base, id), true, "");
+        }
+        channels = current;
+
+        for event in &events {
+            match serde_json::to_vec(event) {
+                Ok(payload) => publisher.publish(&format!("{}/events", base), false, payload),
+                Err(e) => error!("Can not serialize client event: {}", e),
+            }
+        }
+    }
+}
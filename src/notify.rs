@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Write},
+    net::{Shutdown, TcpStream},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use actix_web::rt::time;
+use futures::{
+    channel::mpsc::{self, UnboundedSender},
+    StreamExt,
+};
+use log::{debug, error, info, trace};
+use ts3_query::{raw, OnlineClientFull};
+
+use crate::{
+    compat,
+    config::{Config, ServerConfig},
+    query::{self, Server, SharedCache},
+    resolve,
+};
+
+// Ping the notification connection after it was idle this long, TS3 closes idle connections
+// after five minutes by default
+const IDLE_TIMEOUT: Duration = Duration::from_secs(query::KEEPALIVE_INTERVAL);
+
+// Wait this long before reconnecting after the notification connection failed
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+// Refresh once for all notifications arriving within this period, e.g. when a channel is created
+// together with its subchannels
+const REFRESH_DELAY: Duration = Duration::from_secs(1);
+
+/// Change of a virtual server reported by a notification.
+#[derive(Debug)]
+enum Event {
+    /// A client entered the server, its `cid` is the channel it entered
+    ClientEntered(Box<OnlineClientFull>),
+    /// The client with this id left the server
+    ClientLeft(u16),
+    ClientMoved {
+        client: u16,
+        channel: u64,
+    },
+    /// Anything the status can't be updated with in place, such as channels being edited, or
+    /// notifications were possibly missed
+    Changed,
+}
+
+/// Parse the value of `key`, `None` if it's missing or malformed.
+fn field<T: FromStr>(entry: &HashMap<String, Option<String>>, key: &str) -> Option<T> {
+    entry.get(key)?.as_ref()?.parse().ok()
+}
+
+/// The events of the notification `line`, nothing if it isn't a notification.
+fn parse(line: &str) -> Vec<Event> {
+    let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+    let entries = raw::parse_multi_hashmap(vec![args.to_string()], true);
+    match name {
+        // Entering clients are described like in `clientlist`, but with the channel as `ctid`
+        "notifycliententerview" => entries
+            .into_iter()
+            .map(|mut entry| {
+                let channel = entry.remove("ctid").flatten();
+                entry.insert("cid".to_string(), channel);
+                Event::ClientEntered(Box::new(compat::online_client(&entry)))
+            })
+            .collect(),
+        "notifyclientleftview" => entries
+            .iter()
+            .filter_map(|entry| Some(Event::ClientLeft(field(entry, "clid")?)))
+            .collect(),
+        "notifyclientmoved" => {
+            // Clients moved together only name the target channel once
+            let channel = match entries.first().and_then(|entry| field(entry, "ctid")) {
+                Some(channel) => channel,
+                None => return vec![Event::Changed],
+            };
+            entries
+                .iter()
+                .filter_map(|entry| {
+                    Some(Event::ClientMoved {
+                        client: field(entry, "clid")?,
+                        channel,
+                    })
+                })
+                .collect()
+        }
+        name if name.starts_with("notify") => vec![Event::Changed],
+        _ => Vec::new(),
+    }
+}
+
+/// Connection of the current subscription, shared so the session can be quit when the events are
+/// no longer followed.
+type Session = Arc<Mutex<Option<TcpStream>>>;
+
+/// Quits the session when dropped, which also ends the blocking read of the subscription.
+struct QuitOnDrop(Session);
+
+impl Drop for QuitOnDrop {
+    fn drop(&mut self) {
+        if let Some(mut stream) = self.0.lock().expect("can't lock session").take() {
+            let _ = writeln!(stream, "quit");
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// A query connection subscribed to the notifications of a virtual server. `QueryClient` only
+/// reads responses to its own commands, so this speaks the query protocol itself.
+struct Subscription {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// The line read so far, kept when reading times out halfway
+    line: Vec<u8>,
+}
+
+impl Subscription {
+    fn connect(cfg: &Config, server: &ServerConfig, server_id: u64) -> io::Result<Self> {
+        let host = resolve::ts3_host(server)?;
+        let connect_timeout = Duration::from_secs(cfg.ts3_connect_timeout);
+        let addr = resolve::query_address(server, &host, connect_timeout)?;
+        let stream = TcpStream::connect_timeout(&addr, connect_timeout)?;
+        let command_timeout = Some(Duration::from_secs(cfg.ts3_command_timeout));
+        stream.set_read_timeout(command_timeout)?;
+        stream.set_write_timeout(command_timeout)?;
+
+        let mut subscription = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            line: Vec::new(),
+        };
+        // Skip the `TS3` header and welcome message
+        subscription.read_line()?;
+        subscription.read_line()?;
+        if server.ts3_query_protocol.needs_login() {
+            subscription.command(&format!(
+                "login {} {}",
+                raw::escape_arg(&server.user),
+                raw::escape_arg(&server.password)
+            ))?;
+        }
+        subscription.command(&format!("use sid={}", server_id))?;
+        if !cfg.query_nickname.is_empty() {
+            // The nickname is cosmetic and the refreshing session may use it already
+            let nickname = format!("{} events", cfg.query_nickname);
+            if let Err(e) = subscription.command(&format!(
+                "clientupdate client_nickname={}",
+                raw::escape_arg(&nickname)
+            )) {
+                debug!("Can't set nickname {}: {}", nickname, e);
+            }
+        }
+        subscription.command("servernotifyregister event=server")?;
+        subscription.command("servernotifyregister event=channel id=0")?;
+        subscription
+            .reader
+            .get_ref()
+            .set_read_timeout(Some(IDLE_TIMEOUT))?;
+        Ok(subscription)
+    }
+
+    /// Read the next line, `None` if none arrived before the read timeout.
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        match self.reader.read_until(b'\r', &mut self.line) {
+            Ok(0) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(_) if self.line.ends_with(b"\r") => {
+                let line = String::from_utf8_lossy(&self.line).trim().to_string();
+                self.line.clear();
+                Ok(Some(line))
+            }
+            Ok(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Send `command` and wait for its error line, failing unless it reports success.
+    fn command(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.writer, "{}", command)?;
+        loop {
+            match self.read_line()? {
+                Some(line) if line.starts_with("error ") => {
+                    let response = raw::parse_hashmap(vec![line], true);
+                    return match field::<u32>(&response, "id") {
+                        Some(0) => Ok(()),
+                        _ => Err(io::Error::other(
+                            field::<String>(&response, "msg").unwrap_or_default(),
+                        )),
+                    };
+                }
+                Some(_) => {}
+                None => return Err(io::ErrorKind::TimedOut.into()),
+            }
+        }
+    }
+
+    /// Send the events of incoming notifications to `events` until the connection fails or
+    /// `events` is closed.
+    fn forward(&mut self, events: &UnboundedSender<Event>) -> io::Result<()> {
+        loop {
+            let line = match self.read_line()? {
+                Some(line) => line,
+                None => {
+                    trace!("Sending keepalive on notification connection");
+                    writeln!(self.writer, "whoami")?;
+                    continue;
+                }
+            };
+            for event in parse(&line) {
+                if events.unbounded_send(event).is_err() {
+                    return writeln!(self.writer, "quit");
+                }
+            }
+        }
+    }
+}
+
+/// Keep a subscription to the notifications of the virtual server `server_id` and send their
+/// events to `events`, reconnecting whenever the connection fails. Blocks until `events` is closed.
+fn listen(
+    cfg: Config,
+    server: ServerConfig,
+    server_id: u64,
+    events: UnboundedSender<Event>,
+    session: Session,
+) {
+    while !events.is_closed() {
+        let result =
+            Subscription::connect(&cfg, &server, server_id).and_then(|mut subscription| {
+                *session.lock().expect("can't lock session") =
+                    Some(subscription.writer.try_clone()?);
+                info!(
+                    "Subscribed to notifications of {} server {}",
+                    server.name, server_id
+                );
+                // Notifications while not subscribed were missed
+                let _ = events.unbounded_send(Event::Changed);
+                subscription.forward(&events)
+            });
+        // The session was quit because the events are no longer followed
+        if events.is_closed() {
+            return;
+        }
+        if let Err(e) = result {
+            error!(
+                "Notification connection to {} server {} failed: {}",
+                server.name, server_id, e
+            );
+            thread::sleep(RECONNECT_DELAY);
+        }
+    }
+}
+
+/// Keep `cache` up to date with the notifications of the virtual server `server_id`. Clients
+/// entering, leaving and moving are applied to the cached status, other changes refresh it. The
+/// notification session is quit once this is dropped, e.g. when aborted on shutdown.
+pub async fn follow(cfg: Config, server: Server, server_id: u64, cache: Arc<SharedCache>) {
+    let session = Session::default();
+    let listener_session = session.clone();
+    // Declared before the channel, so the events are closed by the time the session is quit
+    let _quit = QuitOnDrop(session);
+    let (tx, mut rx) = mpsc::unbounded();
+    let server_cfg = server.cfg.clone();
+    thread::Builder::new()
+        .name(format!("notify-{}-{}", server.cfg.name, server_id))
+        .spawn(move || listen(cfg, server_cfg, server_id, tx, listener_session))
+        .expect("can't spawn notification thread");
+
+    while let Some(event) = rx.next().await {
+        trace!(
+            "Notification from {} server {}: {:?}",
+            server.cfg.name,
+            server_id,
+            event
+        );
+        let refresh = match event {
+            Event::ClientEntered(client) => match server.entered_client(server_id, &client) {
+                // The channel may be new, or the client already known from a refresh
+                Some(entry) => {
+                    !cache.modify(|server_info| server_info.add_client(entry, client.cid))
+                }
+                None => false,
+            },
+            Event::ClientLeft(client) => {
+                cache.modify(|server_info| server_info.remove_client(client));
+                false
+            }
+            Event::ClientMoved { client, channel } => {
+                cache.modify(|server_info| server_info.move_client(client, channel));
+                false
+            }
+            Event::Changed => true,
+        };
+        if refresh {
+            time::delay_for(REFRESH_DELAY).await;
+            // The refresh covers everything notified in the meantime
+            while rx.try_recv().is_ok() {}
+            info!(
+                "Refreshing TS3 server status for {} server {} after notification",
+                server.cfg.name, server_id
+            );
+            if let Err(e) = query::refresh_shared(&server, server_id, &cache).await {
+                error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+            }
+        }
+    }
+}
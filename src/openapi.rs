@@ -0,0 +1,124 @@
+use utoipa::{
+    openapi::{
+        self,
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+        Server,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    banner::Theme,
+    delta::{ChannelChange, Delta},
+    events::{ClientEvent, ClientEventKind},
+    format::Format,
+    history::{BusiestChannel, ChannelCounts, ClientCounts, OnlineTime, Peak, PeriodStats, Stats},
+    query::{ChannelNode, Client, Codec, Network, ServerDetails, ServerInfo},
+    spacer::{Alignment, Spacer},
+    view::{ChannelClient, FlatChannel, Sort, StatusView},
+    CacheReadiness, ChannelResponse, ClientsResponse, CountResponse, DeltaResponse,
+    DetailsResponse, HistoryResponse, JsonResponse, LeaderboardResponse, ReadinessResponse,
+    ServerResponse, ShieldResponse, StatsResponse,
+};
+
+/// Swagger UI for the specification at `openapi.json`, loaded from a CDN
+pub const SWAGGER_UI_HTML: &str = include_str!("swagger.html");
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "ts3status",
+        description = "Status of TeamSpeak 3 servers. Every route is also served for the named \
+                       servers at `/{name}/...`, and with stable response shapes at `/v1/...`, \
+                       where the status of `/` is served at `/v1/status`."
+    ),
+    paths(
+        crate::status,
+        crate::server_list,
+        crate::server_by_id,
+        crate::channel_by_id,
+        crate::client_list,
+        crate::client_list_csv,
+        crate::client_count,
+        crate::shield,
+        crate::details,
+        crate::admin_refresh,
+        crate::status_delta,
+        crate::client_history,
+        crate::statistics,
+        crate::leaderboard,
+        crate::event_feed,
+        crate::event_source,
+        crate::metrics_export,
+        crate::liveness,
+        crate::readiness,
+        crate::banner_image,
+        crate::icon_image,
+    ),
+    components(schemas(
+        JsonResponse,
+        ServerResponse,
+        ChannelResponse,
+        ClientsResponse,
+        CountResponse,
+        ShieldResponse,
+        DetailsResponse,
+        DeltaResponse,
+        Delta,
+        ChannelChange,
+        ClientEvent,
+        ClientEventKind,
+        HistoryResponse,
+        ClientCounts,
+        ChannelCounts,
+        StatsResponse,
+        Stats,
+        PeriodStats,
+        Peak,
+        BusiestChannel,
+        LeaderboardResponse,
+        OnlineTime,
+        ReadinessResponse,
+        CacheReadiness,
+        StatusView,
+        ServerInfo,
+        ServerInfo<FlatChannel>,
+        ServerDetails,
+        Network,
+        ChannelNode,
+        FlatChannel,
+        Client,
+        ChannelClient,
+        Codec,
+        Spacer,
+        Alignment,
+        Sort,
+        Format,
+        Theme,
+    )),
+    modifiers(&ApiKeyScheme)
+)]
+pub struct ApiDoc;
+
+/// The specification of the service with its routes below `base_path`, see `Config::base_path`.
+pub fn spec(base_path: &str) -> openapi::OpenApi {
+    let mut spec = ApiDoc::openapi();
+    if !base_path.is_empty() {
+        spec.servers = Some(vec![Server::new(base_path)]);
+    }
+    spec
+}
+
+/// Describes the `X-Api-Key` header required by protected endpoints.
+struct ApiKeyScheme;
+
+impl Modify for ApiKeyScheme {
+    fn modify(&self, openapi: &mut openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+            );
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::str::FromStr;
+
+use crate::query::Client;
+
+/// How clients are anonymized in the status.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyMode {
+    /// Clients are shown as they are
+    Off,
+    /// Nicknames are replaced by a keyed hash of the client's unique id, which stays the same
+    /// across reconnects
+    Hash,
+    /// Nicknames are replaced by "Client #<client id>"
+    Numbered,
+}
+
+impl FromStr for PrivacyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(PrivacyMode::Off),
+            "hash" => Ok(PrivacyMode::Hash),
+            "numbered" => Ok(PrivacyMode::Numbered),
+            _ => Err(format!(
+                "unknown privacy mode {}, expected off, hash or numbered",
+                s
+            )),
+        }
+    }
+}
+
+/// A new random secret to key hashes with.
+pub fn random_secret() -> String {
+    let mut secret = [0; 32];
+    getrandom::getrandom(&mut secret).expect("can't generate a random secret");
+    base64::encode(secret)
+}
+
+/// Replace the nickname of `client`, whose unique id is `unique_id`, and remove its country. The
+/// unique id is replaced by the new nickname as well, so it doesn't end up in the history. Hashes
+/// are HMAC-SHA256 keyed with `secret`, so unique ids can't be guessed from them without it.
+pub fn anonymize(mode: PrivacyMode, secret: &[u8], client: &mut Client, unique_id: &str) {
+    client.nickname = match mode {
+        PrivacyMode::Off => return,
+        PrivacyMode::Hash => {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes keys of any size");
+            mac.update(unique_id.as_bytes());
+            let hash = mac.finalize().into_bytes();
+            let hex: String = hash[..6]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            format!("Client {}", hex)
+        }
+        PrivacyMode::Numbered => format!("Client #{}", client.id),
+    };
+    client.unique_id = client.nickname.clone();
+    client.country = String::new();
+    client.country_name = None;
+    client.country_flag = None;
+}
@@ -1,157 +1,1621 @@
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock},
-    time::Instant,
+    collections::{HashMap, HashSet, VecDeque},
+    convert::TryFrom,
+    mem,
+    ops::Sub,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use log::{error, info, trace};
-use serde::Serialize;
+use actix_web::{
+    error::BlockingError,
+    http::StatusCode,
+    rt::{self, time},
+    web::{self, Bytes},
+};
+use arc_swap::ArcSwap;
+use async_graphql::{Enum, OutputType, SimpleObject};
+use futures::channel::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+use log::{debug, error, info, trace};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{info_span, instrument};
 use ts3_query::*;
+use utoipa::ToSchema;
 
-use crate::Config;
+use crate::{
+    backend::{ConnectedSince, RawStatus, StatusBackend},
+    breaker::CircuitBreaker,
+    compat::ServerVersion,
+    config::{Config, ServerConfig},
+    countries, delta,
+    error::{ErrorCode, QueryError},
+    events::{self, ServerEvent},
+    format::Format,
+    metrics,
+    privacy::{self, PrivacyMode},
+    resolve,
+    spacer::{self, Spacer},
+    webquery::WebQuery,
+};
 
-// Update server status every 20 seconds at the earliest
+// Update server status every 20 seconds at the earliest, unless configured otherwise
 pub const CACHE_LIFETIME: u64 = 20;
 
-#[derive(Clone, Default, Serialize)]
+// Wait this long before querying a TS3 host again after a failed refresh, doubling with every
+// consecutive failure up to MAX_FAILURE_BACKOFF
+const FAILURE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_FAILURE_BACKOFF: Duration = Duration::from_secs(60);
+
+// Keep the snapshots of this many revisions for deltas
+const REVISION_HISTORY: usize = 32;
+
+// Keep this many events for the event feed
+const EVENT_LOG: usize = 50;
+
+// Keep this many rendered response bodies per virtual server, so arbitrary views can't exhaust
+// memory
+const RENDERED_BODIES: usize = 32;
+
+// Ping idle query connections every minute, TS3 closes them after five minutes by default
+pub const KEEPALIVE_INTERVAL: u64 = 60;
+
+#[derive(Clone, Default, PartialEq, Serialize, SimpleObject, ToSchema)]
 pub struct Client {
+    /// Client id, unique while the client is connected
+    pub id: u16,
+    /// Identity of the client across connections, not exposed
+    #[serde(skip)]
+    #[graphql(skip)]
+    pub unique_id: String,
     pub nickname: String,
+    /// ISO 3166-1 alpha-2 country code, empty if unknown
     pub country: String,
+    pub country_name: Option<String>,
+    pub country_flag: Option<String>,
     pub input_muted: bool,
     pub output_muted: bool,
     pub away: bool,
+    /// Time since the client was last active in milliseconds
+    pub idle_ms: u64,
+    /// Unix timestamp of when the client connected, if known
+    pub connected_since: Option<u64>,
+    pub talk_power: i32,
+    pub is_channel_commander: bool,
+    pub channel_group_id: u64,
+    pub channel_group_name: Option<String>,
+    /// Names of the server groups of the client
+    pub groups: Vec<String>,
+    pub platform: Option<String>,
+    pub version: Option<String>,
+    /// Whether this is a ServerQuery client instead of a user
+    pub is_query: bool,
 }
 
 impl From<&OnlineClientFull> for Client {
     fn from(client: &OnlineClientFull) -> Self {
         Self {
+            id: client.clid,
+            unique_id: client.client_unique_identifier.clone(),
             nickname: client.client_nickname.clone(),
             country: client.client_country.clone(),
+            country_name: countries::name(&client.client_country, None).map(str::to_string),
+            country_flag: countries::flag(&client.client_country),
             input_muted: client.client_input_muted,
             output_muted: client.client_output_muted,
             away: client.client_away,
+            idle_ms: client.client_idle_time.max(0) as u64,
+            connected_since: None,
+            talk_power: client.client_talk_power,
+            is_channel_commander: client.client_is_channel_commander,
+            channel_group_id: client.client_channel_group_id,
+            channel_group_name: None,
+            groups: Vec::new(),
+            platform: Some(client.client_platform.clone()),
+            version: Some(client.client_version.clone()),
+            is_query: client.client_type == 1,
+        }
+    }
+}
+
+/// Voice codec of a channel.
+#[derive(Clone, Copy, Debug, Default, Enum, Eq, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    SpeexNarrowband,
+    SpeexWideband,
+    SpeexUltrawideband,
+    CeltMono,
+    #[default]
+    OpusVoice,
+    OpusMusic,
+    Unknown,
+}
+
+impl From<i32> for Codec {
+    fn from(codec: i32) -> Self {
+        match codec {
+            0 => Codec::SpeexNarrowband,
+            1 => Codec::SpeexWideband,
+            2 => Codec::SpeexUltrawideband,
+            3 => Codec::CeltMono,
+            4 => Codec::OpusVoice,
+            5 => Codec::OpusMusic,
+            _ => Codec::Unknown,
         }
     }
 }
 
-#[derive(Clone, Default, Serialize)]
+#[derive(Clone, Default, PartialEq, Serialize, SimpleObject, ToSchema)]
 pub struct ChannelNode {
     pub id: u64,
+    /// Id of the sibling channel sorted before this one, 0 for the first channel
+    #[serde(skip)]
+    #[graphql(skip)]
+    pub order: u64,
     pub name: String,
+    pub is_spacer: bool,
+    /// Alignment and text of spacer channels
+    pub spacer: Option<Spacer>,
+    /// Icon served at `/icons/{icon_id}`, 0 if the channel has none
+    pub icon_id: u64,
+    pub topic: Option<String>,
+    /// Only queried with `channel_descriptions` enabled
+    pub description: Option<String>,
+    pub codec: Codec,
+    /// Codec quality from 0 to 10
+    pub codec_quality: u8,
+    /// Maximum number of clients, `None` if unlimited
+    pub max_clients: Option<u32>,
+    pub is_permanent: bool,
+    pub is_semi_permanent: bool,
+    pub has_password: bool,
+    /// Whether clients join this channel when connecting
+    pub is_default: bool,
+    /// Whether the parent channel is missing from the channel list, so this channel is shown at
+    /// the top level instead
+    pub orphan: bool,
     pub clients: Vec<Client>,
+    #[schema(no_recursion)]
     pub children: Vec<ChannelNode>,
 }
 
-#[derive(Clone, Default, Serialize)]
-pub struct ServerInfo {
+/// Status of a virtual server, `C` is the representation of its channels.
+#[derive(Clone, Default, PartialEq, Serialize, SimpleObject, ToSchema)]
+#[graphql(concrete(name = "ServerInfo", params(ChannelNode)))]
+pub struct ServerInfo<C: OutputType = ChannelNode> {
     pub name: String,
     pub version: String,
     pub platform: String,
-    pub channels: Vec<ChannelNode>,
+    /// Clients online, including the `queryclients_online` ServerQuery clients
+    pub clients_online: u32,
+    pub max_clients: u32,
+    /// Slots of `max_clients` reserved for clients with the reserved slot permission
+    pub reserved_slots: u32,
+    pub queryclients_online: u32,
+    pub uptime_seconds: u64,
+    pub network: Network,
+    /// Icon served at `/icons/{icon_id}`, 0 if the server has none
+    pub icon_id: u64,
+    pub channels: Vec<C>,
+}
+
+/// Connection statistics of a virtual server.
+#[derive(Clone, Default, PartialEq, Serialize, SimpleObject, ToSchema)]
+pub struct Network {
+    /// Average ping of all clients in milliseconds
+    pub ping_ms: f64,
+    /// Average packet loss of all clients, between 0 and 1
+    pub packet_loss: f64,
+    /// Bytes sent during the last second
+    pub bandwidth_sent: u64,
+    /// Bytes received during the last second
+    pub bandwidth_received: u64,
 }
 
+/// Details of a virtual server from `serverinfo`, these change with every refresh and are kept
+/// apart from the channel tree.
+#[derive(Clone, Default, Serialize, SimpleObject, ToSchema)]
+pub struct ServerDetails {
+    pub name: String,
+    pub uptime_seconds: u64,
+    pub clients_online: u32,
+    pub max_clients: u32,
+    pub host_message: String,
+    pub welcome_message: String,
+    /// Bytes sent during the last second
+    pub bandwidth_sent: u64,
+    /// Bytes received during the last second
+    pub bandwidth_received: u64,
+    /// Average ping of all clients in milliseconds
+    pub ping_ms: f64,
+    /// Average packet loss of all clients, between 0 and 1
+    pub packet_loss: f64,
+}
+
+/// Sent to subscribers of a `StatusCache` after every successful refresh and every change
+/// applied in place.
+#[derive(Clone)]
+pub struct StatusUpdate {
+    pub server_info: Arc<ServerInfo>,
+    /// Whether the status differs from the previous one
+    pub changed: bool,
+    /// Whether the status was refreshed, rather than changed in place after a notification
+    pub refresh: bool,
+}
+
+/// A change of a virtual server together with the revision it led to and when it was noticed.
+#[derive(Clone)]
+pub struct LoggedEvent {
+    pub revision: u64,
+    /// Position among the events of the revision
+    pub index: usize,
+    pub time: SystemTime,
+    pub event: ServerEvent,
+}
+
+/// Response body rendered from a cached status.
+#[derive(Clone)]
+pub struct Rendered {
+    pub status: StatusCode,
+    /// Entity tag of the body, which doesn't change with the age of the status
+    pub etag: String,
+    pub body: Bytes,
+}
+
+/// Snapshot of the cached status of a virtual server. Snapshots are shared by a `SharedCache` and
+/// never change once they are, changes are made to a copy.
+#[derive(Clone)]
 pub struct StatusCache {
     pub last_update: Instant,
-    pub server_info: ServerInfo,
+    /// Completion of the last refresh, whether it succeeded or not
+    pub last_attempt: Instant,
+    pub server_info: Arc<ServerInfo>,
+    pub server_details: ServerDetails,
+    /// Error of the last refresh, if it failed
+    pub error: Option<QueryError>,
+    /// Whether `server_info` holds the status of a successful refresh
+    fetched: bool,
+    /// Number of consecutive failed refreshes
+    failures: u32,
+    /// Incremented whenever the channels or clients change
+    pub revision: u64,
+    /// Incremented whenever the status or error changes, unlike `revision` also for changes that
+    /// don't show up in deltas
+    generation: u64,
+    /// Snapshots of the most recent revisions, oldest first
+    history: VecDeque<(u64, Arc<ServerInfo>)>,
+    /// The most recent changes, oldest first
+    pub events: VecDeque<LoggedEvent>,
+    /// Response bodies rendered from the current status by format and view, with the age in
+    /// seconds they were rendered at
+    rendered: HashMap<(Format, String), (Option<u64>, Rendered)>,
 }
 
-impl ChannelNode {
-    pub fn add_to_parent(&mut self, parent_id: u64, channel: &ChannelNode) {
-        if self.id == parent_id {
-            self.children.push(channel.clone());
+impl StatusCache {
+    /// An empty cache that is already expired
+    pub fn new(lifetime: u64) -> Self {
+        Self {
+            // Expired right away, the age has to exceed the lifetime
+            last_update: Instant::now().sub(Duration::from_secs(lifetime + 1)),
+            last_attempt: Instant::now(),
+            server_info: Arc::default(),
+            server_details: ServerDetails::default(),
+            error: Some(QueryError::new(
+                ErrorCode::ConnectFailed,
+                "Server status not fetched yet",
+            )),
+            fetched: false,
+            failures: 0,
+            revision: 0,
+            generation: 0,
+            history: VecDeque::new(),
+            events: VecDeque::new(),
+            rendered: HashMap::new(),
+        }
+    }
+
+    /// Time since the last refresh, `None` if it failed.
+    pub fn age(&self) -> Option<Duration> {
+        match self.error {
+            Some(_) => None,
+            None => Some(self.last_update.elapsed()),
+        }
+    }
+
+    /// Whether the cache was refreshed at all, successfully or not.
+    pub fn attempted(&self) -> bool {
+        self.fetched || self.failures > 0
+    }
+
+    /// The status of the last successful refresh and its age, kept when later refreshes fail.
+    pub fn last_good(&self) -> Option<(&ServerInfo, Duration)> {
+        if self.fetched {
+            Some((&self.server_info, self.last_update.elapsed()))
         } else {
-            for child in &mut self.children {
-                child.add_to_parent(parent_id, channel);
+            None
+        }
+    }
+
+    /// Whether the last refresh failed too recently to query the TS3 host again on request. The
+    /// backoff doubles with every consecutive failure, so outages don't stall every request.
+    pub fn backing_off(&self) -> bool {
+        if self.failures == 0 {
+            return false;
+        }
+        let backoff = FAILURE_BACKOFF * 2u32.saturating_pow(self.failures - 1);
+        self.last_attempt.elapsed() < backoff.min(MAX_FAILURE_BACKOFF)
+    }
+
+    /// The status at `revision`, `None` if it's unknown or too old to be kept.
+    pub fn snapshot(&self, revision: u64) -> Option<Arc<ServerInfo>> {
+        self.history
+            .iter()
+            .find(|(kept, _)| *kept == revision)
+            .map(|(_, server_info)| server_info.clone())
+    }
+
+    /// The response body rendered for `key` when the status was `age` seconds old, `None` if it
+    /// wasn't rendered at that age or the status changed since.
+    pub fn rendered(&self, key: &(Format, String), age: Option<u64>) -> Option<Rendered> {
+        match self.rendered.get(key) {
+            Some((rendered_age, rendered)) if *rendered_age == age => Some(rendered.clone()),
+            _ => None,
+        }
+    }
+
+    /// Incremented whenever the status or error changes, see `generation`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Forget everything rendered from the previous status or error.
+    fn changed(&mut self) {
+        self.generation += 1;
+        self.rendered.clear();
+    }
+
+    /// Start a new revision with the current status.
+    fn record_revision(&mut self) {
+        self.revision += 1;
+        self.history
+            .push_back((self.revision, self.server_info.clone()));
+        if self.history.len() > REVISION_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    /// Log the changes from `previous` to the current status with the current revision.
+    fn log_events(&mut self, previous: &ServerInfo) {
+        let time = SystemTime::now();
+        for (index, event) in events::server_events(previous, &self.server_info)
+            .into_iter()
+            .enumerate()
+        {
+            self.events.push_back(LoggedEvent {
+                revision: self.revision,
+                index,
+                time,
+                event,
+            });
+        }
+        while self.events.len() > EVENT_LOG {
+            self.events.pop_front();
+        }
+    }
+
+    /// Change the status in place with `f`, returns the update for subscribers if `f` reports a
+    /// change. Does nothing before the first successful refresh.
+    fn modify<F: FnOnce(&mut ServerInfo) -> bool>(&mut self, f: F) -> Option<StatusUpdate> {
+        if !self.fetched {
+            return None;
+        }
+        let previous = self.server_info.clone();
+        if !f(Arc::make_mut(&mut self.server_info)) {
+            return None;
+        }
+        self.changed();
+        self.record_revision();
+        self.log_events(&previous);
+        Some(StatusUpdate {
+            server_info: self.server_info.clone(),
+            changed: true,
+            refresh: false,
+        })
+    }
+
+    /// Store the result of a successful refresh, returns the update for subscribers.
+    fn update(&mut self, server_info: &ServerInfo, server_details: ServerDetails) -> StatusUpdate {
+        // The uptime and network statistics change with every refresh, that alone doesn't make
+        // for an update
+        let changed = self.error.is_some()
+            || *self.server_info
+                != ServerInfo {
+                    uptime_seconds: self.server_info.uptime_seconds,
+                    network: self.server_info.network.clone(),
+                    ..server_info.clone()
+                };
+        // Revisions only count changes that show up in deltas
+        let differs = !self.fetched || !delta::delta(&self.server_info, server_info).is_empty();
+        self.last_update = Instant::now();
+        let previous = mem::replace(&mut self.server_info, Arc::new(server_info.clone()));
+        if differs {
+            self.record_revision();
+            if self.fetched {
+                self.log_events(&previous);
             }
         }
+        self.server_details = server_details;
+        self.error = None;
+        self.fetched = true;
+        self.failures = 0;
+        self.changed();
+
+        StatusUpdate {
+            server_info: self.server_info.clone(),
+            changed,
+            refresh: true,
+        }
+    }
+
+    /// Store the error of a failed refresh, the last known status is kept.
+    fn fail(&mut self, error: QueryError) {
+        self.error = Some(error);
+        self.failures = self.failures.saturating_add(1);
+        self.changed();
     }
 }
 
-fn channel_tree(
-    server_info: &HashMap<String, Option<String>>,
-    channels: Vec<ChannelFull>,
-    clients: Vec<OnlineClientFull>,
-) -> ServerInfo {
-    let mut root = ChannelNode {
-        id: 0,
-        name: "Root".to_string(),
-        clients: Vec::new(),
-        children: Vec::new(),
-    };
+/// Cache of the status of a virtual server, shared by requests and background tasks. Readers
+/// load the current `StatusCache` snapshot without waiting for writers, which change a copy and
+/// swap it in.
+pub struct SharedCache {
+    current: ArcSwap<StatusCache>,
+    /// Taken by writers, so they don't overwrite each other's changes
+    writer: Mutex<CacheWriter>,
+}
+
+/// What only writers of a `SharedCache` need, rather than readers of its snapshots.
+#[derive(Default)]
+struct CacheWriter {
+    /// Whether a refresh started by `start_refresh` is running
+    refreshing: bool,
+    /// Requests waiting for the result of the running refresh
+    waiters: Vec<oneshot::Sender<Result<ServerInfo, QueryError>>>,
+    subscribers: Vec<UnboundedSender<StatusUpdate>>,
+}
+
+impl CacheWriter {
+    fn publish(&mut self, update: StatusUpdate) {
+        self.subscribers
+            .retain(|subscriber| subscriber.unbounded_send(update.clone()).is_ok());
+    }
+}
+
+impl SharedCache {
+    /// An empty cache that is already expired
+    pub fn new(lifetime: u64) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(StatusCache::new(lifetime)),
+            writer: Mutex::default(),
+        }
+    }
+
+    /// The current snapshot.
+    pub fn load(&self) -> Arc<StatusCache> {
+        self.current.load_full()
+    }
+
+    /// Exclusive access for changing the cache. Writers only swap in complete snapshots, so one
+    /// that panicked left nothing half-changed behind and the lock can be taken over.
+    fn writer(&self) -> MutexGuard<'_, CacheWriter> {
+        self.writer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A copy of the current snapshot, to be changed and swapped in while holding the writer lock.
+    fn copy(&self) -> StatusCache {
+        StatusCache::clone(&self.current.load())
+    }
+
+    /// Receive a `StatusUpdate` for every future change, along with the current snapshot.
+    pub fn subscribe(&self) -> (Arc<StatusCache>, UnboundedReceiver<StatusUpdate>) {
+        let mut writer = self.writer();
+        let (tx, rx) = mpsc::unbounded();
+        writer.subscribers.push(tx);
+        (self.load(), rx)
+    }
+
+    /// Change the cached status in place with `f`, e.g. from a notification of the TS3 host, and
+    /// notify subscribers if it reports a change. Does nothing before the first successful refresh.
+    /// Returns whether the status changed.
+    pub fn modify<F: FnOnce(&mut ServerInfo) -> bool>(&self, f: F) -> bool {
+        let mut writer = self.writer();
+        let mut next = self.copy();
+        match next.modify(f) {
+            Some(update) => {
+                self.current.store(Arc::new(next));
+                writer.publish(update);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Store the result of a refresh that just completed and notify subscribers if it succeeded.
+    fn record(&self, result: &Result<(ServerInfo, ServerDetails), QueryError>) {
+        let mut writer = self.writer();
+        let mut next = self.copy();
+        next.last_attempt = Instant::now();
+        let update = match result {
+            Ok((server_info, server_details)) => {
+                Some(next.update(server_info, server_details.clone()))
+            }
+            Err(e) => {
+                next.fail(e.clone());
+                None
+            }
+        };
+        self.current.store(Arc::new(next));
+        if let Some(update) = update {
+            writer.publish(update);
+        }
+    }
+
+    /// Count a refresh that wasn't even attempted as attempt, keeping the last known status and
+    /// error.
+    fn record_attempt(&self) {
+        let _writer = self.writer();
+        let mut next = self.copy();
+        next.last_attempt = Instant::now();
+        self.current.store(Arc::new(next));
+    }
+
+    /// Keep the response `body` rendered from `snapshot` for `key` when the status was `age`
+    /// seconds old, unless the status changed since.
+    pub fn keep_rendered(
+        &self,
+        snapshot: &StatusCache,
+        key: (Format, String),
+        age: Option<u64>,
+        rendered: Rendered,
+    ) {
+        let _writer = self.writer();
+        let mut next = self.copy();
+        if next.generation != snapshot.generation {
+            return;
+        }
+        if next.rendered.len() >= RENDERED_BODIES && !next.rendered.contains_key(&key) {
+            next.rendered.clear();
+        }
+        next.rendered.insert(key, (age, rendered));
+        self.current.store(Arc::new(next));
+    }
+}
+
+/// Transport of the ServerQuery connection to a TS3 host.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryProtocol {
+    /// Plain text, usually on port 10011
+    Raw,
+    /// SSH, usually on port 10022. The session is logged in as the query user already.
+    Ssh,
+    /// WebQuery over HTTP, usually on port 10080, authenticated by an API key
+    Http,
+    /// WebQuery over HTTPS, usually on port 10443
+    Https,
+}
+
+impl QueryProtocol {
+    /// Whether the connection has to be logged in with the `login` command. SSH query tunnels
+    /// are logged in already, but only relay once they got it.
+    pub fn needs_login(self) -> bool {
+        matches!(self, QueryProtocol::Raw | QueryProtocol::Ssh)
+    }
+
+    /// Whether the server notifies about events, WebQuery only answers requests.
+    pub fn notifications(self) -> bool {
+        matches!(self, QueryProtocol::Raw | QueryProtocol::Ssh)
+    }
+}
+
+impl FromStr for QueryProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(QueryProtocol::Raw),
+            "ssh" => Ok(QueryProtocol::Ssh),
+            "http" => Ok(QueryProtocol::Http),
+            "https" => Ok(QueryProtocol::Https),
+            _ => Err(format!(
+                "unknown query protocol {}, expected raw, ssh, http or https",
+                s
+            )),
+        }
+    }
+}
+
+/// Manages a logged in query connection to a TS3 host, reconnecting when it breaks.
+pub struct Connection {
+    server: ServerConfig,
+    connect_timeout: Duration,
+    /// Time to wait for the response to a command
+    command_timeout: Duration,
+    client: Option<QueryClient>,
+    /// Version of the server software, resolved when connecting
+    server_version: ServerVersion,
+    /// Nickname of the session on the virtual servers, empty to keep the default one
+    pub nickname: String,
+    /// Virtual servers the session of `client` was named on already
+    named: HashSet<u64>,
+    /// Connection times of the clients of the last query, by virtual server
+    pub connected_since: HashMap<u64, ConnectedSince>,
+}
+
+impl Connection {
+    pub fn new(
+        server: ServerConfig,
+        connect_timeout: Duration,
+        command_timeout: Duration,
+        nickname: String,
+    ) -> Self {
+        Self {
+            server,
+            connect_timeout,
+            command_timeout,
+            client: None,
+            server_version: ServerVersion::Auto,
+            nickname,
+            named: HashSet::new(),
+            connected_since: HashMap::new(),
+        }
+    }
+
+    fn connect(&mut self) -> Result<QueryClient, Ts3Error> {
+        info!("Connecting to TS3 server {}", self.server.name);
+        let host = resolve::ts3_host(&self.server)?;
+        let mut client = QueryClient::with_timeout(
+            resolve::query_address(&self.server, &host, self.connect_timeout)?,
+            Some(self.connect_timeout),
+            Some(self.command_timeout),
+        )?;
+        if self.server.ts3_query_protocol.needs_login() {
+            client.login(&self.server.user, &self.server.password)?;
+        }
+        // A new session has the default nickname everywhere
+        self.named.clear();
+        self.server_version = self
+            .server
+            .ts3_server_version
+            .resolve(&mut client, &self.server.name)?;
+        Ok(client)
+    }
+
+    /// Run `f` with a logged in client, the version of the server software and the virtual servers
+    /// its session was named on. A reused connection that failed for reasons other than an error
+    /// response of the server is reconnected and `f` is retried once.
+    pub fn with_client<T, F>(&mut self, mut f: F) -> Result<T, Ts3Error>
+    where
+        F: FnMut(&mut QueryClient, ServerVersion, &mut HashSet<u64>) -> Result<T, Ts3Error>,
+    {
+        let reused = self.client.is_some();
+        let mut client = match self.client.take() {
+            Some(client) => client,
+            None => self.connect()?,
+        };
+
+        let result = match f(&mut client, self.server_version, &mut self.named) {
+            Err(e) if reused && !e.is_error_response() => {
+                info!("Query connection to {} broke: {:?}", self.server.name, e);
+                drop(client);
+                client = self.connect()?;
+                f(&mut client, self.server_version, &mut self.named)
+            }
+            result => result,
+        };
+
+        // The connection state is unknown after anything but a regular error response
+        match &result {
+            Err(e) if !e.is_error_response() => {}
+            _ => self.client = Some(client),
+        }
+        result
+    }
+
+    /// Log out and close the query connection, if connected.
+    pub fn disconnect(&mut self) -> Result<(), Ts3Error> {
+        if let Some(mut client) = self.client.take() {
+            info!("Logging out of TS3 server {}", self.server.name);
+            // Dropping the client sends `quit` and closes the connection
+            client.logout()?;
+        }
+        Ok(())
+    }
+
+    /// Ping the TS3 host if connected, dropping the connection if that fails.
+    pub fn keepalive(&mut self) -> Result<(), Ts3Error> {
+        if let Some(client) = self.client.as_mut() {
+            trace!("Sending keepalive to {}", self.server.name);
+            if let Err(e) = client.ping() {
+                self.client = None;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How often and how far apart queries failing with transient errors are retried.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry, doubling with every further one
+    pub backoff: Duration,
+}
+
+/// Whether `e` is a network error that may go away on its own, unlike error responses of the
+/// server such as invalid credentials.
+fn is_transient(e: &Ts3Error) -> bool {
+    matches!(e, Ts3Error::Io { .. } | Ts3Error::ConnectionClosed { .. })
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry`, with jitter so refreshes of several caches that failed
+    /// at once don't retry in lockstep.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff * 2u32.saturating_pow(retry);
+        let jitter = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.subsec_nanos() % 1000)
+            .unwrap_or(0);
+        backoff / 2 + backoff * jitter / 2000
+    }
+
+    /// Run `f`, retrying it after transient errors while retries are left. Blocks while waiting,
+    /// so only use it on the blocking thread pool.
+    pub fn run<T, F>(&self, mut f: F) -> Result<T, Ts3Error>
+    where
+        F: FnMut() -> Result<T, Ts3Error>,
+    {
+        let mut retry = 0;
+        loop {
+            match f() {
+                Err(e) if retry < self.retries && is_transient(&e) => {
+                    let delay = self.delay(retry);
+                    info!("Retrying TS3 query in {:?} after: {}", delay, e);
+                    thread::sleep(delay);
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Which details about clients are included in the status.
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    pub versions: bool,
+    pub privacy_mode: PrivacyMode,
+    /// Key of the hashes that replace nicknames
+    pub privacy_secret: Vec<u8>,
+    /// Clients whose nickname or unique id matches any of these are left out
+    pub exclude: Vec<Regex>,
+    /// Whether ServerQuery clients are shown unless requested otherwise
+    pub include_query: bool,
+}
+
+/// Translate the glob `pattern`, supporting `*` and `?`, into a case insensitive regex.
+fn glob(pattern: &str) -> Regex {
+    let mut regex = "(?i)^".to_string();
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).expect("invalid glob regex")
+}
 
-    for channel in channels {
-        let node = ChannelNode {
+impl ClientOptions {
+    pub fn new(cfg: &Config) -> Self {
+        Self {
+            versions: cfg.client_versions,
+            privacy_mode: cfg.privacy_mode,
+            privacy_secret: cfg.privacy_secret.clone().unwrap_or_default().into_bytes(),
+            exclude: cfg.exclude_clients.iter().map(|p| glob(p)).collect(),
+            include_query: cfg.include_query_clients,
+        }
+    }
+
+    fn excludes(&self, client: &OnlineClientFull) -> bool {
+        self.exclude.iter().any(|pattern| {
+            pattern.is_match(&client.client_nickname)
+                || pattern.is_match(&client.client_unique_identifier)
+        })
+    }
+
+    /// Remove the details from `client` with the unique id `unique_id` that aren't enabled.
+    fn apply(&self, mut client: Client, unique_id: &str) -> Client {
+        if !self.versions {
+            client.platform = None;
+            client.version = None;
+        }
+        privacy::anonymize(
+            self.privacy_mode,
+            &self.privacy_secret,
+            &mut client,
+            unique_id,
+        );
+        client
+    }
+}
+
+/// Names of the channel and server groups by id, as of the last query.
+#[derive(Debug, Default)]
+pub struct GroupNames {
+    pub channel: HashMap<u64, String>,
+    pub server: HashMap<u64, String>,
+}
+
+/// Downloaded icons and when they were downloaded by virtual server and icon id
+pub type IconCache = HashMap<(u64, u64), (Instant, web::Bytes)>;
+
+/// A configured TS3 host with its query connection and the caches of its virtual servers.
+#[derive(Clone)]
+pub struct Server {
+    pub cfg: ServerConfig,
+    pub client_options: ClientOptions,
+    /// Query the description of every channel on refresh
+    pub channel_descriptions: bool,
+    pub backend: Arc<Mutex<Box<dyn StatusBackend>>>,
+    pub retry: RetryPolicy,
+    pub breaker: Arc<Mutex<CircuitBreaker>>,
+    pub caches: HashMap<u64, Arc<SharedCache>>,
+    pub icons: Arc<RwLock<IconCache>>,
+    /// Group names of the last query by virtual server, for clients that join in between
+    pub groups: Arc<RwLock<HashMap<u64, Arc<GroupNames>>>>,
+}
+
+impl Server {
+    pub fn new(cfg: ServerConfig, config: &Config) -> Self {
+        let connect_timeout = Duration::from_secs(config.ts3_connect_timeout);
+        let command_timeout = Duration::from_secs(config.ts3_command_timeout);
+        let backend: Box<dyn StatusBackend> = match cfg.ts3_query_protocol {
+            QueryProtocol::Http | QueryProtocol::Https => {
+                Box::new(WebQuery::new(cfg.clone(), connect_timeout, command_timeout))
+            }
+            _ => Box::new(Connection::new(
+                cfg.clone(),
+                connect_timeout,
+                command_timeout,
+                config.query_nickname.clone(),
+            )),
+        };
+        Self::with_backend(cfg, config, backend)
+    }
+
+    /// Like `new`, but queries the status from `backend` instead of the TS3 host.
+    pub fn with_backend(
+        cfg: ServerConfig,
+        config: &Config,
+        backend: Box<dyn StatusBackend>,
+    ) -> Self {
+        let caches = cfg
+            .ts3_server_ids
+            .iter()
+            .map(|&id| (id, Arc::new(SharedCache::new(config.cache_lifetime))))
+            .collect();
+
+        Self {
+            backend: Arc::new(Mutex::new(backend)),
+            client_options: ClientOptions::new(config),
+            channel_descriptions: config.channel_descriptions,
+            retry: RetryPolicy {
+                retries: config.ts3_retries,
+                backoff: Duration::from_millis(config.ts3_retry_backoff_ms),
+            },
+            breaker: Arc::new(Mutex::new(CircuitBreaker::new(
+                config.circuit_breaker_threshold,
+                Duration::from_secs(config.circuit_breaker_cooldown),
+            ))),
+            cfg,
+            caches,
+            icons: Arc::new(RwLock::new(HashMap::new())),
+            groups: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// `client` that just entered the virtual server `server_id` as it is shown in the status,
+    /// with the group names of the last query. `None` if it is left out.
+    pub fn entered_client(&self, server_id: u64, client: &OnlineClientFull) -> Option<Client> {
+        if self.client_options.excludes(client) {
+            return None;
+        }
+        let groups = self
+            .groups
+            .read()
+            .expect("can't readlock group names")
+            .get(&server_id)
+            .cloned()
+            .unwrap_or_default();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        Some(
+            self.client_options.apply(
+                Client {
+                    connected_since: Some(now),
+                    channel_group_name: groups
+                        .channel
+                        .get(&client.client_channel_group_id)
+                        .cloned(),
+                    groups: client
+                        .client_servergroups
+                        .iter()
+                        .filter_map(|id| groups.server.get(id).cloned())
+                        .collect(),
+                    ..client.into()
+                },
+                &client.client_unique_identifier,
+            ),
+        )
+    }
+}
+
+impl ServerInfo {
+    /// All clients on the server together with the channel they are in.
+    pub fn clients(&self) -> Vec<(&ChannelNode, &Client)> {
+        fn collect<'a>(channels: &'a [ChannelNode], out: &mut Vec<(&'a ChannelNode, &'a Client)>) {
+            for channel in channels {
+                out.extend(channel.clients.iter().map(|client| (channel, client)));
+                collect(&channel.children, out);
+            }
+        }
+
+        let mut clients = Vec::new();
+        collect(&self.channels, &mut clients);
+        clients
+    }
+
+    /// Every channel with the number of clients in it, not counting its subchannels.
+    pub fn channel_counts(&self) -> Vec<(u64, usize)> {
+        fn collect(channels: &[ChannelNode], out: &mut Vec<(u64, usize)>) {
+            for channel in channels {
+                out.push((channel.id, channel.clients.len()));
+                collect(&channel.children, out);
+            }
+        }
+
+        let mut counts = Vec::new();
+        collect(&self.channels, &mut counts);
+        counts
+    }
+
+    /// A copy of this status, without ServerQuery clients unless `include_query` is set.
+    pub fn visible(&self, include_query: bool) -> Self {
+        let mut server_info = self.clone();
+        if !include_query {
+            server_info.remove_query_clients();
+        }
+        server_info
+    }
+
+    /// Remove the ServerQuery clients from all channels.
+    pub fn remove_query_clients(&mut self) {
+        fn remove(channels: &mut [ChannelNode]) {
+            for channel in channels {
+                channel.clients.retain(|client| !client.is_query);
+                remove(&mut channel.children);
+            }
+        }
+
+        remove(&mut self.channels);
+    }
+
+    /// The channel `id` anywhere in the tree.
+    pub fn channel(&self, id: u64) -> Option<&ChannelNode> {
+        fn find(channels: &[ChannelNode], id: u64) -> Option<&ChannelNode> {
+            channels.iter().find_map(|channel| {
+                if channel.id == id {
+                    Some(channel)
+                } else {
+                    find(&channel.children, id)
+                }
+            })
+        }
+
+        find(&self.channels, id)
+    }
+
+    fn channel_mut(&mut self, id: u64) -> Option<&mut ChannelNode> {
+        fn find(channels: &mut [ChannelNode], id: u64) -> Option<&mut ChannelNode> {
+            channels.iter_mut().find_map(|channel| {
+                if channel.id == id {
+                    Some(channel)
+                } else {
+                    find(&mut channel.children, id)
+                }
+            })
+        }
+
+        find(&mut self.channels, id)
+    }
+
+    /// Take the client `id` out of its channel, `None` if it isn't shown, e.g. because it is
+    /// excluded.
+    fn take_client(&mut self, id: u16) -> Option<Client> {
+        fn take(channels: &mut [ChannelNode], id: u16) -> Option<Client> {
+            channels.iter_mut().find_map(|channel| {
+                match channel.clients.iter().position(|client| client.id == id) {
+                    Some(index) => Some(channel.clients.remove(index)),
+                    None => take(&mut channel.children, id),
+                }
+            })
+        }
+
+        take(&mut self.channels, id)
+    }
+
+    /// Remove the client `id` after it left the server, returns whether it was shown.
+    pub fn remove_client(&mut self, id: u16) -> bool {
+        let client = match self.take_client(id) {
+            Some(client) => client,
+            None => return false,
+        };
+        self.clients_online = self.clients_online.saturating_sub(1);
+        if client.is_query {
+            self.queryclients_online = self.queryclients_online.saturating_sub(1);
+        }
+        true
+    }
+
+    /// Add `client`, who just entered the server, to the channel `channel_id`, returns whether it
+    /// was added. Clients that are shown already or whose channel isn't known aren't.
+    pub fn add_client(&mut self, client: Client, channel_id: u64) -> bool {
+        let shown = self
+            .clients()
+            .iter()
+            .any(|(_, shown)| shown.id == client.id);
+        let is_query = client.is_query;
+        match self.channel_mut(channel_id) {
+            Some(channel) if !shown => channel.clients.push(client),
+            _ => return false,
+        }
+        self.clients_online += 1;
+        if is_query {
+            self.queryclients_online += 1;
+        }
+        true
+    }
+
+    /// Move the client `id` to the channel `channel_id`, returns whether it moved.
+    pub fn move_client(&mut self, id: u16, channel_id: u64) -> bool {
+        match self.channel(channel_id) {
+            Some(channel) if !channel.clients.iter().any(|client| client.id == id) => {}
+            _ => return false,
+        }
+        let client = match self.take_client(id) {
+            Some(client) => client,
+            None => return false,
+        };
+        if let Some(channel) = self.channel_mut(channel_id) {
+            channel.clients.push(client);
+        }
+        true
+    }
+}
+
+/// TS3 reports icon ids, the CRC32 of the icon, as signed 32 bit integer.
+fn icon_id(id: i64) -> u64 {
+    id as u32 as u64
+}
+
+/// The channels below `parent_id` with their subchannels, in the order of `children`, the
+/// indices of the subchannels of each channel in `nodes`. Channels are moved out of `nodes` once
+/// they are placed.
+fn subtree(
+    parent_id: u64,
+    nodes: &mut [Option<ChannelNode>],
+    children: &HashMap<u64, Vec<usize>>,
+) -> Vec<ChannelNode> {
+    let mut channels = Vec::new();
+    for &index in children.get(&parent_id).into_iter().flatten() {
+        if let Some(mut node) = nodes[index].take() {
+            node.children = subtree(node.id, nodes, children);
+            channels.push(node);
+        }
+    }
+    channels
+}
+
+/// How many clients of `raw` are left out by `options`, and how many of those are ServerQuery
+/// clients.
+fn excluded_clients(raw: &RawStatus, options: &ClientOptions) -> (u32, u32) {
+    raw.clients
+        .iter()
+        .filter(|c| options.excludes(c))
+        .fold((0, 0), |(all, query), c| {
+            (all + 1, query + u32::from(c.client_type == 1))
+        })
+}
+
+fn channel_tree(raw: &RawStatus, options: &ClientOptions) -> ServerInfo {
+    let server_info = &raw.server_info;
+    // The counts from `serverinfo` include excluded clients, which mustn't show up anywhere
+    let (excluded, excluded_query) = excluded_clients(raw, options);
+
+    let mut clients: HashMap<u64, Vec<Client>> = HashMap::new();
+    for c in raw.clients.iter().filter(|c| !options.excludes(c)) {
+        let client = options.apply(
+            Client {
+                connected_since: raw.connected_since.get(&c.clid).copied(),
+                channel_group_name: raw.channel_groups.get(&c.client_channel_group_id).cloned(),
+                groups: c
+                    .client_servergroups
+                    .iter()
+                    .filter_map(|id| raw.server_groups.get(id).cloned())
+                    .collect(),
+                ..c.into()
+            },
+            &c.client_unique_identifier,
+        );
+        clients.entry(c.cid).or_default().push(client);
+    }
+
+    // `channellist` doesn't list parents before their subchannels, so the tree is only built
+    // once all channels are known. `nodes` is in the order of `raw.channels`.
+    let mut nodes = Vec::with_capacity(raw.channels.len());
+    let mut children: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut indices = HashMap::with_capacity(raw.channels.len());
+    for (index, channel) in raw.channels.iter().enumerate() {
+        let spacer = spacer::parse(&channel.channel_name, channel.pid);
+        nodes.push(Some(ChannelNode {
             id: channel.cid,
+            order: channel.channel_order,
             name: channel.channel_name.clone(),
-            clients: clients
-                .iter()
-                .filter(|c| c.client_type == 0 && c.cid == channel.cid)
-                .map(|c| c.into())
-                .collect(),
+            is_spacer: spacer.is_some(),
+            spacer,
+            icon_id: icon_id(channel.channel_icon_id),
+            topic: channel
+                .channel_topic
+                .clone()
+                .filter(|topic| !topic.is_empty()),
+            description: raw.descriptions.get(&channel.cid).cloned(),
+            codec: channel.channel_codec.into(),
+            codec_quality: channel.channel_codec_quality,
+            max_clients: u32::try_from(channel.channel_maxclients).ok(),
+            is_permanent: channel.channel_flag_permanent,
+            is_semi_permanent: channel.channel_flag_semi_permanent,
+            has_password: channel.channel_flag_password,
+            is_default: channel.channel_flag_default,
+            orphan: false,
+            clients: clients.remove(&channel.cid).unwrap_or_default(),
             children: Vec::new(),
-        };
-        root.add_to_parent(channel.pid, &node);
+        }));
+        children.entry(channel.pid).or_default().push(index);
+        indices.insert(channel.cid, index);
+    }
+
+    let mut channels = subtree(0, &mut nodes, &children);
+    // Channels whose parent is missing aren't reached from the top level, they are shown there
+    // instead of vanishing. What remains after them are channels that are their own ancestors.
+    let unplaced = |nodes: &[Option<ChannelNode>], id: u64| {
+        indices
+            .get(&id)
+            .is_some_and(|&index| nodes[index].is_some())
+    };
+    let orphans: Vec<usize> = raw
+        .channels
+        .iter()
+        .enumerate()
+        .filter(|(_, channel)| unplaced(&nodes, channel.cid) && !unplaced(&nodes, channel.pid))
+        .map(|(index, _)| index)
+        .collect();
+    for index in orphans.into_iter().chain(0..nodes.len()) {
+        if let Some(mut node) = nodes[index].take() {
+            debug!(
+                "Channel {} has no parent, showing it at the top level",
+                node.id
+            );
+            node.orphan = true;
+            node.children = subtree(node.id, &mut nodes, &children);
+            channels.push(node);
+        }
     }
 
     ServerInfo {
-        name: server_info["virtualserver_name"]
-            .clone()
-            .unwrap_or_default(),
-        version: server_info["virtualserver_version"]
-            .clone()
-            .unwrap_or_default(),
-        platform: server_info["virtualserver_platform"]
-            .clone()
-            .unwrap_or_default(),
-        channels: root.children,
+        name: server_info.name.clone(),
+        version: server_info.version.clone(),
+        platform: server_info.platform.clone(),
+        clients_online: server_info.clients_online.saturating_sub(excluded),
+        max_clients: server_info.max_clients,
+        reserved_slots: server_info.reserved_slots,
+        queryclients_online: server_info
+            .queryclients_online
+            .saturating_sub(excluded_query),
+        uptime_seconds: server_info.uptime,
+        network: Network {
+            ping_ms: server_info.total_ping,
+            packet_loss: server_info.total_packet_loss,
+            bandwidth_sent: server_info.bandwidth_sent,
+            bandwidth_received: server_info.bandwidth_received,
+        },
+        icon_id: icon_id(server_info.icon_id),
+        channels,
+    }
+}
+
+fn server_details(raw: &RawStatus, options: &ClientOptions) -> ServerDetails {
+    let server_info = &raw.server_info;
+    let (excluded, _) = excluded_clients(raw, options);
+    ServerDetails {
+        name: server_info.name.clone(),
+        uptime_seconds: server_info.uptime,
+        clients_online: server_info.clients_online.saturating_sub(excluded),
+        max_clients: server_info.max_clients,
+        host_message: server_info.host_message.clone(),
+        welcome_message: server_info.welcome_message.clone(),
+        bandwidth_sent: server_info.bandwidth_sent,
+        bandwidth_received: server_info.bandwidth_received,
+        ping_ms: server_info.total_ping,
+        packet_loss: server_info.total_packet_loss,
     }
 }
 
+/// Build the status of a virtual server from what was queried from it.
+fn status(raw: &RawStatus, options: &ClientOptions) -> (ServerInfo, ServerDetails) {
+    (channel_tree(raw, options), server_details(raw, options))
+}
+
+/// Query the current status and details of the virtual server `server_id` of `server`, retrying
+/// transient failures, without using or updating its cache. This blocks, async code has to run it
+/// on a blocking thread pool.
+pub fn query_blocking(
+    server: &Server,
+    server_id: u64,
+) -> Result<(ServerInfo, ServerDetails), Ts3Error> {
+    server.retry.run(|| {
+        let mut backend = info_span!("connection_lock")
+            .in_scope(|| server.backend.lock().expect("can't lock connection"));
+        let raw = backend.fetch(server_id, server.channel_descriptions)?;
+        let groups = GroupNames {
+            channel: raw.channel_groups.clone(),
+            server: raw.server_groups.clone(),
+        };
+        server
+            .groups
+            .write()
+            .expect("can't writelock group names")
+            .insert(server_id, Arc::new(groups));
+        Ok(status(&raw, &server.client_options))
+    })
+}
+
+/// Query the TS3 host on the blocking thread pool and store the result, or the error, in `cache`.
+pub async fn refresh_status(
+    server: &Server,
+    server_id: u64,
+    cache: &Arc<SharedCache>,
+) -> Result<ServerInfo, QueryError> {
+    let allowed = server
+        .breaker
+        .lock()
+        .expect("can't lock circuit breaker")
+        .allow();
+    if let Err(remaining) = allowed {
+        // The cache keeps the last known status and error, but this counts as an attempt
+        cache.record_attempt();
+        return Err(QueryError::new(
+            ErrorCode::ConnectFailed,
+            format!(
+                "TS3 host {} failed repeatedly, not querying it for {} seconds",
+                server.cfg.name,
+                remaining.as_secs()
+            ),
+        ));
+    }
+
+    let id = server_id.to_string();
+    let labels = [server.cfg.name.as_str(), id.as_str()];
+    let timer = metrics::QUERY_DURATION
+        .with_label_values(&labels)
+        .start_timer();
+
+    let blocking_server = server.clone();
+    let span = info_span!("ts3_query", server = %server.cfg.name, server_id);
+    let result = web::block(move || {
+        let _span = span.enter();
+        query_blocking(&blocking_server, server_id)
+    })
+    .await
+    .map_err(|e| match e {
+        BlockingError::Error(e) => QueryError::from(e),
+        BlockingError::Canceled => {
+            QueryError::new(ErrorCode::ConnectFailed, "TS3 query was canceled")
+        }
+    });
+    timer.observe_duration();
+    server
+        .breaker
+        .lock()
+        .expect("can't lock circuit breaker")
+        .record(result.is_ok());
+    if result.is_err() {
+        metrics::REFRESH_FAILURES.with_label_values(&labels).inc();
+    }
+
+    cache.record(&result);
+
+    result.map(|(server_info, _)| server_info)
+}
+
+/// Periodically ping the TS3 host so the idle query connection isn't closed.
+pub async fn keepalive_periodically(server: Server) {
+    let mut interval = time::interval(Duration::from_secs(KEEPALIVE_INTERVAL));
+    loop {
+        interval.tick().await;
+        let backend = server.backend.clone();
+        let result =
+            web::block(move || backend.lock().expect("can't lock connection").keepalive()).await;
+        if let Err(e) = result {
+            error!("Keepalive for {} failed: {:?}", server.cfg.name, e);
+        }
+    }
+}
+
+/// Periodically refresh `cache`, independent of incoming requests.
+pub async fn refresh_periodically(
+    cfg: Config,
+    server: Server,
+    server_id: u64,
+    cache: Arc<SharedCache>,
+) {
+    let mut interval = time::interval(Duration::from_secs(cfg.cache_lifetime.max(1)));
+    loop {
+        interval.tick().await;
+        info!(
+            "Refreshing TS3 server status for {} server {}",
+            server.cfg.name, server_id
+        );
+        if let Err(e) = refresh_status(&server, server_id, &cache).await {
+            error!(ts3_error = e.message.as_str(); "TS3 Error: {}", e);
+        }
+    }
+}
+
+#[instrument(skip(cfg, server, cache), fields(server = %server.cfg.name))]
 pub async fn fetch_status(
     cfg: &Config,
-    cache: &Arc<RwLock<StatusCache>>,
-) -> Result<ServerInfo, Ts3Error> {
-    info!("Fetching TS3 server status");
+    server: &Server,
+    server_id: u64,
+    cache: &Arc<SharedCache>,
+) -> Result<ServerInfo, QueryError> {
+    refresh_if_expired(cfg, server, server_id, cache).await?;
+    Ok(ServerInfo::clone(&cache.load().server_info))
+}
+
+/// Like `fetch_status`, but leaves the status in `cache` instead of copying it. Returns the error
+/// of the last refresh if it failed.
+#[instrument(skip(cfg, server, cache), fields(server = %server.cfg.name))]
+pub async fn refresh_if_expired(
+    cfg: &Config,
+    server: &Server,
+    server_id: u64,
+    cache: &Arc<SharedCache>,
+) -> Result<(), QueryError> {
+    info!(
+        "Fetching TS3 server status for {} server {}",
+        server.cfg.name, server_id
+    );
 
-    let last_update = cache.read().expect("can't readlock cache").last_update;
-    let info = if last_update.elapsed().as_secs() > CACHE_LIFETIME {
+    let id = server_id.to_string();
+    let labels = [server.cfg.name.as_str(), id.as_str()];
+    let (last_update, has_snapshot, backing_off) = {
+        let cache = cache.load();
+        (
+            cache.last_update,
+            cache.error.is_none(),
+            cache.backing_off(),
+        )
+    };
+    if backing_off {
+        metrics::CACHE_HITS.with_label_values(&labels).inc();
+        info!("Last refresh failed recently, using cached error");
+    } else if !cfg.background_refresh && last_update.elapsed().as_secs() > cfg.cache_lifetime {
+        metrics::CACHE_MISSES.with_label_values(&labels).inc();
+        if !(cfg.stale_while_revalidate && has_snapshot) {
+            info!(
+                "Status is {} seconds old, updating cache",
+                last_update.elapsed().as_secs()
+            );
+            return refresh_shared(server, server_id, cache).await.map(drop);
+        }
         info!(
-            "Status is {} seconds old, updating cache",
+            "Status is {} seconds old, serving it while updating the cache",
             last_update.elapsed().as_secs()
         );
-        let mut client = QueryClient::new((&*cfg.ts3_host, cfg.ts3_port))?;
+        // Nobody waits for the result, the next request finds it in the cache
+        drop(start_refresh(server, server_id, cache));
+    } else {
+        metrics::CACHE_HITS.with_label_values(&labels).inc();
+        info!("Using cached server status");
+    }
 
-        client.login(&cfg.user, &cfg.password)?;
-        client.select_server_by_id(cfg.ts3_server_id)?;
+    match cache.load().error.as_ref() {
+        Some(e) => Err(e.clone()),
+        None => Ok(()),
+    }
+}
 
-        let server_info = client
-            .raw_command("serverinfo")
-            .map(|res| raw::parse_hashmap(res, true))?;
-        trace!("info: {:?}", server_info);
+/// Refresh `cache` in a background task unless a refresh is already running, and return a
+/// receiver for the result of the running refresh. The task isn't canceled along with the
+/// request that started it, so other requests waiting for it always get a result.
+fn start_refresh(
+    server: &Server,
+    server_id: u64,
+    cache: &Arc<SharedCache>,
+) -> oneshot::Receiver<Result<ServerInfo, QueryError>> {
+    let (tx, rx) = oneshot::channel();
+    let mut writer = cache.writer();
+    writer.waiters.push(tx);
+    if !writer.refreshing {
+        writer.refreshing = true;
+        let server = server.clone();
+        let cache = cache.clone();
+        rt::spawn(async move {
+            let result = refresh_status(&server, server_id, &cache).await;
+            let waiters = {
+                let mut writer = cache.writer();
+                writer.refreshing = false;
+                mem::take(&mut writer.waiters)
+            };
+            for waiter in waiters {
+                let _ = waiter.send(result.clone());
+            }
+        });
+    }
+    rx
+}
 
-        let channels = client.channels_full()?;
-        trace!("channels: {:?}", channels);
+/// Refresh `cache`, or wait for the refresh that is already running, so concurrent requests
+/// query the TS3 host only once.
+pub async fn refresh_shared(
+    server: &Server,
+    server_id: u64,
+    cache: &Arc<SharedCache>,
+) -> Result<ServerInfo, QueryError> {
+    start_refresh(server, server_id, cache)
+        .await
+        .unwrap_or_else(|_| {
+            Err(QueryError::new(
+                ErrorCode::ConnectFailed,
+                "TS3 query was canceled",
+            ))
+        })
+}
 
-        let clients = client.online_clients_full()?;
-        trace!("clients: {:?}", clients);
-        client.logout()?;
+/// Like `fetch_status`, but returns the details of the virtual server instead of its channel tree.
+/// They are queried along with it.
+pub async fn fetch_details(
+    cfg: &Config,
+    server: &Server,
+    server_id: u64,
+    cache: &Arc<SharedCache>,
+) -> Result<ServerDetails, QueryError> {
+    refresh_if_expired(cfg, server, server_id, cache).await?;
+    Ok(cache.load().server_details.clone())
+}
 
-        let server_info = channel_tree(&server_info, channels, clients);
-        if let Ok(mut cache) = cache.write() {
-            cache.last_update = Instant::now();
-            cache.server_info = server_info.clone();
-        } else {
-            error!("Can not write lock cache");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::RawServerInfo;
+
+    fn raw_channel(cid: u64, pid: u64) -> ChannelFull {
+        ChannelFull {
+            cid,
+            pid,
+            channel_order: 0,
+            channel_name: format!("Channel {}", cid),
+            total_clients: 0,
+            channel_needed_subscribe_power: 0,
+            channel_topic: None,
+            channel_flag_default: false,
+            channel_flag_password: false,
+            channel_flag_permanent: true,
+            channel_flag_semi_permanent: false,
+            channel_codec: 4,
+            channel_codec_quality: 6,
+            channel_needed_talk_power: 0,
+            channel_icon_id: 0,
+            seconds_empty: 0,
+            total_clients_family: 0,
+            channel_maxclients: -1,
+            channel_maxfamilyclients: -1,
+        }
+    }
+
+    /// The tree built from `channels` as `(id, orphan, children)` per channel.
+    fn tree(channels: Vec<ChannelFull>) -> Vec<(u64, bool, Vec<u64>)> {
+        fn shape(channels: &[ChannelNode], out: &mut Vec<(u64, bool, Vec<u64>)>) {
+            for channel in channels {
+                let children = channel.children.iter().map(|child| child.id).collect();
+                out.push((channel.id, channel.orphan, children));
+                shape(&channel.children, out);
+            }
         }
-        server_info
-    } else {
-        info!("Using cached server status");
-        cache
-            .read()
-            .expect("can't readlock cache")
-            .server_info
-            .clone()
-    };
 
-    Ok(info)
+        let raw = RawStatus {
+            server_info: RawServerInfo::default(),
+            channels,
+            clients: Vec::new(),
+            connected_since: HashMap::new(),
+            channel_groups: HashMap::new(),
+            server_groups: HashMap::new(),
+            descriptions: HashMap::new(),
+        };
+        let options = ClientOptions {
+            versions: true,
+            privacy_mode: PrivacyMode::Off,
+            privacy_secret: Vec::new(),
+            exclude: Vec::new(),
+            include_query: true,
+        };
+        let mut out = Vec::new();
+        shape(&channel_tree(&raw, &options).channels, &mut out);
+        out
+    }
+
+    #[test]
+    fn subchannels_listed_before_parents() {
+        let channels = vec![
+            raw_channel(3, 2),
+            raw_channel(2, 1),
+            raw_channel(4, 1),
+            raw_channel(1, 0),
+            raw_channel(5, 0),
+        ];
+        assert_eq!(
+            tree(channels),
+            [
+                (1, false, vec![2, 4]),
+                (2, false, vec![3]),
+                (3, false, vec![]),
+                (4, false, vec![]),
+                (5, false, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn orphans_kept_at_top_level() {
+        let channels = vec![
+            raw_channel(1, 0),
+            raw_channel(3, 2),
+            raw_channel(2, 9),
+            raw_channel(5, 6),
+            raw_channel(6, 5),
+        ];
+        assert_eq!(
+            tree(channels),
+            [
+                (1, false, vec![]),
+                (2, true, vec![3]),
+                (3, false, vec![]),
+                (5, true, vec![6]),
+                (6, false, vec![]),
+            ]
+        );
+    }
 }
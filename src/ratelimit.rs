@@ -0,0 +1,183 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::header,
+    Error, HttpResponse,
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{access::IpRange, JsonResponse};
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Number of clients whose requests are counted at most
+const MAX_CLIENTS: usize = 16384;
+
+/// Requests of a single client in the current window.
+struct Window {
+    start: Instant,
+    requests: u32,
+}
+
+/// Windows of the clients that sent requests recently.
+struct Windows {
+    clients: HashMap<IpAddr, Window>,
+    /// When expired windows were last removed
+    swept: Instant,
+}
+
+/// Limits the number of requests per client IP and minute.
+pub struct RateLimiter {
+    /// Requests per minute, unlimited if `None`
+    limit: Option<u32>,
+    client_ips: ClientIps,
+    windows: Mutex<Windows>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<u32>, client_ips: ClientIps) -> Self {
+        Self {
+            limit,
+            client_ips,
+            windows: Mutex::new(Windows {
+                clients: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Count a request of `ip`, returns the seconds until it may send requests again if it
+    /// exceeded the limit.
+    fn count(&self, ip: IpAddr, limit: u32) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().expect("can't lock rate limits");
+        // Forget clients whose window expired once per window, so the map doesn't grow with every
+        // client ever seen
+        if now.duration_since(windows.swept) >= WINDOW {
+            windows
+                .clients
+                .retain(|_, window| now.duration_since(window.start) < WINDOW);
+            windows.swept = now;
+        }
+        // Make room for new clients in bulk, so a flood of them doesn't scan every window per
+        // request: forget expired windows and, if that doesn't free half of them, the older half
+        if windows.clients.len() >= MAX_CLIENTS && !windows.clients.contains_key(&ip) {
+            windows
+                .clients
+                .retain(|_, window| now.duration_since(window.start) < WINDOW);
+            windows.swept = now;
+            if windows.clients.len() >= MAX_CLIENTS / 2 {
+                let mut starts: Vec<Instant> = windows
+                    .clients
+                    .values()
+                    .map(|window| window.start)
+                    .collect();
+                let middle = starts.len() / 2;
+                let (_, &mut median, _) = starts.select_nth_unstable(middle);
+                windows.clients.retain(|_, window| window.start > median);
+            }
+        }
+
+        let window = windows.clients.entry(ip).or_insert(Window {
+            start: now,
+            requests: 0,
+        });
+        if now.duration_since(window.start) >= WINDOW {
+            window.start = now;
+            window.requests = 0;
+        }
+        window.requests += 1;
+
+        if window.requests > limit {
+            let remaining = WINDOW.saturating_sub(now.duration_since(window.start));
+            Err(remaining.as_secs().max(1))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Where the IPs of clients are taken from.
+#[derive(Clone, Debug, Default)]
+pub struct ClientIps {
+    /// Header set by a reverse proxy that contains the client IP, such as `X-Forwarded-For`
+    pub header: Option<String>,
+    /// Peers whose `header` is trusted, it is ignored in requests of others
+    pub trusted_proxies: Vec<IpRange>,
+}
+
+impl ClientIps {
+    /// The IP of the client that sent `req`. It is taken from the last address in `header` if
+    /// configured and the peer is a trusted proxy, which is the one added by the proxy, and the
+    /// peer address otherwise. `Forwarded` headers are read from the `for` parameter of their
+    /// last element.
+    pub fn of(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr().map(|addr| addr.ip());
+        let trusted = peer.is_some_and(|peer| {
+            self.trusted_proxies
+                .iter()
+                .any(|range| range.contains(&peer))
+        });
+        let forwarded = self.header.as_deref().filter(|_| trusted).and_then(|name| {
+            let value = req.headers().get(name)?.to_str().ok()?;
+            let last = value.rsplit(',').next()?;
+            if name.eq_ignore_ascii_case("forwarded") {
+                last.split(';').find_map(|pair| {
+                    let (key, node) = pair.trim().split_once('=')?;
+                    key.eq_ignore_ascii_case("for").then(|| node_ip(node))?
+                })
+            } else {
+                node_ip(last)
+            }
+        });
+        forwarded.or(peer)
+    }
+}
+
+/// The IP of a proxied client such as `192.0.2.1`, `"[2001:db8::1]:4711"`, leaving out the port
+/// and quotes. Obfuscated ones such as `unknown` have none.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// Middleware answering requests of clients that exceeded the rate limit with
+/// `429 Too Many Requests`.
+pub fn limit_requests<S>(
+    limiter: &RateLimiter,
+    req: ServiceRequest,
+    srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let limited = limiter.limit.and_then(|limit| {
+        let ip = limiter.client_ips.of(&req)?;
+        limiter.count(ip, limit).err()
+    });
+
+    let response = match limited {
+        Some(retry_after) => Err(req.into_response(
+            HttpResponse::TooManyRequests()
+                .header(header::RETRY_AFTER, retry_after)
+                .json(JsonResponse::error("Too many requests".to_string())),
+        )),
+        None => Ok(srv.call(req)),
+    };
+
+    async move {
+        match response {
+            Ok(response) => response.await,
+            Err(response) => Ok(response),
+        }
+    }
+}
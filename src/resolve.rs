@@ -0,0 +1,147 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use log::debug;
+use trust_dns_resolver::{
+    error::{ResolveError, ResolveErrorKind},
+    Resolver,
+};
+
+use crate::{config::ServerConfig, query::QueryProtocol, ssh};
+
+/// Time to wait for the answer of a TSDNS server
+const TSDNS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Host the query connection to `server` is made to. With `ts3_resolve` it's looked up like the
+/// TeamSpeak client does: the `_ts3._udp` SRV record of `ts3_host` first, then the TSDNS server
+/// of its `_tsdns._tcp` SRV record and `ts3_host` itself if there is neither. This blocks and must
+/// not be called from the async runtime.
+pub fn ts3_host(server: &ServerConfig) -> io::Result<String> {
+    if !server.ts3_resolve {
+        return Ok(server.ts3_host.clone());
+    }
+
+    let resolver = Resolver::from_system_conf()?;
+    let domain = format!("{}.", server.ts3_host.trim_end_matches('.'));
+    if let Some((host, port)) = srv_record(&resolver, &format!("_ts3._udp.{}", domain))? {
+        debug!(
+            "Resolved {} to {} with voice port {} by SRV record",
+            server.ts3_host, host, port
+        );
+        return Ok(host);
+    }
+    if let Some(tsdns) = srv_record(&resolver, &format!("_tsdns._tcp.{}", domain))? {
+        if let Some((host, port)) = tsdns_lookup(tsdns, &server.ts3_host)? {
+            debug!(
+                "Resolved {} to {} with voice port {} by TSDNS",
+                server.ts3_host, host, port
+            );
+            return Ok(host);
+        }
+    }
+    Ok(server.ts3_host.clone())
+}
+
+/// Address to open the query connection to `server` at, whose `host` was looked up by `ts3_host`.
+/// SSH query connections are forwarded from a local port by `ssh::tunnel`. WebQuery has no query
+/// connection, it's used by `webquery::WebQuery` instead.
+pub fn query_address(
+    server: &ServerConfig,
+    host: &str,
+    connect_timeout: Duration,
+) -> io::Result<SocketAddr> {
+    match server.ts3_query_protocol {
+        QueryProtocol::Raw => (host, server.ts3_port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for TS3 host")),
+        QueryProtocol::Ssh => ssh::tunnel(server, host, connect_timeout),
+        QueryProtocol::Http | QueryProtocol::Https => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "WebQuery has no query connection",
+        )),
+    }
+}
+
+/// Target and port of the SRV record `name` with the lowest priority and highest weight, if any.
+fn srv_record(resolver: &Resolver, name: &str) -> io::Result<Option<(String, u16)>> {
+    let lookup = match resolver.srv_lookup(name) {
+        Ok(lookup) => lookup,
+        Err(e) if not_found(&e) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(lookup
+        .iter()
+        .min_by_key(|srv| (srv.priority(), u16::MAX - srv.weight()))
+        .map(|srv| {
+            let target = srv.target().to_utf8();
+            (target.trim_end_matches('.').to_string(), srv.port())
+        }))
+}
+
+fn not_found(e: &ResolveError) -> bool {
+    matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+/// Ask the TSDNS server at `tsdns` for the address of `domain`, `None` if it doesn't know it.
+fn tsdns_lookup(tsdns: (String, u16), domain: &str) -> io::Result<Option<(String, u16)>> {
+    let addr = tsdns
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address for TSDNS server"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TSDNS_TIMEOUT)?;
+    stream.set_read_timeout(Some(TSDNS_TIMEOUT))?;
+    stream.write_all(domain.as_bytes())?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    Ok(parse_tsdns_answer(&answer))
+}
+
+/// Host and port of a TSDNS answer, which is `<host>:<port>` with IPv6 hosts in brackets or `404`
+/// if the domain is unknown.
+fn parse_tsdns_answer(answer: &str) -> Option<(String, u16)> {
+    let (host, port) = answer.trim().rsplit_once(':')?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+/// `host` and `port` joined to an address such as `ts.example.com:10011`, with IPv6 hosts in
+/// brackets.
+pub fn authority(host: &str, port: u16) -> String {
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tsdns_answers() {
+        assert_eq!(
+            parse_tsdns_answer("ts.example.com:9987\n"),
+            Some(("ts.example.com".to_string(), 9987))
+        );
+        assert_eq!(
+            parse_tsdns_answer("[::1]:9987"),
+            Some(("::1".to_string(), 9987))
+        );
+        assert_eq!(parse_tsdns_answer("404"), None);
+    }
+
+    #[test]
+    fn ipv6_authority() {
+        assert_eq!(authority("::1", 10080), "[::1]:10080");
+        assert_eq!(authority("127.0.0.1", 10080), "127.0.0.1:10080");
+    }
+}
@@ -0,0 +1,46 @@
+use async_graphql::{Enum, SimpleObject};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+lazy_static! {
+    // `[<alignment>spacer<id>]<text>`, the id only makes the channel name unique
+    static ref SPACER: Regex = Regex::new(r"^\[([lcr*]?)spacer[^\]]*\](.*)$").unwrap();
+}
+
+#[derive(Clone, Copy, Debug, Enum, Eq, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+    /// The text is repeated to fill the whole line
+    Repeat,
+}
+
+/// Decoded name of a spacer channel, which TS3 clients render as separator or heading.
+#[derive(Clone, Debug, PartialEq, Serialize, SimpleObject, ToSchema)]
+pub struct Spacer {
+    pub alignment: Alignment,
+    pub text: String,
+}
+
+/// Decode the channel name `name` if it describes a spacer, only top level channels can be spacers.
+pub fn parse(name: &str, parent_id: u64) -> Option<Spacer> {
+    if parent_id != 0 {
+        return None;
+    }
+
+    let captures = SPACER.captures(name)?;
+    let alignment = match &captures[1] {
+        "c" => Alignment::Center,
+        "r" => Alignment::Right,
+        "*" => Alignment::Repeat,
+        _ => Alignment::Left,
+    };
+    Some(Spacer {
+        alignment,
+        text: captures[2].to_string(),
+    })
+}
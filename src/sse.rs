@@ -0,0 +1,49 @@
+use actix_web::{web::Bytes, Error};
+use futures::{channel::mpsc::UnboundedReceiver, stream, Stream, StreamExt};
+use log::error;
+use serde::Serialize;
+
+use crate::{
+    events::client_events,
+    query::{ServerInfo, StatusUpdate},
+};
+
+/// Format a single Server-Sent Event with a JSON payload.
+fn event<T: Serialize>(name: &str, data: &T) -> String {
+    match serde_json::to_string(data) {
+        Ok(json) => format!("event: {}\ndata: {}\n\n", name, json),
+        Err(e) => {
+            error!("Can not serialize {} event: {}", name, e);
+            String::new()
+        }
+    }
+}
+
+/// Stream of `status` events for every refresh, preceded by `join` and `leave` events for the
+/// clients that changed since the previous refresh.
+pub fn event_stream(
+    current: Option<ServerInfo>,
+    updates: UnboundedReceiver<StatusUpdate>,
+    include_query: bool,
+) -> impl Stream<Item = Result<Bytes, Error>> {
+    let initial = current
+        .as_ref()
+        .map(|server_info| event("status", server_info));
+    let mut previous = current;
+
+    let updates = updates.map(move |update| {
+        let server_info = update.server_info.visible(include_query);
+        let mut events = String::new();
+        if let Some(previous) = previous.as_ref() {
+            for client_event in client_events(previous, &server_info) {
+                events += &event(client_event.event.name(), &client_event);
+            }
+        }
+        events += &event("status", &server_info);
+        previous = Some(server_info);
+
+        Ok(Bytes::from(events))
+    });
+
+    stream::iter(initial.map(|initial| Ok(Bytes::from(initial)))).chain(updates)
+}
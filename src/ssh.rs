@@ -0,0 +1,197 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error};
+use mio::{Events, Interest, Poll, Token};
+use ssh2::{Channel, Session};
+use ts3_query::raw::escape_arg;
+
+use crate::config::ServerConfig;
+
+// Wait this long between polls of the listener, and of writes to a full channel
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Wait at most this long for either side of an idle tunnel to become readable
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+
+const LOCAL: Token = Token(0);
+const REMOTE: Token = Token(1);
+
+/// Open an SSH query session to `host`, logged in as the query user of `server`, and forward it
+/// from a listener on the loopback interface, so it can be spoken to like a raw query connection.
+/// Returns the address of the listener, which accepts a single connection within
+/// `connect_timeout`. That connection has to `login` with the query user and password first, so
+/// other local processes can't use the session.
+pub fn tunnel(
+    server: &ServerConfig,
+    host: &str,
+    connect_timeout: Duration,
+) -> io::Result<SocketAddr> {
+    let addr = (host, server.ts3_port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "no address for TS3 host"))?;
+    let stream = TcpStream::connect_timeout(&addr, connect_timeout)?;
+    let remote = stream.try_clone()?;
+
+    let mut session = Session::new()?;
+    session.set_tcp_stream(stream);
+    session.set_timeout(connect_timeout.as_millis() as u32);
+    session.handshake()?;
+    session
+        .userauth_password(&server.user, &server.password)
+        .map_err(|e| {
+            io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "SSH login as {} failed: {}, check the ServerQuery user and password",
+                    server.user, e
+                ),
+            )
+        })?;
+    let mut channel = session.channel_session()?;
+    channel.shell()?;
+
+    let listener = TcpListener::bind(("127.0.0.1", 0))?;
+    let local_addr = listener.local_addr()?;
+    let name = server.name.clone();
+    let login = format!(
+        "login {} {}",
+        escape_arg(&server.user),
+        escape_arg(&server.password)
+    );
+    thread::Builder::new()
+        .name(format!("ssh-{}", server.name))
+        .spawn(move || {
+            let result = accept(&listener, connect_timeout).and_then(|local| {
+                forward(
+                    &session,
+                    &mut channel,
+                    remote,
+                    local,
+                    &login,
+                    connect_timeout,
+                )
+            });
+            if let Err(e) = result {
+                error!("SSH query tunnel to {} failed: {}", name, e);
+            }
+            let _ = channel.close();
+            debug!("SSH query tunnel to {} closed", name);
+        })?;
+    Ok(local_addr)
+}
+
+/// The first connection to `listener` within `timeout`.
+fn accept(listener: &TcpListener, timeout: Duration) -> io::Result<TcpStream> {
+    listener.set_nonblocking(true)?;
+    let started = Instant::now();
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => return Ok(stream),
+            Err(e) if e.kind() == ErrorKind::WouldBlock && started.elapsed() < timeout => {
+                thread::sleep(POLL_INTERVAL)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Copy data between `local` and `channel` until either side closes, once `local` sent the
+/// `login` command within `timeout`. The tunnel answers it itself, the session is logged in
+/// already. While both sides are idle, it waits for them or `remote`, the SSH connection of the
+/// session, to become readable.
+fn forward(
+    session: &Session,
+    channel: &mut Channel,
+    remote: TcpStream,
+    local: TcpStream,
+    login: &str,
+    timeout: Duration,
+) -> io::Result<()> {
+    // libssh2 locks the session during blocking reads, so both directions are read non-blocking
+    session.set_blocking(false);
+    local.set_nonblocking(true)?;
+    let mut local = mio::net::TcpStream::from_std(local);
+    let mut remote = mio::net::TcpStream::from_std(remote);
+    let mut poll = Poll::new()?;
+    poll.registry()
+        .register(&mut local, LOCAL, Interest::READABLE)?;
+    poll.registry()
+        .register(&mut remote, REMOTE, Interest::READABLE)?;
+    let mut events = Events::with_capacity(2);
+
+    let started = Instant::now();
+    // What `local` sent before the end of the `login` line, `None` once it logged in
+    let mut pending = Some(Vec::new());
+    let mut buffer = [0; 8192];
+    loop {
+        let mut idle = true;
+        match local.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                idle = false;
+                match pending.as_mut() {
+                    None => write_all(channel, &buffer[..n])?,
+                    Some(line) => {
+                        line.extend_from_slice(&buffer[..n]);
+                        if let Some(end) = line.iter().position(|&b| b == b'\n') {
+                            if String::from_utf8_lossy(&line[..end]).trim_end() != login {
+                                return Err(not_logged_in());
+                            }
+                            write_all(&mut local, b"error id=0 msg=ok\n\r")?;
+                            write_all(channel, &line[end + 1..])?;
+                            pending = None;
+                        }
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        match channel.read(&mut buffer) {
+            Ok(0) if channel.eof() => return Ok(()),
+            Ok(0) => {}
+            Ok(n) => {
+                write_all(&mut local, &buffer[..n])?;
+                idle = false;
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+        if pending.is_some() && started.elapsed() > timeout {
+            return Err(not_logged_in());
+        }
+        if idle {
+            match poll.poll(&mut events, Some(IDLE_INTERVAL)) {
+                Err(e) if e.kind() != ErrorKind::Interrupted => return Err(e),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn not_logged_in() -> io::Error {
+    io::Error::new(
+        ErrorKind::PermissionDenied,
+        "local connection didn't log in, closing it",
+    )
+}
+
+/// Like `Write::write_all` for non-blocking writers, waiting while they would block. Doesn't
+/// flush, which discards the unread data of SSH channels.
+fn write_all(writer: &mut impl Write, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        match writer.write(data) {
+            Ok(0) => return Err(ErrorKind::WriteZero.into()),
+            Ok(n) => data = &data[n..],
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
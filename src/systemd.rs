@@ -0,0 +1,92 @@
+use actix_web::rt::time;
+use listenfd::ListenFd;
+use log::{error, info, warn};
+use sd_notify::NotifyState;
+use std::{
+    net::TcpListener,
+    os::unix::net::UnixListener,
+    time::{Duration, Instant},
+};
+
+use crate::State;
+
+/// Time a background refresh may take beyond the cache lifetime before the service counts as
+/// stuck and the watchdog lets systemd restart it
+const REFRESH_GRACE: Duration = Duration::from_secs(60);
+
+/// A listening socket passed by systemd socket activation.
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// The sockets passed by systemd with `LISTEN_FDS`, empty without socket activation.
+pub fn listeners() -> Vec<Listener> {
+    let mut fds = ListenFd::from_env();
+    (0..fds.len())
+        .filter_map(|idx| {
+            let listener = match fds.take_tcp_listener(idx) {
+                Ok(listener) => listener.map(Listener::Tcp),
+                Err(_) => fds
+                    .take_unix_listener(idx)
+                    .unwrap_or_else(|e| panic!("unsupported socket passed by systemd: {}", e))
+                    .map(Listener::Unix),
+            };
+            if listener.is_some() {
+                info!("Listening on socket {} passed by systemd", idx);
+            }
+            listener
+        })
+        .collect()
+}
+
+/// Tell systemd that the service is ready, a no-op if it wasn't started by systemd.
+pub fn notify_ready() {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Ready]) {
+        warn!("Can't notify systemd: {}", e);
+    }
+}
+
+/// Tell systemd that the service is shutting down.
+pub fn notify_stopping() {
+    if let Err(e) = sd_notify::notify(false, &[NotifyState::Stopping]) {
+        warn!("Can't notify systemd: {}", e);
+    }
+}
+
+/// Whether the background refreshes of every cache still complete, failed ones included.
+fn refreshing(state: &State) -> bool {
+    if !state.cfg.background_refresh {
+        return true;
+    }
+    let deadline = Duration::from_secs(state.cfg.cache_lifetime) + REFRESH_GRACE;
+    state
+        .servers
+        .values()
+        .flat_map(|server| server.caches.values())
+        .all(|cache| {
+            let last_attempt = cache.load().last_attempt;
+            Instant::now().duration_since(last_attempt) < deadline
+        })
+}
+
+/// Send `WATCHDOG=1` to systemd twice per `WatchdogSec=` as long as the background refreshes
+/// complete, so systemd restarts the service if they get stuck. A no-op without a watchdog.
+pub async fn watchdog(state: State) {
+    let mut usec = 0;
+    if !sd_notify::watchdog_enabled(false, &mut usec) {
+        return;
+    }
+
+    let mut interval = time::interval(Duration::from_micros(usec) / 2);
+    loop {
+        interval.tick().await;
+        if !refreshing(&state) {
+            error!("Background refresh is stuck, skipping systemd watchdog notification");
+            continue;
+        }
+        if let Err(e) = sd_notify::notify(false, &[NotifyState::Watchdog]) {
+            warn!("Can't notify systemd watchdog: {}", e);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    Error,
+};
+use log::{error, info};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use std::future::Future;
+use tracing::{info_span, Instrument};
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::config::Config;
+
+/// Export tracing spans to the configured OTLP/HTTP collector. Spans are discarded if no
+/// collector is configured.
+pub fn init(cfg: &Config) -> Option<SdkTracerProvider> {
+    let endpoint = cfg.otlp_endpoint.as_ref()?;
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build();
+    let exporter = match exporter {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            error!("Can not create OTLP exporter: {}", e);
+            return None;
+        }
+    };
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("ts3status").build())
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("ts3status"));
+    if let Err(e) =
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+    {
+        error!("Can not install tracing subscriber: {}", e);
+        return None;
+    }
+
+    info!("Exporting traces to {}", endpoint);
+    Some(provider)
+}
+
+/// Flush remaining spans to the collector.
+pub fn shutdown(provider: SdkTracerProvider) {
+    if let Err(e) = provider.shutdown() {
+        error!("Can not flush traces: {}", e);
+    }
+}
+
+/// Middleware wrapping every request in a tracing span.
+pub fn trace_request<S>(
+    req: ServiceRequest,
+    srv: &mut S,
+) -> impl Future<Output = Result<ServiceResponse, Error>>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error>,
+{
+    let span = info_span!("http_request", method = %req.method(), path = %req.path());
+    let response = span.in_scope(|| srv.call(req));
+    response.instrument(span)
+}
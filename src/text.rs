@@ -0,0 +1,114 @@
+use async_graphql::OutputType;
+use std::fmt::Write;
+
+use crate::{
+    query::{ChannelNode, Client, ServerInfo},
+    spacer::{Alignment, Spacer},
+    view::{FlatChannel, StatusView},
+};
+
+/// Width of spacers whose text is repeated to fill the line
+const SPACER_WIDTH: usize = 40;
+
+/// `<name>: <online>/<max> online`, ServerQuery clients aren't counted.
+fn summary<C: OutputType>(server_info: &ServerInfo<C>) -> String {
+    format!(
+        "{}: {}/{} online",
+        server_info.name,
+        server_info
+            .clients_online
+            .saturating_sub(server_info.queryclients_online),
+        server_info.max_clients
+    )
+}
+
+fn spacer_line(spacer: &Spacer) -> String {
+    match spacer.alignment {
+        Alignment::Repeat if !spacer.text.is_empty() => {
+            spacer.text.chars().cycle().take(SPACER_WIDTH).collect()
+        }
+        _ => spacer.text.clone(),
+    }
+}
+
+fn client_line(out: &mut String, depth: usize, client: &Client) {
+    let mut flags = Vec::new();
+    if client.away {
+        flags.push("away");
+    }
+    if client.input_muted {
+        flags.push("muted");
+    }
+    if client.output_muted {
+        flags.push("deaf");
+    }
+
+    let _ = write!(
+        out,
+        "{:indent$}- {}",
+        "",
+        client.nickname,
+        indent = depth * 2
+    );
+    if !flags.is_empty() {
+        let _ = write!(out, " ({})", flags.join(", "));
+    }
+    out.push('\n');
+}
+
+fn channel_lines(
+    out: &mut String,
+    depth: usize,
+    name: &str,
+    spacer: Option<&Spacer>,
+    clients: &[Client],
+) {
+    let name = spacer.map_or_else(|| name.to_string(), spacer_line);
+    let _ = writeln!(out, "{:indent$}{}", "", name, indent = depth * 2);
+    for client in clients {
+        client_line(out, depth + 1, client);
+    }
+}
+
+fn tree_lines(out: &mut String, depth: usize, channels: &[ChannelNode]) {
+    for channel in channels {
+        channel_lines(
+            out,
+            depth,
+            &channel.name,
+            channel.spacer.as_ref(),
+            &channel.clients,
+        );
+        tree_lines(out, depth + 1, &channel.children);
+    }
+}
+
+fn flat_lines(out: &mut String, channels: &[FlatChannel]) {
+    for channel in channels {
+        channel_lines(
+            out,
+            channel.depth as usize,
+            &channel.name,
+            channel.spacer.as_ref(),
+            &channel.clients,
+        );
+    }
+}
+
+/// Render the status of a virtual server as summary line followed by an indented channel tree.
+pub fn status(view: &StatusView) -> String {
+    let mut out = String::new();
+    match view {
+        StatusView::Tree(server_info) => {
+            out += &summary(server_info);
+            out.push('\n');
+            tree_lines(&mut out, 0, &server_info.channels);
+        }
+        StatusView::Flat(server_info) => {
+            out += &summary(server_info);
+            out.push('\n');
+            flat_lines(&mut out, &server_info.channels);
+        }
+    }
+    out
+}
@@ -0,0 +1,40 @@
+use rustls::{
+    internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys},
+    NoClientAuth, PrivateKey, ServerConfig,
+};
+use std::{fs::File, io::BufReader, path::Path};
+
+fn open(path: &Path) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| format!("can't read {}: {}", path.display(), e))
+}
+
+/// Read the first PKCS#8 or RSA private key from the PEM file `path`.
+fn private_key(path: &Path) -> Result<PrivateKey, String> {
+    let keys = pkcs8_private_keys(&mut open(path)?)
+        .ok()
+        .filter(|keys| !keys.is_empty());
+    let keys = match keys {
+        Some(keys) => keys,
+        None => rsa_private_keys(&mut open(path)?).unwrap_or_default(),
+    };
+    keys.into_iter()
+        .next()
+        .ok_or_else(|| format!("no private key found in {}", path.display()))
+}
+
+/// TLS configuration serving the certificate chain from the PEM file `cert` with the private key
+/// from `key`, or why either is invalid.
+pub fn server_config(cert: &Path, key: &Path) -> Result<ServerConfig, String> {
+    let chain = certs(&mut open(cert)?)
+        .ok()
+        .filter(|chain| !chain.is_empty())
+        .ok_or_else(|| format!("no certificates found in {}", cert.display()))?;
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config
+        .set_single_cert(chain, private_key(key)?)
+        .map_err(|e| format!("invalid TLS certificate or key: {}", e))?;
+    Ok(config)
+}
@@ -0,0 +1,139 @@
+use std::{
+    env,
+    io::{self, ErrorKind},
+    net::ToSocketAddrs,
+    time::Duration,
+};
+
+use ts3_query::{QueryClient, Ts3Error};
+
+use crate::{
+    config::{Config, ServerConfig},
+    error::{BANNED, FLOOD_BANNED, INSUFFICIENT_PERMISSIONS, INVALID_LOGIN, INVALID_SERVER_ID},
+    query::QueryProtocol,
+    resolve,
+    webquery::WebQuery,
+};
+
+/// Problems of the settings that would otherwise only show up once serving.
+pub fn settings(cfg: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    if cfg.listen.is_empty() && env::var_os("LISTEN_FDS").is_none() {
+        problems.push("no address to listen on, pass --listen or set LISTEN_ADDR".to_string());
+    }
+    problems
+}
+
+/// Log in to the TS3 host `server` and select each of its virtual servers, without retries.
+/// Returns a description of the first failure and what to check to fix it. This blocks and must
+/// not be called from the async runtime.
+pub fn connect(server: &ServerConfig, cfg: &Config) -> Result<(), String> {
+    let host = resolve::ts3_host(server)
+        .map_err(|e| format!("can't look up ts3_host {}: {}", server.ts3_host, e))?;
+    let address = resolve::authority(&host, server.ts3_port);
+    (&*host, server.ts3_port)
+        .to_socket_addrs()
+        .map_err(|e| format!("can't resolve {}: {}", host, e))?;
+    let connect_timeout = Duration::from_secs(cfg.ts3_connect_timeout);
+    let (protocol, default_port) = match server.ts3_query_protocol {
+        QueryProtocol::Raw => ("raw query", 10011),
+        QueryProtocol::Ssh => ("SSH query", 10022),
+        QueryProtocol::Http => ("WebQuery", 10080),
+        QueryProtocol::Https => ("WebQuery", 10443),
+    };
+    let connect_error = |e: &io::Error| match e.kind() {
+        ErrorKind::ConnectionRefused => format!(
+            "connection to {} refused, is ts3_port the {} port ({} by default) and is the \
+             TS3 server running?",
+            address, protocol, default_port
+        ),
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => format!(
+            "no answer from {} within {}s, check that a firewall doesn't block the port",
+            address, cfg.ts3_connect_timeout
+        ),
+        ErrorKind::PermissionDenied => e.to_string(),
+        _ => format!("can't connect to {}: {}", address, e),
+    };
+    let command_timeout = Duration::from_secs(cfg.ts3_command_timeout);
+    let select_error = |id: u64, e: Ts3Error| match response_id(&e) {
+        Some(INVALID_SERVER_ID) => format!(
+            "virtual server {} doesn't exist on {}, check ts3_server_id",
+            id, address
+        ),
+        Some(INSUFFICIENT_PERMISSIONS) if !server.ts3_query_protocol.needs_login() => format!(
+            "the API key may not use virtual server {}, grant its user access to it",
+            id
+        ),
+        Some(INSUFFICIENT_PERMISSIONS) => format!(
+            "{} may not use virtual server {}, grant the ServerQuery user access to it",
+            server.user, id
+        ),
+        Some(_) => format!("can't use virtual server {}: {}", id, e),
+        None => match &e {
+            Ts3Error::Io { source, .. } => connect_error(source),
+            _ => format!("can't use virtual server {}: {}", id, e),
+        },
+    };
+
+    if matches!(
+        server.ts3_query_protocol,
+        QueryProtocol::Http | QueryProtocol::Https
+    ) {
+        let mut api = WebQuery::new(server.clone(), connect_timeout, command_timeout);
+        api.check_api_key().map_err(|e| match &e {
+            Ts3Error::Io { source, .. } => connect_error(source),
+            Ts3Error::ServerError { response, .. } => format!(
+                "WebQuery API key was rejected ({}), check ts3_api_key",
+                response.msg
+            ),
+            _ => format!("can't use WebQuery at {}: {}", address, e),
+        })?;
+        for &id in &server.ts3_server_ids {
+            api.select(id).map_err(|e| select_error(id, e))?;
+        }
+        return Ok(());
+    }
+
+    let query_address =
+        resolve::query_address(server, &host, connect_timeout).map_err(|e| connect_error(&e))?;
+    let mut client =
+        QueryClient::with_timeout(query_address, Some(connect_timeout), Some(command_timeout))
+            .map_err(|e| match &e {
+                Ts3Error::Io { source, .. } => connect_error(source),
+                _ => format!("can't connect to {}: {}", address, e),
+            })?;
+
+    if server.ts3_query_protocol.needs_login() {
+        client
+            .login(&server.user, &server.password)
+            .map_err(|e| match response_id(&e) {
+                Some(INVALID_LOGIN) => format!(
+                    "login as {} failed, check the ServerQuery user and password",
+                    server.user
+                ),
+                Some(BANNED) | Some(FLOOD_BANNED) => format!(
+                    "banned by {}, add this host to its query_ip_allowlist.txt or wait for the \
+                     ban to expire",
+                    address
+                ),
+                _ => format!("login as {} failed: {}", server.user, e),
+            })?;
+    }
+
+    for &id in &server.ts3_server_ids {
+        client
+            .select_server_by_id(id)
+            .map_err(|e| select_error(id, e))?;
+    }
+
+    let _ = client.logout();
+    Ok(())
+}
+
+/// Id of the error response `e`, if the server responded with one.
+fn response_id(e: &Ts3Error) -> Option<usize> {
+    match e {
+        Ts3Error::ServerError { response, .. } => Some(response.id),
+        _ => None,
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+
+use async_graphql::SimpleObject;
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    countries,
+    query::{ChannelNode, Client, Codec, ServerInfo},
+    spacer::Spacer,
+};
+
+/// Order of channels and clients in the returned tree.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Sort {
+    /// Like the TS3 client: channels by their configured order, clients by talk power and nickname
+    #[default]
+    Ts3,
+    /// Channels and clients by name
+    Name,
+    /// As returned by the TS3 host
+    #[serde(rename = "none")]
+    Unsorted,
+}
+
+/// Query parameters of the status endpoints that shape the returned channel tree.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(default)]
+pub struct ViewQuery {
+    /// Remove channels without clients, unless one of their subchannels has clients
+    pub hide_empty: bool,
+    /// Return the channels as flat list instead of a tree
+    pub flat: bool,
+    /// Remove channels nested deeper than this, top level channels have depth 0
+    pub max_depth: Option<u32>,
+    /// Only return channels with matching names, together with their subchannels
+    #[serde(deserialize_with = "deserialize_regex")]
+    #[param(value_type = Option<String>)]
+    pub channel_filter: Option<Regex>,
+    /// Keep the parent channels of channels matching `channel_filter`
+    pub channel_ancestors: bool,
+    pub sort: Sort,
+    /// Remove spacer channels
+    pub hide_spacers: bool,
+    /// Language of country names, such as `de`
+    pub locale: Option<String>,
+    /// Show ServerQuery clients, defaults to `include_query_clients` of the configuration
+    pub include_query: Option<bool>,
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Option<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map(Some).map_err(de::Error::custom)
+}
+
+/// A channel of the flattened tree, its position is described by `parent_id` and `depth`.
+#[derive(Serialize, SimpleObject, ToSchema)]
+pub struct FlatChannel {
+    pub id: u64,
+    /// Id of the parent channel, 0 for top level channels
+    pub parent_id: u64,
+    /// Nesting level, 0 for top level channels
+    pub depth: u32,
+    pub name: String,
+    pub is_spacer: bool,
+    pub spacer: Option<Spacer>,
+    pub icon_id: u64,
+    pub topic: Option<String>,
+    pub description: Option<String>,
+    pub codec: Codec,
+    pub codec_quality: u8,
+    pub max_clients: Option<u32>,
+    pub is_permanent: bool,
+    pub is_semi_permanent: bool,
+    pub has_password: bool,
+    pub is_default: bool,
+    /// Whether the parent channel is missing, so this channel is shown at the top level instead
+    pub orphan: bool,
+    pub clients: Vec<Client>,
+}
+
+/// Query parameters of the client list endpoints.
+#[derive(Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(default)]
+pub struct PageQuery {
+    /// Number of clients to skip
+    pub offset: usize,
+    /// Maximum number of clients to return, all remaining ones if unset
+    pub limit: Option<usize>,
+    /// Language of country names, such as `de`
+    pub locale: Option<String>,
+    /// Show ServerQuery clients, defaults to `include_query_clients` of the configuration
+    pub include_query: Option<bool>,
+}
+
+/// A client of the flat client list together with the channel it is in.
+#[derive(Serialize, ToSchema)]
+pub struct ChannelClient {
+    #[serde(flatten)]
+    pub client: Client,
+    pub channel_id: u64,
+    pub channel_name: String,
+}
+
+/// The status of a virtual server as returned by the status endpoints.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum StatusView {
+    Tree(ServerInfo),
+    Flat(ServerInfo<FlatChannel>),
+}
+
+/// Remove all channels from `channels` without clients in them or in any of their subchannels.
+fn prune_empty(channels: &mut Vec<ChannelNode>) {
+    for channel in channels.iter_mut() {
+        prune_empty(&mut channel.children);
+    }
+    channels.retain(|channel| !channel.clients.is_empty() || !channel.children.is_empty());
+}
+
+/// Translate the country name of `client` to the language of `locale`.
+pub fn localize(client: &mut Client, locale: &str) {
+    client.country_name = countries::name(&client.country, Some(locale)).map(str::to_string);
+}
+
+fn localize_channels(channels: &mut [ChannelNode], locale: &str) {
+    for channel in channels {
+        for client in &mut channel.clients {
+            localize(client, locale);
+        }
+        localize_channels(&mut channel.children, locale);
+    }
+}
+
+/// Sort `channels`, their clients and their subchannels by `sort`.
+fn sort_channels(channels: &mut Vec<ChannelNode>, sort: Sort) {
+    match sort {
+        Sort::Ts3 => {
+            // Every channel references its predecessor, channels with broken references go last
+            let mut remaining: Vec<Option<ChannelNode>> =
+                std::mem::take(channels).into_iter().map(Some).collect();
+            let mut successors = HashMap::with_capacity(remaining.len());
+            for (index, channel) in remaining.iter().flatten().enumerate() {
+                successors.entry(channel.order).or_insert(index);
+            }
+            let mut previous = 0;
+            while let Some(channel) = successors
+                .get(&previous)
+                .and_then(|&index| remaining[index].take())
+            {
+                previous = channel.id;
+                channels.push(channel);
+            }
+            channels.extend(remaining.into_iter().flatten());
+            for channel in channels.iter_mut() {
+                channel.clients.sort_by(|a, b| {
+                    b.talk_power
+                        .cmp(&a.talk_power)
+                        .then_with(|| a.nickname.to_lowercase().cmp(&b.nickname.to_lowercase()))
+                });
+            }
+        }
+        Sort::Name => {
+            channels.sort_by_key(|channel| channel.name.to_lowercase());
+            for channel in channels.iter_mut() {
+                channel
+                    .clients
+                    .sort_by_key(|client| client.nickname.to_lowercase());
+            }
+        }
+        Sort::Unsorted => return,
+    }
+
+    for channel in channels {
+        sort_channels(&mut channel.children, sort);
+    }
+}
+
+/// Only keep channels whose names match `filter` with all of their subchannels. Non-matching
+/// parents are kept if `ancestors` is set, otherwise the matching channels take their place.
+fn filter_channels(
+    channels: Vec<ChannelNode>,
+    filter: &Regex,
+    ancestors: bool,
+) -> Vec<ChannelNode> {
+    let mut matching = Vec::new();
+    for mut channel in channels {
+        if filter.is_match(&channel.name) {
+            matching.push(channel);
+            continue;
+        }
+
+        let children = filter_channels(channel.children, filter, ancestors);
+        if ancestors {
+            if !children.is_empty() {
+                channel.children = children;
+                matching.push(channel);
+            }
+        } else {
+            matching.extend(children);
+        }
+    }
+    matching
+}
+
+/// Remove the subchannels of all channels at `max_depth`.
+fn truncate(channels: &mut [ChannelNode], max_depth: u32) {
+    for channel in channels {
+        if max_depth == 0 {
+            channel.children.clear();
+        } else {
+            truncate(&mut channel.children, max_depth - 1);
+        }
+    }
+}
+
+/// Append `channels` and their subchannels to `out` in depth first order.
+fn flatten(channels: Vec<ChannelNode>, parent_id: u64, depth: u32, out: &mut Vec<FlatChannel>) {
+    for channel in channels {
+        out.push(FlatChannel {
+            id: channel.id,
+            parent_id,
+            depth,
+            name: channel.name,
+            is_spacer: channel.is_spacer,
+            spacer: channel.spacer,
+            icon_id: channel.icon_id,
+            topic: channel.topic,
+            description: channel.description,
+            codec: channel.codec,
+            codec_quality: channel.codec_quality,
+            max_clients: channel.max_clients,
+            is_permanent: channel.is_permanent,
+            is_semi_permanent: channel.is_semi_permanent,
+            has_password: channel.has_password,
+            is_default: channel.is_default,
+            orphan: channel.orphan,
+            clients: channel.clients,
+        });
+        flatten(channel.children, channel.id, depth + 1, out);
+    }
+}
+
+/// Apply the options of `query` to the status of a virtual server.
+pub fn apply(query: &ViewQuery, mut server_info: ServerInfo) -> StatusView {
+    if let Some(locale) = query.locale.as_deref() {
+        localize_channels(&mut server_info.channels, locale);
+    }
+    sort_channels(&mut server_info.channels, query.sort);
+    if let Some(filter) = query.channel_filter.as_ref() {
+        server_info.channels =
+            filter_channels(server_info.channels, filter, query.channel_ancestors);
+    }
+    if query.hide_spacers {
+        server_info.channels.retain(|channel| !channel.is_spacer);
+    }
+    if let Some(max_depth) = query.max_depth {
+        truncate(&mut server_info.channels, max_depth);
+    }
+    if query.hide_empty {
+        prune_empty(&mut server_info.channels);
+    }
+
+    if !query.flat {
+        return StatusView::Tree(server_info);
+    }
+
+    let mut channels = Vec::new();
+    flatten(server_info.channels, 0, 0, &mut channels);
+    StatusView::Flat(ServerInfo {
+        name: server_info.name,
+        version: server_info.version,
+        platform: server_info.platform,
+        clients_online: server_info.clients_online,
+        max_clients: server_info.max_clients,
+        reserved_slots: server_info.reserved_slots,
+        queryclients_online: server_info.queryclients_online,
+        uptime_seconds: server_info.uptime_seconds,
+        network: server_info.network,
+        icon_id: server_info.icon_id,
+        channels,
+    })
+}
+
+/// The page of the clients on the server described by `query`, together with the total number of
+/// clients.
+pub fn clients(query: &PageQuery, server_info: &ServerInfo) -> (usize, Vec<ChannelClient>) {
+    let clients = server_info.clients();
+    let total = clients.len();
+    let page = clients
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|(channel, client)| {
+            let mut client = client.clone();
+            if let Some(locale) = query.locale.as_deref() {
+                localize(&mut client, locale);
+            }
+            ChannelClient {
+                client,
+                channel_id: channel.id,
+                channel_name: channel.name.clone(),
+            }
+        })
+        .collect();
+    (total, page)
+}
@@ -0,0 +1,155 @@
+use std::{
+    fmt::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    http::header,
+    rt::{self, time},
+    web::Bytes,
+};
+use futures::{channel::mpsc::UnboundedReceiver, StreamExt};
+use hmac::{Hmac, Mac};
+use log::{error, info, warn};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{
+    events::{client_event_stream, ClientEvent},
+    query::{ServerInfo, StatusUpdate},
+};
+
+// Give up on a delivery attempt after this long
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// Wait this long before retrying a failed delivery, doubling with every further retry
+const RETRY_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Signature of the request body, `sha256=<hex HMAC-SHA256 with the secret>`
+const SIGNATURE_HEADER: &str = "x-ts3status-signature";
+
+/// URLs notified about joining and leaving clients.
+#[derive(Clone, Debug, Default)]
+pub struct WebhookOptions {
+    pub urls: Vec<String>,
+    /// Key to sign requests with, unsigned if unset
+    pub secret: Option<String>,
+    /// Retries of deliveries failing with network errors or error responses
+    pub retries: u32,
+}
+
+impl WebhookOptions {
+    pub fn enabled(&self) -> bool {
+        !self.urls.is_empty()
+    }
+}
+
+/// Body of the POST request for a client event.
+#[derive(Serialize)]
+struct Payload<'a> {
+    /// Name of the configured TS3 host
+    server: &'a str,
+    server_id: u64,
+    /// Unix timestamp of when the event was noticed
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a ClientEvent,
+}
+
+/// Hex encoded HMAC-SHA256 of `body` with `secret`.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{:02x}", byte);
+            hex
+        })
+}
+
+/// POST `body` to `url`, retrying after failures as configured.
+async fn deliver(options: WebhookOptions, url: String, event: &'static str, body: Bytes) {
+    let client = awc::Client::builder().timeout(TIMEOUT).finish();
+    let signature = options
+        .secret
+        .as_ref()
+        .map(|secret| format!("sha256={}", sign(secret, &body)));
+
+    let mut retry = 0;
+    loop {
+        let mut request = client
+            .post(&url)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("x-ts3status-event", event);
+        if let Some(signature) = signature.as_ref() {
+            request = request.header(SIGNATURE_HEADER, signature.as_str());
+        }
+        let error = match request.send_body(body.clone()).await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => format!("status {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+
+        if retry >= options.retries {
+            error!("Webhook {} failed, giving up: {}", url, error);
+            return;
+        }
+        let delay = RETRY_BACKOFF * 2u32.saturating_pow(retry);
+        warn!("Webhook {} failed, retrying in {:?}: {}", url, delay, error);
+        time::delay_for(delay).await;
+        retry += 1;
+    }
+}
+
+/// Notify the configured URLs about every client joining or leaving the virtual server
+/// `server_id` of the TS3 host `server`, comparing the status of consecutive refreshes. Clients
+/// online when the first status arrives aren't reported.
+pub async fn notify(
+    options: WebhookOptions,
+    server: String,
+    server_id: u64,
+    current: Option<ServerInfo>,
+    updates: UnboundedReceiver<StatusUpdate>,
+    include_query: bool,
+) {
+    let mut changes = Box::pin(client_event_stream(current, updates, include_query));
+    while let Some((_, events)) = changes.next().await {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        for event in &events {
+            let payload = Payload {
+                server: &server,
+                server_id,
+                timestamp,
+                event,
+            };
+            let body = match serde_json::to_vec(&payload) {
+                Ok(body) => Bytes::from(body),
+                Err(e) => {
+                    error!("Can not serialize webhook payload: {}", e);
+                    continue;
+                }
+            };
+            info!(
+                "Sending {} of client {} to {} webhooks",
+                event.event.name(),
+                event.client.id,
+                options.urls.len()
+            );
+            for url in &options.urls {
+                // Deliveries retry independently, so a slow URL doesn't hold up the others
+                rt::spawn(deliver(
+                    options.clone(),
+                    url.clone(),
+                    event.event.name(),
+                    body.clone(),
+                ));
+            }
+        }
+    }
+}
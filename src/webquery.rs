@@ -0,0 +1,281 @@
+use std::{collections::HashMap, io, time::Duration};
+
+use log::{info, trace};
+use reqwest::{blocking::Client, Url};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use snafu::GenerateBacktrace;
+use ts3_query::{raw::unescape_val, ErrorResponse, Ts3Error};
+
+use crate::{
+    backend::{self, Commands, ConnectedSince, RawServerInfo, RawStatus, StatusBackend},
+    compat,
+    config::ServerConfig,
+    icons::{self, Transfer},
+    query::QueryProtocol,
+    resolve,
+};
+
+/// Response of every WebQuery request.
+#[derive(Deserialize)]
+struct Response {
+    #[serde(default)]
+    body: Vec<Map<String, Value>>,
+    status: Status,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    code: usize,
+    message: String,
+}
+
+/// WebQuery API of a TS3 host, which answers query commands over HTTP authenticated by an API
+/// key instead of a query connection. There is no session, so nothing has to be kept alive or
+/// logged out.
+pub struct WebQuery {
+    server: ServerConfig,
+    connect_timeout: Duration,
+    command_timeout: Duration,
+    /// Client for the looked up host, `None` until the first request or after the host couldn't
+    /// be reached
+    api: Option<Api>,
+    /// Connection times of clients of the last fetch, by virtual server
+    connected_since: HashMap<u64, ConnectedSince>,
+}
+
+impl WebQuery {
+    pub fn new(server: ServerConfig, connect_timeout: Duration, command_timeout: Duration) -> Self {
+        Self {
+            server,
+            connect_timeout,
+            command_timeout,
+            api: None,
+            connected_since: HashMap::new(),
+        }
+    }
+
+    /// Run `f` with the API of the TS3 host, looking it up first if necessary. The host is looked
+    /// up again after requests that didn't get an answer.
+    fn with_api<T, F>(&mut self, f: F) -> Result<T, Ts3Error>
+    where
+        F: FnOnce(&Api) -> Result<T, Ts3Error>,
+    {
+        let api = match self.api.take() {
+            Some(api) => api,
+            None => Api::new(&self.server, self.connect_timeout, self.command_timeout)?,
+        };
+        let result = f(&api);
+        match &result {
+            Err(Ts3Error::Io { .. }) => {}
+            _ => self.api = Some(api),
+        }
+        result
+    }
+
+    /// Check that the API key is accepted by running `version`.
+    pub fn check_api_key(&mut self) -> Result<(), Ts3Error> {
+        self.with_api(|api| api.request(None, "version", &[]).map(drop))
+    }
+
+    /// Check that the virtual server `server_id` exists and may be used with the API key.
+    pub fn select(&mut self, server_id: u64) -> Result<(), Ts3Error> {
+        self.with_api(|api| api.request(Some(server_id), "whoami", &[]).map(drop))
+    }
+}
+
+impl StatusBackend for WebQuery {
+    fn fetch(&mut self, server_id: u64, descriptions: bool) -> Result<RawStatus, Ts3Error> {
+        let mut known = self.connected_since.remove(&server_id).unwrap_or_default();
+        let result = self.with_api(|api| {
+            query_status(&mut api.virtual_server(server_id), descriptions, &mut known)
+        });
+        self.connected_since.insert(server_id, known);
+        result
+    }
+
+    fn init_download(
+        &mut self,
+        server_id: u64,
+        icon_id: u64,
+    ) -> Result<Option<Transfer>, Ts3Error> {
+        self.with_api(|api| icons::init_download(&mut api.virtual_server(server_id), icon_id))
+    }
+
+    fn keepalive(&mut self) -> Result<(), Ts3Error> {
+        Ok(())
+    }
+
+    fn disconnect(&mut self) -> Result<(), Ts3Error> {
+        Ok(())
+    }
+}
+
+/// Query everything the status of a virtual server is built from over WebQuery, whose responses
+/// are parsed leniently. `known` holds the connection times of clients of the last query.
+fn query_status(
+    server: &mut VirtualServer,
+    descriptions: bool,
+    known: &mut ConnectedSince,
+) -> Result<RawStatus, Ts3Error> {
+    let server_info = server
+        .run("serverinfo")?
+        .first()
+        .map(RawServerInfo::parse)
+        .unwrap_or_default();
+    trace!("info: {:?}", server_info);
+
+    let channels: Vec<_> = server
+        .run("channellist -topic -flags -voice -limits -icon -secondsempty")?
+        .iter()
+        .map(compat::channel)
+        .collect();
+    trace!("channels: {:?}", channels);
+
+    let clients: Vec<_> = server
+        .run("clientlist -uid -away -voice -times -groups -info -country -ip -badges")?
+        .iter()
+        .map(compat::online_client)
+        .collect();
+    trace!("clients: {:?}", clients);
+
+    Ok(RawStatus {
+        connected_since: backend::connected_since(server, &clients, known)?,
+        channel_groups: backend::channel_groups(server)?,
+        server_groups: compat::server_groups(&server.run("servergrouplist")?),
+        descriptions: if descriptions {
+            backend::channel_descriptions(server, &channels)?
+        } else {
+            HashMap::new()
+        },
+        server_info,
+        channels,
+        clients,
+    })
+}
+
+/// Client for the WebQuery API of a looked up TS3 host.
+struct Api {
+    client: Client,
+    base: Url,
+    api_key: String,
+}
+
+impl Api {
+    fn new(
+        server: &ServerConfig,
+        connect_timeout: Duration,
+        command_timeout: Duration,
+    ) -> Result<Self, Ts3Error> {
+        let host = resolve::ts3_host(server)?;
+        info!("Using WebQuery of TS3 server {} at {}", server.name, host);
+        let scheme = match server.ts3_query_protocol {
+            QueryProtocol::Https => "https",
+            _ => "http",
+        };
+        let base = Url::parse(&format!(
+            "{}://{}/",
+            scheme,
+            resolve::authority(&host, server.ts3_port)
+        ))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let client = Client::builder()
+            .connect_timeout(connect_timeout)
+            .timeout(command_timeout)
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            client,
+            base,
+            api_key: server.ts3_api_key.clone().unwrap_or_default(),
+        })
+    }
+
+    /// Commands run on the virtual server `server_id`.
+    fn virtual_server(&self, server_id: u64) -> VirtualServer<'_> {
+        VirtualServer {
+            api: self,
+            server_id,
+        }
+    }
+
+    /// Run `command` with the raw query `args`, on the virtual server `server_id` if any.
+    /// Failing commands are returned as error responses, like from a query connection.
+    fn request(
+        &self,
+        server_id: Option<u64>,
+        command: &str,
+        args: &[&str],
+    ) -> Result<Vec<HashMap<String, Option<String>>>, Ts3Error> {
+        let path = match server_id {
+            Some(id) => format!("{}/{}", id, command),
+            None => command.to_string(),
+        };
+        let mut url = self
+            .base
+            .join(&path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        if !args.is_empty() {
+            let mut query = url.query_pairs_mut();
+            for arg in args {
+                match arg.split_once('=') {
+                    Some((key, value)) => query.append_pair(key, &unescape_val(value)),
+                    None => query.append_key_only(arg),
+                };
+            }
+        }
+        let response = self
+            .client
+            .get(url)
+            .header("x-api-key", &self.api_key)
+            .send()
+            .map_err(|e| match e.is_timeout() {
+                true => io::Error::new(io::ErrorKind::TimedOut, e),
+                false => io::Error::other(e),
+            })?;
+        // Failing commands are answered with an error status, but the JSON body is the same
+        let response: Response = response.json().map_err(|e| Ts3Error::InvalidResponse {
+            context: "expected WebQuery JSON, got: ",
+            data: e.to_string(),
+        })?;
+        if response.status.code != 0 {
+            return Err(Ts3Error::ServerError {
+                response: ErrorResponse {
+                    id: response.status.code,
+                    msg: response.status.message,
+                },
+                backtrace: GenerateBacktrace::generate(),
+            });
+        }
+        Ok(response.body.into_iter().map(item).collect())
+    }
+}
+
+/// An item of a WebQuery response like `raw::parse_hashmap` returns them.
+fn item(item: Map<String, Value>) -> HashMap<String, Option<String>> {
+    item.into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(value) => Some(value),
+                Value::Null => None,
+                value => Some(value.to_string()),
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+/// Runs commands on a virtual server over WebQuery.
+struct VirtualServer<'a> {
+    api: &'a Api,
+    server_id: u64,
+}
+
+impl Commands for VirtualServer<'_> {
+    fn run(&mut self, command: &str) -> Result<Vec<HashMap<String, Option<String>>>, Ts3Error> {
+        let mut words = command.split(' ').filter(|word| !word.is_empty());
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        self.api.request(Some(self.server_id), name, &args)
+    }
+}
@@ -0,0 +1,72 @@
+use actix::{Actor, ActorContext, AsyncContext, StreamHandler};
+use actix_web_actors::ws;
+use futures::channel::mpsc::UnboundedReceiver;
+use log::{debug, error};
+
+use crate::query::{ServerInfo, StatusUpdate};
+
+/// WebSocket session pushing the status of a virtual server whenever it changed.
+pub struct StatusSocket {
+    current: Option<ServerInfo>,
+    updates: Option<UnboundedReceiver<StatusUpdate>>,
+    include_query: bool,
+}
+
+impl StatusSocket {
+    pub fn new(
+        current: Option<ServerInfo>,
+        updates: UnboundedReceiver<StatusUpdate>,
+        include_query: bool,
+    ) -> Self {
+        Self {
+            current,
+            updates: Some(updates),
+            include_query,
+        }
+    }
+
+    fn send(ctx: &mut ws::WebsocketContext<Self>, server_info: &ServerInfo) {
+        match serde_json::to_string(server_info) {
+            Ok(json) => ctx.text(json),
+            Err(e) => error!("Can not serialize server status: {}", e),
+        }
+    }
+}
+
+impl Actor for StatusSocket {
+    type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        if let Some(server_info) = self.current.take() {
+            Self::send(ctx, &server_info);
+        }
+        if let Some(updates) = self.updates.take() {
+            ctx.add_stream(updates);
+        }
+    }
+}
+
+impl StreamHandler<StatusUpdate> for StatusSocket {
+    fn handle(&mut self, update: StatusUpdate, ctx: &mut Self::Context) {
+        if update.changed {
+            Self::send(ctx, &update.server_info.visible(self.include_query));
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for StatusSocket {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
+            Ok(_) => (),
+            Err(e) => {
+                debug!("WebSocket protocol error: {}", e);
+                ctx.stop();
+            }
+        }
+    }
+}
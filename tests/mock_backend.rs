@@ -0,0 +1,181 @@
+use std::{collections::HashMap, env, fs, process, thread, time::Duration};
+
+use actix_web::{http::StatusCode, test, App};
+use serde_json::Value;
+use ts3status::{
+    config::Config,
+    mock::{self, MockBackend, MockChannel, MockClient, MockServer},
+    query::{self, Server},
+};
+
+/// Configuration of a default server with virtual server 1 and `settings` added, which is
+/// queried synchronously whenever its status is older than `cache_lifetime`.
+fn config(name: &str, settings: &str) -> Config {
+    let path = env::temp_dir().join(format!("ts3status-{}-{}.toml", name, process::id()));
+    fs::write(
+        &path,
+        format!(
+            "ts3_host = \"127.0.0.1\"\n\
+             ts3_port = 10011\n\
+             ts3_server_ids = [1]\n\
+             user = \"serveradmin\"\n\
+             password = \"secret\"\n\
+             hostname = \"localhost\"\n\
+             background_refresh = false\n\
+             stale_while_revalidate = false\n\
+             ts3_retries = 0\n\
+             {}\n",
+            settings
+        ),
+    )
+    .expect("can't write config");
+    let cfg = Config::load(Some(&path), None, Vec::new());
+    fs::remove_file(&path).expect("can't remove config");
+    cfg.expect("invalid config")
+}
+
+fn channel(id: u64, parent_id: u64, name: &str) -> MockChannel {
+    MockChannel {
+        id,
+        parent_id,
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+fn client(id: u16, channel_id: u64, nickname: &str, is_query: bool) -> MockClient {
+    MockClient {
+        id,
+        channel_id,
+        nickname: nickname.to_string(),
+        unique_id: format!("uid{}=", id),
+        is_query,
+        ..Default::default()
+    }
+}
+
+/// A virtual server whose subchannel is listed before its parent, with two users and a
+/// ServerQuery client.
+fn mock_server() -> MockServer {
+    MockServer {
+        name: "Mock Server".to_string(),
+        max_clients: 32,
+        channels: vec![
+            channel(3, 2, "AFK"),
+            channel(1, 0, "Lobby"),
+            channel(2, 0, "Games"),
+        ],
+        clients: vec![
+            client(1, 1, "Alice", false),
+            client(2, 3, "Bob", false),
+            client(3, 1, "serveradmin", true),
+        ],
+        server_groups: HashMap::new(),
+    }
+}
+
+fn server(cfg: &Config, backend: &MockBackend) -> Server {
+    Server::with_backend(cfg.default_server().clone(), cfg, Box::new(backend.clone()))
+}
+
+#[test]
+fn builds_channel_tree() {
+    let cfg = config("tree", "cache_lifetime = 10");
+    let backend = MockBackend::new();
+    backend.set_server(1, mock_server());
+
+    let (status, _) = query::query_blocking(&server(&cfg, &backend), 1).expect("query failed");
+    let top: Vec<&str> = status.channels.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(top, ["Lobby", "Games"]);
+    let games = &status.channels[1];
+    assert_eq!(games.children.len(), 1);
+    assert_eq!(games.children[0].name, "AFK");
+    assert_eq!(games.children[0].clients[0].nickname, "Bob");
+    assert_eq!(status.clients_online, 3);
+    assert_eq!(status.queryclients_online, 1);
+}
+
+#[actix_rt::test]
+async fn refreshes_expired_cache() {
+    let cfg = config("expiry", "cache_lifetime = 0");
+    let backend = MockBackend::new();
+    backend.set_server(1, mock_server());
+    let server = server(&cfg, &backend);
+    let cache = &server.caches[&1];
+
+    query::refresh_if_expired(&cfg, &server, 1, cache)
+        .await
+        .expect("refresh failed");
+    query::refresh_if_expired(&cfg, &server, 1, cache)
+        .await
+        .expect("refresh failed");
+    assert_eq!(backend.fetches(), 1);
+
+    backend.modify(1, |server| {
+        server.clients.pop();
+    });
+    thread::sleep(Duration::from_millis(1100));
+    query::refresh_if_expired(&cfg, &server, 1, cache)
+        .await
+        .expect("refresh failed");
+    assert_eq!(backend.fetches(), 2);
+    let snapshot = cache.load();
+    let (status, _) = snapshot.last_good().expect("no status");
+    assert_eq!(status.clients_online, 2);
+}
+
+#[actix_rt::test]
+async fn serves_stale_status() {
+    let cfg = config("stale", "cache_lifetime = 0");
+    let backend = MockBackend::new();
+    backend.set_server(1, mock_server());
+    let mut servers = HashMap::new();
+    servers.insert(cfg.default_server().name.clone(), server(&cfg, &backend));
+    let mut app = test::init_service(App::new().configure(mock::routes_for(cfg, servers))).await;
+
+    let response =
+        test::call_service(&mut app, test::TestRequest::get().uri("/").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    backend.set_down(true);
+    thread::sleep(Duration::from_millis(1100));
+    let response =
+        test::call_service(&mut app, test::TestRequest::get().uri("/").to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["success"], false);
+    assert_eq!(body["stale"], true);
+    assert_eq!(body["error_code"], "connect_failed");
+    assert_eq!(body["server_info"]["name"], "Mock Server");
+    assert_eq!(backend.fetches(), 2);
+}
+
+#[actix_rt::test]
+async fn serves_status_and_count() {
+    let cfg = config("handlers", "cache_lifetime = 10");
+    let backend = MockBackend::new();
+    backend.set_server(1, mock_server());
+    let mut servers = HashMap::new();
+    servers.insert(cfg.default_server().name.clone(), server(&cfg, &backend));
+    let mut app = test::init_service(App::new().configure(mock::routes_for(cfg, servers))).await;
+
+    let request = test::TestRequest::get().uri("/?format=json").to_request();
+    let body: Value = test::read_response_json(&mut app, request).await;
+    assert_eq!(body["success"], true);
+    assert_eq!(body["stale"], false);
+    assert_eq!(body["server_info"]["name"], "Mock Server");
+    assert_eq!(
+        body["server_info"]["channels"][1]["children"][0]["name"],
+        "AFK"
+    );
+
+    let request = test::TestRequest::get().uri("/count").to_request();
+    let body: Value = test::read_response_json(&mut app, request).await;
+    assert_eq!(body["online"], 2);
+    assert_eq!(body["max"], 32);
+    assert_eq!(backend.fetches(), 1);
+
+    let request = test::TestRequest::get().uri("/unknown/count").to_request();
+    let response = test::call_service(&mut app, request).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}